@@ -0,0 +1,8 @@
+#define_import_path flow_field::custom_field
+
+// Stands in for the shader set in `CustomField` until it has loaded. The flow field calls `field`
+// with the position of a particle in noise space and the evolution of the field, and expects the
+// direction of the flow there.
+fn field(pos: vec2<f32>, t: f32) -> vec2<f32> {
+    return vec2(0.0);
+}
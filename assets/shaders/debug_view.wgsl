@@ -0,0 +1,56 @@
+// Writes the raw trail energies of a flow field into the image of the debug view, every channel
+// scaled linearly so the brightest one of the image is white.
+
+struct Sizes {
+  size: vec2<u32>,
+  supersample: u32,
+}
+
+@group(0) @binding(0) var<storage, read> energies: array<u32>;
+@group(0) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2) var<storage, read_write> peak: atomic<u32>;
+@group(0) @binding(3) var<uniform> sizes: Sizes;
+
+// The energy summed over the accumulated pixels covering an image pixel.
+fn pixel_energy(pixel: vec2<u32>) -> vec3<f32> {
+  let width = sizes.size.x * sizes.supersample;
+  var energy = vec3(0.0);
+  for (var y = 0u; y < sizes.supersample; y++) {
+    for (var x = 0u; x < sizes.supersample; x++) {
+      let sample = pixel * sizes.supersample + vec2(x, y);
+      let index = 4u * (sample.x + width * sample.y);
+      energy += vec3<f32>(vec3(energies[index], energies[index + 1u], energies[index + 2u]));
+    }
+  }
+  return energy;
+}
+
+var<workgroup> workgroup_peak: atomic<u32>;
+
+@compute @workgroup_size(16, 16, 1)
+fn find_peak(
+  @builtin(global_invocation_id) id: vec3<u32>,
+  @builtin(local_invocation_index) index: u32,
+) {
+  if index == 0u {
+    atomicStore(&workgroup_peak, 0u);
+  }
+  workgroupBarrier();
+  if all(id.xy < sizes.size) {
+    let energy = pixel_energy(id.xy);
+    atomicMax(&workgroup_peak, u32(max(energy.r, max(energy.g, energy.b))));
+  }
+  workgroupBarrier();
+  if index == 0u {
+    atomicMax(&peak, atomicLoad(&workgroup_peak));
+  }
+}
+
+@compute @workgroup_size(16, 16, 1)
+fn composite(@builtin(global_invocation_id) id: vec3<u32>) {
+  if any(id.xy >= sizes.size) {
+    return;
+  }
+  let brightest = f32(max(atomicLoad(&peak), 1u));
+  textureStore(output, id.xy, vec4(pixel_energy(id.xy) / brightest, 1.0));
+}
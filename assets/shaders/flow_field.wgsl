@@ -1,12 +1,237 @@
+// `field` is only called with a `CustomField`, see `custom_field.wgsl` for the stub otherwise.
+#import flow_field::custom_field field
+
 struct Particle {
   position: vec2<f32>,
   velocity: vec2<f32>,
   seed: u32,
+  age: f32,
+  max_age: f32,
+  mass: f32,
+  color: vec4<f32>,
+  species: u32,
+}
+
+struct Species {
+  noise_offset: vec2<f32>,
+  speed: f32,
+  deposit: f32,
+  color: vec4<f32>,
+}
+
+struct Params {
+  size: vec2<u32>,
+  // How many times finer than `size` the energies are accumulated in each direction.
+  supersample: u32,
+  noise_scale: f32,
+  field_evolution_speed: f32,
+  octaves: u32,
+  lacunarity: f32,
+  gain: f32,
+  noise_mix: f32,
+  image_influence: f32,
+  vector_field_influence: f32,
+  sdf_threshold: f32,
+  field_type: u32,
+  curl_frequency: f32,
+  curl_epsilon: f32,
+  field_rotation: f32,
+  noise_offset: vec2<f32>,
+  boundary: u32,
+  particle_lifetime: f32,
+  lifetime_variance: f32,
+  // How respawned particles start out, one of the `VELOCITY_*` constants.
+  initial_velocity: u32,
+  initial_speed_min: f32,
+  initial_speed_max: f32,
+  field_strength: f32,
+  drag: f32,
+  max_speed: f32,
+  gravity: vec2<f32>,
+  wind: vec2<f32>,
+  neighbors_enabled: u32,
+  repulsion_enabled: u32,
+  repulsion_radius: f32,
+  repulsion_strength: f32,
+  alignment_strength: f32,
+  cohesion_strength: f32,
+  neighbor_radius: f32,
+  fade: f32,
+  deposit: f32,
+  splat_radius: f32,
+  trail_lines: u32,
+  color_mode: u32,
+  color_saturation: f32,
+  color_value: f32,
+  color_slow: vec4<f32>,
+  color_fast: vec4<f32>,
+  speed_gamma: f32,
+  age_curve: u32,
+  display_mode: u32,
+  exposure: f32,
+  tonemap: u32,
+  gamma: f32,
+  invert: u32,
+  hue_shift: f32,
+  glow_strength: f32,
+  glow_radius: f32,
+  bloom_enabled: u32,
+  bloom_threshold: f32,
+  bloom_intensity: f32,
+  vignette_strength: f32,
+  vignette_radius: f32,
+  grain_amount: f32,
+  // Position in `xy`, signed strength in `z` and radius in `w`.
+  pointers: array<vec4<f32>, MAX_POINTERS>,
+  pointer_count: u32,
+  background_color: vec4<f32>,
+  trail_color: vec4<f32>,
 }
 
-@group(0) @binding(0) var dst_image: texture_storage_2d<rgba32float, read_write>;
+struct SimParams {
+  dt: f32,
+  frame_dt: f32,
+  elapsed: f32,
+  speed: f32,
+  primitive_count: u32,
+  has_field_image: u32,
+  has_vector_field: u32,
+  obstacle_count: u32,
+  has_obstacle_sdf: u32,
+  has_palette: u32,
+  frame: u32,
+  film_effects: u32,
+}
+
+struct Obstacle {
+  center: vec2<f32>,
+  radius: f32,
+}
+
+struct FieldPrimitive {
+  position: vec2<f32>,
+  direction: vec2<f32>,
+  kind: u32,
+  strength: f32,
+  radius: f32,
+}
+
+// Tuned for the original fixed step of 0.3 pixels per frame at 60 fps.
+const PIXELS_PER_SECOND: f32 = 18.0;
+const STEERING_PER_SECOND: f32 = 0.6;
+const MAX_OCTAVES: u32 = 8u;
+// Must match `PointerForce::MAX_POINTERS` on the Rust side.
+const MAX_POINTERS: u32 = 8u;
+
+// Energies are stored as fixed point so they can be accumulated atomically, which keeps the
+// brightness of crossings independent of the order in which particles deposit. The `draw` pass
+// resolves them into the output image.
+const ENERGY_SCALE: f32 = 256.0;
+// Deposits stop short of this so saturated pixels don't wrap around to black.
+const ENERGY_LIMIT: u32 = 0xf0000000u;
+// Most points deposited along a line, longer moves space them further apart.
+const MAX_LINE_STEPS: u32 = 64u;
+// Must match `FlowFieldParams::MAX_SPLAT_RADIUS`, bounds the accumulated pixels touched per deposit
+// whatever the supersampling.
+const MAX_SPLAT_RADIUS: f32 = 4.0;
+// Must match `FlowFieldParams::MAX_GLOW_RADIUS`.
+const MAX_GLOW_RADIUS: f32 = 8.0;
+// Half width of the blur applied to each bloom level, in pixels of that level.
+const BLOOM_RADIUS: i32 = 4;
+// How much the half and quarter resolution levels contribute to the bloom.
+const BLOOM_HALF_WEIGHT: f32 = 0.6;
+const BLOOM_QUARTER_WEIGHT: f32 = 0.4;
+// Brightest value written to the output, well within the range of half floats.
+const MAX_COLOR: f32 = 1000.0;
+
+// Must match `FieldType` on the Rust side.
+const FIELD_ANGLE: u32 = 0u;
+const FIELD_CURL: u32 = 1u;
+
+// Must match `ColorMode` on the Rust side.
+const COLOR_PARTICLE: u32 = 0u;
+const COLOR_HEADING: u32 = 1u;
+const COLOR_SPEED: u32 = 2u;
+const COLOR_AGE: u32 = 3u;
+
+// Must match `DisplayMode` on the Rust side.
+const DISPLAY_LINEAR: u32 = 0u;
+const DISPLAY_LOG: u32 = 1u;
+const DISPLAY_EQUALIZED: u32 = 2u;
+
+// Must match `HISTOGRAM_BINS` in src/buffers.rs.
+const HISTOGRAM_BINS: u32 = 256u;
+
+// Must match `Tonemap` on the Rust side.
+const TONEMAP_NONE: u32 = 0u;
+const TONEMAP_REINHARD: u32 = 1u;
+const TONEMAP_ACES: u32 = 2u;
+
+// Must match `AgeCurve` on the Rust side.
+const AGE_LINEAR: u32 = 0u;
+const AGE_EASE_IN: u32 = 1u;
+const AGE_EASE_OUT: u32 = 2u;
+
+// Must match `PrimitiveKind` on the Rust side.
+const PRIMITIVE_VORTEX: u32 = 0u;
+const PRIMITIVE_SOURCE: u32 = 1u;
+const PRIMITIVE_SINK: u32 = 2u;
+const PRIMITIVE_WIND: u32 = 3u;
+
+// Bounds the work for particles caught between overlapping obstacles, which may push them back
+// and forth.
+const MAX_OBSTACLE_ITERATIONS: u32 = 4u;
+
+// Must match `NEIGHBOR_CELL_SIZE` in src/buffers.rs, the neighbor radii are at most one cell.
+const NEIGHBOR_CELL_SIZE: u32 = 8u;
+// Caps the work per cell in dense clumps, which are pushed apart over a few frames instead.
+const MAX_CELL_NEIGHBORS: u32 = 32u;
+const PREFIX_SUM_THREADS: u32 = 256u;
+
+// Must match `InitialVelocity::mode` on the Rust side.
+const VELOCITY_ZERO: u32 = 0u;
+const VELOCITY_RANDOM: u32 = 1u;
+const VELOCITY_RADIAL: u32 = 2u;
+
+// Must match `BoundaryMode` on the Rust side.
+const BOUNDARY_RESPAWN: u32 = 0u;
+const BOUNDARY_WRAP: u32 = 1u;
+const BOUNDARY_BOUNCE: u32 = 2u;
+const BOUNDARY_KILL: u32 = 3u;
+
+// Declared in the `OutputFormat` chosen on the Rust side.
+#ifdef OUTPUT_RGBA8UNORM
+@group(0) @binding(0) var dst_image: texture_storage_2d<rgba8unorm, write>;
+#else ifdef OUTPUT_RGBA16FLOAT
+@group(0) @binding(0) var dst_image: texture_storage_2d<rgba16float, write>;
+#else
+@group(0) @binding(0) var dst_image: texture_storage_2d<rgba32float, write>;
+#endif
 @group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
 @group(0) @binding(2) var<storage, read_write> energy_buffer: array<atomic<u32>>;
+@group(0) @binding(3) var<uniform> params: Params;
+@group(0) @binding(4) var<uniform> sim: SimParams;
+@group(0) @binding(5) var<storage, read> species: array<Species>;
+@group(0) @binding(6) var<storage, read> primitives: array<FieldPrimitive>;
+@group(0) @binding(7) var field_image: texture_2d<f32>;
+@group(0) @binding(8) var vector_field: texture_2d<f32>;
+@group(0) @binding(9) var<storage, read> obstacles: array<Obstacle>;
+@group(0) @binding(10) var obstacle_sdf: texture_2d<f32>;
+// Counts, then offsets, of the particles per neighbor cell with the total at the end.
+@group(0) @binding(11) var<storage, read_write> grid_cells: array<atomic<u32>>;
+// Position in `xy` and velocity in `zw`.
+@group(0) @binding(12) var<storage, read_write> sorted_particles: array<vec4<f32>>;
+// A 256x1 lookup table, only bound when `sim.has_palette` is set.
+@group(0) @binding(13) var palette: texture_2d<f32>;
+// The peak energy in `ENERGY_SCALE` units, then the histogram of the lit pixels which
+// `energy_cdf` turns into its running sum.
+@group(0) @binding(14) var<storage, read_write> display_stats: array<atomic<u32>>;
+// The horizontally blurred energy of every pixel followed by the fully blurred energy, in
+// `ENERGY_SCALE` units. Too small to hold them while the glow is disabled.
+@group(0) @binding(15) var<storage, read_write> glow: array<vec4<f32>>;
+// The half resolution bloom level and its blur scratch space, then the same for the quarter
+// resolution level. Too small to hold them while the bloom is disabled.
+@group(0) @binding(16) var<storage, read_write> bloom: array<vec4<f32>>;
 
 fn xxhash32(n: u32) -> u32 {
     var h32 = n + 374761393u;
@@ -16,6 +241,9 @@ fn xxhash32(n: u32) -> u32 {
     return h32^(h32 >> 16u);
 }
 
+// Hashed before every pixel `deposit_pixel` writes, for its stochastic rounding.
+var<private> dither_seed: u32;
+
 fn randf(n: u32) -> f32 {
   particles[n].seed = xxhash32(particles[n].seed);
   return f32(particles[n].seed) / 4294967296.0;
@@ -31,10 +259,22 @@ fn mod289_3(x: vec3f) -> vec3f {
     return x - floor(x * (1. / 289.)) * 289.;
 }
 
+fn mod289_4(x: vec4f) -> vec4f {
+    return x - floor(x * (1. / 289.)) * 289.;
+}
+
 fn permute3(x: vec3f) -> vec3f {
     return mod289_3(((x * 34.) + 1.) * x);
 }
 
+fn permute4(x: vec4f) -> vec4f {
+    return mod289_4(((x * 34.) + 1.) * x);
+}
+
+fn taylorInvSqrt4(r: vec4f) -> vec4f {
+    return 1.79284291400159 - 0.85373472095314 * r;
+}
+
 //  MIT License. © Ian McEwan, Stefan Gustavson, Munrocket
 fn simplexNoise2(v: vec2f) -> f32 {
     let C = vec4(
@@ -82,47 +322,1070 @@ fn simplexNoise2(v: vec2f) -> f32 {
     return 130. * dot(m, g);
 }
 
+//  MIT License. © Ian McEwan, Stefan Gustavson, Munrocket
+fn simplexNoise3(v: vec3f) -> f32 {
+    let C = vec2(1. / 6., 1. / 3.);
+    let D = vec4(0., 0.5, 1., 2.);
+
+    // First corner
+    var i = floor(v + dot(v, C.yyy));
+    let x0 = v - i + dot(i, C.xxx);
+
+    // Other corners
+    let g = step(x0.yzx, x0.xyz);
+    let l = 1.0 - g;
+    let i1 = min(g.xyz, l.zxy);
+    let i2 = max(g.xyz, l.zxy);
+
+    // x0 = x0 - 0. + 0.0 * C
+    let x1 = x0 - i1 + 1. * C.xxx;
+    let x2 = x0 - i2 + 2. * C.xxx;
+    let x3 = x0 - 1. + 3. * C.xxx;
+
+    // Permutations
+    i = mod289_3(i);
+    let p = permute4(permute4(permute4(
+        i.z + vec4(0., i1.z, i2.z, 1.)) +
+        i.y + vec4(0., i1.y, i2.y, 1.)) +
+        i.x + vec4(0., i1.x, i2.x, 1.));
+
+    // Gradients (NxN points uniformly over a square, mapped onto an octahedron.)
+    let n_ = 1. / 7.; // N=7
+    let ns = n_ * D.wyz - D.xzx;
+
+    let j = p - 49. * floor(p * ns.z * ns.z); // mod(p,N*N)
+
+    let x_ = floor(j * ns.z);
+    let y_ = floor(j - 7.0 * x_); // mod(j,N)
+
+    let x = x_ * ns.x + ns.yyyy;
+    let y = y_ * ns.x + ns.yyyy;
+    let h = 1.0 - abs(x) - abs(y);
+
+    let b0 = vec4(x.xy, y.xy);
+    let b1 = vec4(x.zw, y.zw);
+
+    let s0 = floor(b0) * 2.0 + 1.0;
+    let s1 = floor(b1) * 2.0 + 1.0;
+    let sh = -step(h, vec4(0.));
+
+    let a0 = b0.xzyw + s0.xzyw * sh.xxyy;
+    let a1 = b1.xzyw + s1.xzyw * sh.zzww;
+
+    var p0 = vec3(a0.xy, h.x);
+    var p1 = vec3(a0.zw, h.y);
+    var p2 = vec3(a1.xy, h.z);
+    var p3 = vec3(a1.zw, h.w);
+
+    // Normalise gradients
+    let norm = taylorInvSqrt4(vec4(dot(p0, p0), dot(p1, p1), dot(p2, p2), dot(p3, p3)));
+    p0 *= norm.x;
+    p1 *= norm.y;
+    p2 *= norm.z;
+    p3 *= norm.w;
+
+    // Mix final noise value
+    var m = max(0.6 - vec4(dot(x0, x0), dot(x1, x1), dot(x2, x2), dot(x3, x3)), vec4(0.));
+    m *= m;
+    return 42. * dot(m * m, vec4(dot(p0, x0), dot(p1, x1), dot(p2, x2), dot(p3, x3)));
+}
+
+// Sum of `params.octaves` layers of simplex noise, normalized back to [-1, 1]. The octave count is
+// read from the uniform rather than a shader def so changing it doesn't recompile the pipeline.
+fn fbm(p: vec3f) -> f32 {
+    var sum = 0.0;
+    var amplitude = 1.0;
+    var frequency = 1.0;
+    var total_amplitude = 0.0;
+    for (var octave = 0u; octave < min(params.octaves, MAX_OCTAVES); octave++) {
+        // Offset each octave so their lattices don't line up at the origin.
+        sum += amplitude * simplexNoise3(p * frequency + f32(octave) * 17.31);
+        total_amplitude += amplitude;
+        amplitude *= params.gain;
+        frequency *= params.lacunarity;
+    }
+    return sum / max(total_amplitude, 1e-6);
+}
+
+// Direction of the flow at `p` in noise space, with the evolution of the field in `p.z`.
+fn field_direction(p: vec3f) -> vec2f {
+    if params.field_type == FIELD_CURL {
+        // The curl of a scalar potential (d/dy, -d/dx) has no sinks for particles to pile up in.
+        let q = p * vec3(params.curl_frequency, params.curl_frequency, 1.0);
+        let e = params.curl_epsilon;
+        let dx = fbm(q + vec3(e, 0.0, 0.0)) - fbm(q - vec3(e, 0.0, 0.0));
+        let dy = fbm(q + vec3(0.0, e, 0.0)) - fbm(q - vec3(0.0, e, 0.0));
+        let curl = vec2(dy, -dx);
+        // Flat spots in the potential have no direction to steer towards.
+        let len = length(curl);
+        if len < 1e-7 {
+            return vec2(0.0);
+        }
+        return curl / len;
+    }
+
+    let angle = fbm(p) * 3.14159;
+    return vec2(cos(angle), sin(angle));
+}
+
+// Sum of the flow of all primitives at `position`, in pixels.
+fn primitive_flow(position: vec2f) -> vec2f {
+    var flow = vec2(0.0);
+    for (var i = 0u; i < min(sim.primitive_count, arrayLength(&primitives)); i++) {
+        let primitive = primitives[i];
+        let offset = position - primitive.position;
+        var falloff = 1.0;
+        if primitive.radius > 0.0 {
+            let d = length(offset) / primitive.radius;
+            falloff = exp(-d * d);
+        }
+        // Shrinks the flow within a pixel of the center instead of dividing by zero.
+        let outward = offset / max(length(offset), 1.0);
+        var dir = primitive.direction;
+        if primitive.kind == PRIMITIVE_VORTEX {
+            dir = vec2(-outward.y, outward.x);
+        } else if primitive.kind == PRIMITIVE_SOURCE {
+            dir = outward;
+        } else if primitive.kind == PRIMITIVE_SINK {
+            dir = -outward;
+        }
+        flow += dir * primitive.strength * falloff;
+    }
+    return flow;
+}
+
+fn image_luminance(texel: vec2<i32>, dims: vec2<i32>) -> f32 {
+    let color = textureLoad(field_image, clamp(texel, vec2(0), dims - 1), 0).rgb;
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Direction along the brightness contours of the field image at `position`, and how strongly
+// defined they are there.
+fn image_contour(position: vec2f) -> vec3f {
+    let dims = vec2<i32>(textureDimensions(field_image));
+    let texel = vec2<i32>(position / vec2<f32>(params.size) * vec2<f32>(dims));
+
+    // Sobel kernel.
+    var l: array<f32, 9>;
+    for (var y = 0; y < 3; y++) {
+        for (var x = 0; x < 3; x++) {
+            l[y * 3 + x] = image_luminance(texel + vec2(x - 1, y - 1), dims);
+        }
+    }
+    let gx = (l[2] + 2.0 * l[5] + l[8]) - (l[0] + 2.0 * l[3] + l[6]);
+    let gy = (l[6] + 2.0 * l[7] + l[8]) - (l[0] + 2.0 * l[1] + l[2]);
+
+    let magnitude = length(vec2(gx, gy));
+    if magnitude < 1e-5 {
+        return vec3(0.0);
+    }
+    // Flat areas of the image are left to the noise.
+    return vec3(vec2(-gy, gx) / magnitude, smoothstep(0.0, 0.1, magnitude));
+}
+
+fn vector_field_texel(texel: vec2<i32>, dims: vec2<i32>) -> vec2f {
+    return textureLoad(vector_field, clamp(texel, vec2(0), dims - 1), 0).xy;
+}
+
+// Bilinear sample of the vector field stretched over the flow field. Filtering is done by hand
+// since 32-bit float textures usually can't be sampled with a filtering sampler.
+fn sample_vector_field(position: vec2f) -> vec2f {
+    let dims = vec2<i32>(textureDimensions(vector_field));
+    let grid = position / vec2<f32>(params.size) * vec2<f32>(dims) - 0.5;
+    let base = vec2<i32>(floor(grid));
+    let t = fract(grid);
+    let top = mix(vector_field_texel(base, dims), vector_field_texel(base + vec2(1, 0), dims), t.x);
+    let bottom = mix(
+        vector_field_texel(base + vec2(0, 1), dims),
+        vector_field_texel(base + vec2(1, 1), dims),
+        t.x,
+    );
+    return mix(top, bottom, t.y);
+}
+
+// Linear lookup in the palette, with intensities outside `0.0..=1.0` clamped to its ends.
+fn sample_palette(intensity: f32) -> vec3f {
+    let last = i32(textureDimensions(palette).x) - 1;
+    let position = clamp(intensity, 0.0, 1.0) * f32(last);
+    let low = i32(floor(position));
+    let high = min(low + 1, last);
+    let a = textureLoad(palette, vec2(low, 0), 0).rgb;
+    let b = textureLoad(palette, vec2(high, 0), 0).rgb;
+    return mix(a, b, fract(position));
+}
+
+fn obstacle_count() -> u32 {
+    return min(sim.obstacle_count, arrayLength(&obstacles));
+}
+
+fn inside_obstacle(position: vec2f) -> bool {
+    for (var i = 0u; i < obstacle_count(); i++) {
+        if distance(position, obstacles[i].center) < obstacles[i].radius {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Pushes a particle that moved into an obstacle back onto its edge, keeping only the tangential
+// part of its velocity so it slides around.
+fn resolve_obstacles(pid: u32) {
+    for (var iteration = 0u; iteration < MAX_OBSTACLE_ITERATIONS; iteration++) {
+        var collided = false;
+        for (var i = 0u; i < obstacle_count(); i++) {
+            let obstacle = obstacles[i];
+            let offset = particles[pid].position - obstacle.center;
+            let distance = length(offset);
+            if distance >= obstacle.radius {
+                continue;
+            }
+            collided = true;
+            // A particle right at the center has no way out, pick one.
+            var normal = vec2(1.0, 0.0);
+            if distance > 1e-4 {
+                normal = offset / distance;
+            }
+            particles[pid].position = obstacle.center + normal * (obstacle.radius + 0.01);
+            let velocity = particles[pid].velocity;
+            particles[pid].velocity = velocity - normal * min(dot(velocity, normal), 0.0);
+        }
+        if !collided {
+            return;
+        }
+    }
+}
+
+fn sdf_texel(texel: vec2<i32>, dims: vec2<i32>) -> f32 {
+    return textureLoad(obstacle_sdf, clamp(texel, vec2(0), dims - 1), 0).r;
+}
+
+// Distance in pixels of the flow field to the nearest obstacle edge, followed by the direction
+// away from the obstacles.
+fn sample_obstacle_sdf(position: vec2f) -> vec3f {
+    let dims = vec2<i32>(textureDimensions(obstacle_sdf));
+    let texels_per_pixel = vec2<f32>(dims) / vec2<f32>(params.size);
+    let texel = vec2<i32>(position * texels_per_pixel);
+    let distance = sdf_texel(texel, dims) / max(texels_per_pixel.x, texels_per_pixel.y);
+    let gradient = vec2(
+        sdf_texel(texel + vec2(1, 0), dims) - sdf_texel(texel - vec2(1, 0), dims),
+        sdf_texel(texel + vec2(0, 1), dims) - sdf_texel(texel - vec2(0, 1), dims),
+    );
+    return vec3(distance, gradient);
+}
+
+fn sample_max_age(pid: u32) -> f32 {
+    let jitter = randf(pid) * 2.0 - 1.0;
+    return params.particle_lifetime * max(1.0 + params.lifetime_variance * jitter, 0.0);
+}
+
+// Samples the `InitialVelocity` of the field like `InitialVelocity::sample`.
+fn sample_velocity(pid: u32, position: vec2<f32>, size: vec2<f32>) -> vec2<f32> {
+    let speed = mix(params.initial_speed_min, params.initial_speed_max, randf(pid));
+    if params.initial_velocity == VELOCITY_RANDOM {
+        let angle = randf(pid) * 6.28318;
+        return vec2(cos(angle), sin(angle)) * speed;
+    } else if params.initial_velocity == VELOCITY_RADIAL {
+        let offset = position - size / 2.0;
+        if all(offset == vec2(0.0)) {
+            return vec2(0.0);
+        }
+        return normalize(offset) * speed;
+    }
+    return vec2(0.0);
+}
+
+fn respawn(pid: u32, size: vec2<f32>) {
+    let position = vec2(randf(pid) * size.x, randf(pid) * size.y);
+    particles[pid].position = position;
+    particles[pid].velocity = sample_velocity(pid, position, size);
+    particles[pid].age = 0.0;
+    particles[pid].max_age = sample_max_age(pid);
+}
+
+fn deposit_energy(index: u32, amount: u32) {
+    if atomicLoad(&energy_buffer[index]) < ENERGY_LIMIT {
+        atomicAdd(&energy_buffer[index], amount);
+    }
+}
+
+// How far a particle has faded at the given fraction of its lifetime.
+fn age_fade(age: f32) -> f32 {
+    if params.age_curve == AGE_EASE_IN {
+        return age * age;
+    } else if params.age_curve == AGE_EASE_OUT {
+        return 1.0 - (1.0 - age) * (1.0 - age);
+    }
+    return age;
+}
+
+fn hsv_to_rgb(hsv: vec3<f32>) -> vec3<f32> {
+    let hue = fract(hsv.x + vec3(0.0, 2.0 / 3.0, 1.0 / 3.0));
+    let rgb = clamp(abs(hue * 6.0 - 3.0) - 1.0, vec3(0.0), vec3(1.0));
+    return hsv.z * mix(vec3(1.0), rgb, hsv.y);
+}
+
+// Checks the exponent bits, since `x != x` may be optimized away by the shader compiler.
+fn is_finite(v: vec2<f32>) -> bool {
+    let exponent = bitcast<vec2<u32>>(v) & vec2(0x7f800000u);
+    return all(exponent != vec2(0x7f800000u));
+}
+
+fn in_bounds(position: vec2<f32>, size: vec2<f32>) -> bool {
+    return all(position >= vec2(0.0)) && all(position < size);
+}
+
+fn grid_dims() -> vec2<u32> {
+    return (params.size + NEIGHBOR_CELL_SIZE - 1u) / NEIGHBOR_CELL_SIZE;
+}
+
+fn grid_index(cell: vec2<u32>) -> u32 {
+    return cell.x + cell.y * grid_dims().x;
+}
+
+fn grid_cell(position: vec2<f32>) -> vec2<u32> {
+    return min(vec2<u32>(position) / NEIGHBOR_CELL_SIZE, grid_dims() - 1u);
+}
+
+// Particles outside the field, including killed ones, are left out of the grid.
+@compute @workgroup_size(#{WORKGROUP_SIZE},1,1)
+fn bin_particles(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let pid = invocation_id.x;
+    if pid >= arrayLength(&particles) || !in_bounds(particles[pid].position, vec2<f32>(params.size)) {
+        return;
+    }
+    atomicAdd(&grid_cells[grid_index(grid_cell(particles[pid].position))], 1u);
+}
+
+var<workgroup> chunk_totals: array<u32, PREFIX_SUM_THREADS>;
+
+// Turns the counts into the end of every cell in `sorted_particles`. Each thread sums a chunk of
+// cells, the chunk totals are scanned in shared memory and each thread then writes its chunk.
 @compute @workgroup_size(256,1,1)
+fn prefix_sum(@builtin(local_invocation_index) index: u32) {
+    let dims = grid_dims();
+    let cells = dims.x * dims.y;
+    let chunk = (cells + PREFIX_SUM_THREADS - 1u) / PREFIX_SUM_THREADS;
+    let start = min(index * chunk, cells);
+    let end = min(start + chunk, cells);
+
+    var total = 0u;
+    for (var cell = start; cell < end; cell += 1u) {
+        total += atomicLoad(&grid_cells[cell]);
+    }
+    chunk_totals[index] = total;
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < PREFIX_SUM_THREADS; offset *= 2u) {
+        var value = chunk_totals[index];
+        if index >= offset {
+            value += chunk_totals[index - offset];
+        }
+        workgroupBarrier();
+        chunk_totals[index] = value;
+        workgroupBarrier();
+    }
+
+    var running = chunk_totals[index] - total;
+    for (var cell = start; cell < end; cell += 1u) {
+        running += atomicLoad(&grid_cells[cell]);
+        atomicStore(&grid_cells[cell], running);
+    }
+    if index == PREFIX_SUM_THREADS - 1u {
+        atomicStore(&grid_cells[cells], chunk_totals[index]);
+    }
+}
+
+// Fills every cell from its end down, which leaves the start of each cell in `grid_cells`.
+@compute @workgroup_size(#{WORKGROUP_SIZE},1,1)
+fn scatter_particles(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let pid = invocation_id.x;
+    if pid >= arrayLength(&particles) || !in_bounds(particles[pid].position, vec2<f32>(params.size)) {
+        return;
+    }
+    let position = particles[pid].position;
+    let slot = atomicSub(&grid_cells[grid_index(grid_cell(position))], 1u) - 1u;
+    sorted_particles[slot] = vec4(position, particles[pid].velocity);
+}
+
+struct Neighborhood {
+  // Sum of the pushes away from the particles within the repulsion radius, each falling off
+  // linearly to nothing at the radius.
+  repulsion: vec2<f32>,
+  // Sums over the particles within the neighbor radius.
+  velocity: vec2<f32>,
+  position: vec2<f32>,
+  count: f32,
+}
+
+fn neighborhood(position: vec2<f32>) -> Neighborhood {
+    let dims = vec2<i32>(grid_dims());
+    let center = vec2<i32>(grid_cell(position));
+    var result = Neighborhood(vec2(0.0), vec2(0.0), vec2(0.0), 0.0);
+    for (var y = max(center.y - 1, 0); y <= min(center.y + 1, dims.y - 1); y += 1) {
+        for (var x = max(center.x - 1, 0); x <= min(center.x + 1, dims.x - 1); x += 1) {
+            let cell = grid_index(vec2<u32>(vec2(x, y)));
+            let start = atomicLoad(&grid_cells[cell]);
+            let end = min(atomicLoad(&grid_cells[cell + 1u]), start + MAX_CELL_NEIGHBORS);
+            for (var i = start; i < end; i += 1u) {
+                let neighbor = sorted_particles[i];
+                let offset = position - neighbor.xy;
+                let distance = length(offset);
+                // Skips the particle itself.
+                if distance <= 1e-4 {
+                    continue;
+                }
+                if distance < params.repulsion_radius {
+                    result.repulsion += offset / distance * (1.0 - distance / params.repulsion_radius);
+                }
+                if distance < params.neighbor_radius {
+                    result.velocity += neighbor.zw;
+                    result.position += neighbor.xy;
+                    result.count += 1.0;
+                }
+            }
+        }
+    }
+    return result;
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE},1,1)
 fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     let pid = invocation_id.x;
+    if pid >= arrayLength(&particles) {
+        return;
+    }
+
     let particle = particles[pid];
+    // The species list can shrink at runtime without the particles being reassigned.
+    let kind = species[min(particle.species, arrayLength(&species) - 1u)];
+    let size = vec2<f32>(params.size);
 
-    let plocf = vec2<f32>(particle.position) / 100.0;
+    // Killed particles stay outside the field until the boundary mode changes or the simulation
+    // is reset.
+    if params.boundary == BOUNDARY_KILL && !in_bounds(particle.position, size) {
+        return;
+    }
 
-    let angle = simplexNoise2(plocf / 2.8) * 3.14159;
-    let dir = vec2<f32>(cos(angle), sin(angle));
+    let plocf = vec2<f32>(particle.position) / params.noise_scale + kind.noise_offset + params.noise_offset;
 
-    let alpha = 0.01;
+#ifdef CUSTOM_FIELD
+    var noise_dir = field(plocf, sim.elapsed * params.field_evolution_speed);
+#else
+    var noise_dir = field_direction(vec3(plocf, sim.elapsed * params.field_evolution_speed));
+#endif
+    if params.field_rotation != 0.0 {
+        let c = cos(params.field_rotation);
+        let s = sin(params.field_rotation);
+        noise_dir = vec2(c * noise_dir.x - s * noise_dir.y, s * noise_dir.x + c * noise_dir.y);
+    }
+    var dir = mix(primitive_flow(particle.position), noise_dir, params.noise_mix);
+    if sim.has_field_image != 0u {
+        let contour = image_contour(particle.position);
+        dir = mix(dir, contour.xy, contour.z * params.image_influence);
+    }
+    if sim.has_vector_field != 0u {
+        // Short vectors only partially replace the flow, so unpainted areas keep the noise.
+        let vector = sample_vector_field(particle.position);
+        let weight = params.vector_field_influence * min(length(vector), 1.0);
+        dir = dir * (1.0 - weight) + vector * params.vector_field_influence;
+    }
+    if sim.has_obstacle_sdf != 0u {
+        let sdf = sample_obstacle_sdf(particle.position);
+        if sdf.x < params.sdf_threshold && length(sdf.yz) > 1e-6 {
+            let normal = normalize(sdf.yz);
+            if sdf.x <= 0.0 {
+                dir = normal;
+            } else {
+                // Follow the edge in whichever direction the flow was already going.
+                var tangent = vec2(-normal.y, normal.x);
+                if dot(tangent, dir) < 0.0 {
+                    tangent = -tangent;
+                }
+                dir = mix(dir, tangent, 1.0 - sdf.x / params.sdf_threshold);
+            }
+        }
+    }
+
+    let dt = sim.dt * sim.speed * kind.speed;
+    let alpha = 1.0 - exp(-STEERING_PER_SECOND * params.field_strength * dt / particle.mass);
+
+    var velocity = (particle.velocity * (1.0 - alpha)) + (dir * alpha);
+    for (var i = 0u; i < min(params.pointer_count, MAX_POINTERS); i += 1u) {
+        let pointer = params.pointers[i];
+        let to_pointer = pointer.xy - particle.position;
+        let distance = length(to_pointer);
+        let falloff = 1.0 - smoothstep(0.0, pointer.w, distance);
+        velocity += to_pointer / max(distance, 1.0) * pointer.z * falloff * dt / particle.mass;
+    }
+    velocity += (params.gravity + params.wind / particle.mass) * dt;
+    if params.neighbors_enabled != 0u && in_bounds(particle.position, size) {
+        let neighbors = neighborhood(particle.position);
+        if params.repulsion_enabled != 0u {
+            velocity += neighbors.repulsion * params.repulsion_strength * dt / particle.mass;
+        }
+        if neighbors.count > 0.0 {
+            if params.alignment_strength > 0.0 {
+                let alignment = 1.0 - exp(-params.alignment_strength * dt / particle.mass);
+                velocity = mix(velocity, neighbors.velocity / neighbors.count, alignment);
+            }
+            if params.cohesion_strength != 0.0 {
+                let to_center = neighbors.position / neighbors.count - particle.position;
+                velocity += to_center / max(params.neighbor_radius, 1.0) * params.cohesion_strength
+                    * dt / particle.mass;
+            }
+        }
+    }
+    velocity *= pow(1.0 - params.drag, dt * 60.0);
+    let max_velocity = params.max_speed / PIXELS_PER_SECOND;
+    let speed = length(velocity);
+    if speed > max_velocity {
+        velocity *= max_velocity / speed;
+    }
+    particles[pid].velocity = velocity;
+    particles[pid].position += particles[pid].velocity * dt * PIXELS_PER_SECOND;
 
-    particles[pid].velocity = (particles[pid].velocity * (1.0 - alpha)) + (dir * alpha);
-    particles[pid].position += particles[pid].velocity * 0.3;
+    resolve_obstacles(pid);
 
-    if (particles[pid].position.x >= 1280.0
-       || particles[pid].position.x < 0.0
-       || particles[pid].position.y >= 720.0
-       || particles[pid].position.y < 0.0) {
-        particles[pid].position.x = randf(pid) * 1280.0;
-        particles[pid].position.y = randf(pid) * 720.0;
-        particles[pid].velocity.x = randf(pid) * 2.0 - 1.0;
-        particles[pid].velocity.y = randf(pid) * 2.0 - 1.0;
+    // Cleared when the particle jumps, so it doesn't draw a line across the image.
+    var continuous = true;
+
+    // Respawn particles that blew up before they poison the energy buffer.
+    if !is_finite(particles[pid].position) || !is_finite(particles[pid].velocity) {
+        respawn(pid, size);
+        continuous = false;
+    }
+
+    let position = particles[pid].position;
+    if !in_bounds(position, size) {
+        if params.boundary == BOUNDARY_WRAP {
+            let wrapped = position - floor(position / size) * size;
+            // Rounding can land exactly on the far edge.
+            particles[pid].position = min(wrapped, size - 0.001);
+            continuous = false;
+        } else if params.boundary == BOUNDARY_BOUNCE {
+            let velocity = particles[pid].velocity;
+            let moving_out = (position < vec2(0.0) & velocity < vec2(0.0))
+                | (position >= size & velocity > vec2(0.0));
+            particles[pid].velocity = select(velocity, -velocity, moving_out);
+            particles[pid].position = clamp(position, vec2(0.0), size - 0.001);
+        } else if params.boundary == BOUNDARY_KILL {
+            return;
+        } else {
+            respawn(pid, size);
+            continuous = false;
+        }
+    }
+
+    if params.particle_lifetime > 0.0 {
+        if particles[pid].max_age <= 0.0 {
+            // Lifetimes were just enabled, spread the first deaths out like the initial state does.
+            particles[pid].max_age = sample_max_age(pid);
+            particles[pid].age = randf(pid) * particles[pid].max_age;
+        }
+        particles[pid].age += dt;
+        if particles[pid].age >= particles[pid].max_age {
+            respawn(pid, size);
+            continuous = false;
+        }
     }
 
     let p = particles[pid].position;
 
-    var oldValue: u32 = atomicAdd(&energy_buffer[u32(p.x) + #{SCREEN_WIDTH}u * u32(p.y)], 1u);
+    var color = particles[pid].color * kind.color;
+    if params.color_mode == COLOR_HEADING {
+        let heading = particles[pid].velocity;
+        let hue = atan2(heading.y, heading.x) / 6.28318 + 0.5;
+        color = vec4(hsv_to_rgb(vec3(hue, params.color_saturation, params.color_value)), color.a);
+    } else if params.color_mode == COLOR_SPEED {
+        let relative = length(particles[pid].velocity) / max(max_velocity, 1e-6);
+        let t = pow(clamp(relative, 0.0, 1.0), params.speed_gamma);
+        color = vec4(mix(params.color_slow.rgb, params.color_fast.rgb, t), color.a);
+    } else if params.color_mode == COLOR_AGE && params.particle_lifetime > 0.0 {
+        let age = clamp(particles[pid].age / max(particles[pid].max_age, 1e-6), 0.0, 1.0);
+        color = vec4(color.rgb * (1.0 - age_fade(age)), color.a);
+    }
+    let deposit = color * kind.deposit * params.deposit;
+    dither_seed = xxhash32(pid ^ xxhash32(sim.frame ^ bitcast<u32>(p.x) ^ bitcast<u32>(p.y)));
+    if params.trail_lines != 0u && continuous {
+        deposit_line(particle.position, p, deposit);
+    } else {
+        deposit_point(p, deposit);
+    }
+}
+
+fn energy_size() -> vec2<u32> {
+    return params.size * params.supersample;
+}
+
+fn deposit_point(position: vec2<f32>, deposit: vec4<f32>) {
+    let position = position * f32(params.supersample);
+    if params.splat_radius > 0.0 {
+        deposit_splat(position, deposit);
+    } else {
+        deposit_pixel(vec2<u32>(position), deposit);
+    }
+}
+
+// Deposits at evenly spaced points about a pixel apart from just past `start` up to `end`, sharing
+// the energy of a single deposit so fast particles aren't brighter than slow ones.
+fn deposit_line(start: vec2<f32>, end: vec2<f32>, deposit: vec4<f32>) {
+    let steps = u32(clamp(ceil(distance(start, end)), 1.0, f32(MAX_LINE_STEPS)));
+    let size = vec2<f32>(params.size);
+    for (var i = 1u; i <= steps; i += 1u) {
+        let point = mix(start, end, f32(i) / f32(steps));
+        if in_bounds(point, size) {
+            deposit_point(point, deposit / f32(steps));
+        }
+    }
+}
+
+// Rounded stochastically, so the small shares of a deposit split along a line or over a splat
+// still add up to the whole deposit on average instead of truncating to nothing.
+fn deposit_pixel(pixel: vec2<u32>, deposit: vec4<f32>) {
+    dither_seed = xxhash32(dither_seed);
+    // 24 bits fit a float exactly, keeping the dither below one so zero stays zero.
+    let dither = f32(dither_seed >> 8u) / 16777216.0;
+    let energy = vec4<u32>(floor(max(deposit, vec4(0.0)) * ENERGY_SCALE + dither));
+    let pxl_id = 4u * (pixel.x + energy_size().x * pixel.y);
+    deposit_energy(pxl_id, energy.r);
+    deposit_energy(pxl_id + 1u, energy.g);
+    deposit_energy(pxl_id + 2u, energy.b);
+    deposit_energy(pxl_id + 3u, energy.a);
+}
+
+// Spreads the deposit over a Gaussian around the exact position, in accumulated pixels, normalized
+// so a splat adds up to the same energy as a single pixel. Pixels outside the field are dropped.
+fn deposit_splat(position: vec2<f32>, deposit: vec4<f32>) {
+    // Capped after scaling, so supersampling doesn't multiply the pixels a splat covers.
+    let radius = min(params.splat_radius * f32(params.supersample), MAX_SPLAT_RADIUS);
+    let reach = i32(ceil(radius));
+    let center = vec2<i32>(floor(position));
+    let sigma = radius * 0.5;
+    let falloff = 1.0 / (2.0 * sigma * sigma);
+
+    var total = 0.0;
+    for (var y = -reach; y <= reach; y += 1) {
+        for (var x = -reach; x <= reach; x += 1) {
+            let offset = vec2<f32>(center + vec2(x, y)) + 0.5 - position;
+            total += exp(-dot(offset, offset) * falloff);
+        }
+    }
+
+    let size = vec2<i32>(energy_size());
+    for (var y = -reach; y <= reach; y += 1) {
+        for (var x = -reach; x <= reach; x += 1) {
+            let pixel = center + vec2(x, y);
+            if any(pixel < vec2(0)) || any(pixel >= size) {
+                continue;
+            }
+            let offset = vec2<f32>(pixel) + 0.5 - position;
+            let weight = exp(-dot(offset, offset) * falloff) / total;
+            deposit_pixel(vec2<u32>(pixel), deposit * weight);
+        }
+    }
+}
+
+// The energy summed over the accumulated pixels covering an image pixel. Deposits are spaced in
+// image pixels, so the sum matches the energy without supersampling.
+fn pixel_energy(pixel: vec2<u32>) -> vec3<f32> {
+    var energy = vec3(0.0);
+    for (var y = 0u; y < params.supersample; y++) {
+        for (var x = 0u; x < params.supersample; x++) {
+            let sample = pixel * params.supersample + vec2(x, y);
+            let pxl_id = 4u * (sample.x + energy_size().x * sample.y);
+            energy += vec3<f32>(vec3(
+                atomicLoad(&energy_buffer[pxl_id]),
+                atomicLoad(&energy_buffer[pxl_id + 1u]),
+                atomicLoad(&energy_buffer[pxl_id + 2u]),
+            ));
+        }
+    }
+    return energy;
+}
+
+fn peak_energy(energy: vec3<f32>) -> f32 {
+    return max(energy.r, max(energy.g, energy.b));
+}
+
+// Where the peak channel of a pixel falls between no energy and the brightest pixel, on a log
+// scale.
+fn log_energy(peak: f32) -> f32 {
+    let brightest = f32(atomicLoad(&display_stats[0]));
+    return log(1.0 + peak) / log(1.0 + max(brightest, 1.0));
+}
+
+fn histogram_bin(peak: f32) -> u32 {
+    return min(u32(log_energy(peak) * f32(HISTOGRAM_BINS)), HISTOGRAM_BINS - 1u);
+}
+
+var<workgroup> workgroup_peak: atomic<u32>;
+
+@compute @workgroup_size(16,16,1)
+fn energy_max(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    if index == 0u {
+        atomicStore(&workgroup_peak, 0u);
+    }
+    workgroupBarrier();
+    if all(invocation_id.xy < params.size) {
+        atomicMax(&workgroup_peak, u32(peak_energy(pixel_energy(invocation_id.xy))));
+    }
+    workgroupBarrier();
+    if index == 0u {
+        atomicMax(&display_stats[0], atomicLoad(&workgroup_peak));
+    }
+}
+
+var<workgroup> workgroup_histogram: array<atomic<u32>, HISTOGRAM_BINS>;
+
+// Dark pixels are left out, so the background doesn't take up most of the brightness range.
+@compute @workgroup_size(16,16,1)
+fn energy_histogram(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&workgroup_histogram[index], 0u);
+    workgroupBarrier();
+    if all(invocation_id.xy < params.size) {
+        let peak = peak_energy(pixel_energy(invocation_id.xy));
+        if peak > 0.0 {
+            atomicAdd(&workgroup_histogram[histogram_bin(peak)], 1u);
+        }
+    }
+    workgroupBarrier();
+    let count = atomicLoad(&workgroup_histogram[index]);
+    if count > 0u {
+        atomicAdd(&display_stats[1u + index], count);
+    }
+}
+
+@compute @workgroup_size(256,1,1)
+fn energy_cdf(@builtin(local_invocation_index) index: u32) {
+    chunk_totals[index] = atomicLoad(&display_stats[1u + index]);
+    workgroupBarrier();
+    for (var offset = 1u; offset < HISTOGRAM_BINS; offset *= 2u) {
+        var value = chunk_totals[index];
+        if index >= offset {
+            value += chunk_totals[index - offset];
+        }
+        workgroupBarrier();
+        chunk_totals[index] = value;
+        workgroupBarrier();
+    }
+    atomicStore(&display_stats[1u + index], chunk_totals[index]);
+}
+
+fn glow_allocated() -> bool {
+    return arrayLength(&glow) >= 2u * params.size.x * params.size.y;
+}
+
+fn glow_weight(offset: i32) -> f32 {
+    let sigma = max(params.glow_radius * 0.5, 0.5);
+    return exp(-f32(offset * offset) / (2.0 * sigma * sigma));
+}
+
+// Pixels beyond the edges count as having no energy, so the glow fades out towards them.
+@compute @workgroup_size(16,16,1)
+fn glow_horizontal(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if any(invocation_id.xy >= params.size) || !glow_allocated() {
+        return;
+    }
+
+    let radius = i32(ceil(min(params.glow_radius, MAX_GLOW_RADIUS)));
+    var sum = vec3(0.0);
+    var total = 0.0;
+    for (var offset = -radius; offset <= radius; offset++) {
+        let weight = glow_weight(offset);
+        total += weight;
+        let x = i32(invocation_id.x) + offset;
+        if x >= 0 && x < i32(params.size.x) {
+            sum += pixel_energy(vec2(u32(x), invocation_id.y)) * weight;
+        }
+    }
+    glow[invocation_id.x + params.size.x * invocation_id.y] = vec4(sum / total, 0.0);
+}
+
+@compute @workgroup_size(16,16,1)
+fn glow_vertical(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if any(invocation_id.xy >= params.size) || !glow_allocated() {
+        return;
+    }
+
+    let radius = i32(ceil(min(params.glow_radius, MAX_GLOW_RADIUS)));
+    var sum = vec3(0.0);
+    var total = 0.0;
+    for (var offset = -radius; offset <= radius; offset++) {
+        let weight = glow_weight(offset);
+        total += weight;
+        let y = i32(invocation_id.y) + offset;
+        if y >= 0 && y < i32(params.size.y) {
+            sum += glow[invocation_id.x + params.size.x * u32(y)].rgb * weight;
+        }
+    }
+    let pixels = params.size.x * params.size.y;
+    glow[pixels + invocation_id.x + params.size.x * invocation_id.y] = vec4(sum / total, 0.0);
+}
+
+// The energy scaled so its peak channel follows the transfer curve of the display mode. Takes the
+// raw counts as floats so the blurred glow can go through it as well.
+fn normalized_energy(raw: vec3<f32>) -> vec3<f32> {
+    let energy = raw / ENERGY_SCALE;
+    let peak = peak_energy(raw);
+    if params.display_mode == DISPLAY_LINEAR || peak <= 0.0 {
+        return energy / 1000.0;
+    }
+    var brightness = log_energy(peak);
+    if params.display_mode == DISPLAY_EQUALIZED {
+        let lit = atomicLoad(&display_stats[HISTOGRAM_BINS]);
+        brightness = f32(atomicLoad(&display_stats[1u + histogram_bin(peak)])) / f32(max(lit, 1u));
+    }
+    return energy / (peak / ENERGY_SCALE) * brightness;
+}
+
+// The brightness of a pixel before the exposure, including its glow.
+fn pixel_light(pixel: vec2<u32>) -> vec3<f32> {
+    var light = normalized_energy(pixel_energy(pixel));
+    if params.glow_strength > 0.0 && glow_allocated() {
+        let index = params.size.x * params.size.y + pixel.x + params.size.x * pixel.y;
+        light += params.glow_strength * normalized_energy(glow[index].rgb);
+    }
+    return light;
+}
+
+// Level 0 is half the resolution of the image, level 1 a quarter.
+fn bloom_dims(level: u32) -> vec2<u32> {
+    let half = (params.size + 1u) / 2u;
+    if level == 0u {
+        return half;
+    }
+    return (half + 1u) / 2u;
+}
+
+fn bloom_start(level: u32) -> u32 {
+    if level == 0u {
+        return 0u;
+    }
+    let half = bloom_dims(0u);
+    return 2u * half.x * half.y;
+}
+
+fn bloom_allocated() -> bool {
+    let quarter = bloom_dims(1u);
+    return arrayLength(&bloom) >= bloom_start(1u) + 2u * quarter.x * quarter.y;
+}
+
+fn bloom_index(level: u32, texel: vec2<u32>) -> u32 {
+    return bloom_start(level) + texel.x + bloom_dims(level).x * texel.y;
+}
+
+// The part of the light above the threshold, keeping its hue.
+fn bright_part(light: vec3<f32>) -> vec3<f32> {
+    let peak = max(light.r, max(light.g, light.b));
+    if peak <= params.bloom_threshold {
+        return vec3(0.0);
+    }
+    return light * (peak - params.bloom_threshold) / peak;
+}
+
+@compute @workgroup_size(16,16,1)
+fn bloom_threshold(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if any(invocation_id.xy >= bloom_dims(0u)) || !bloom_allocated() {
+        return;
+    }
+
+    var sum = vec3(0.0);
+    var count = 0.0;
+    for (var i = 0u; i < 4u; i++) {
+        let pixel = 2u * invocation_id.xy + vec2(i % 2u, i / 2u);
+        if all(pixel < params.size) {
+            sum += bright_part(pixel_light(pixel));
+            count += 1.0;
+        }
+    }
+    bloom[bloom_index(0u, invocation_id.xy)] = vec4(sum / count, 0.0);
+}
+
+@compute @workgroup_size(16,16,1)
+fn bloom_downsample(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if any(invocation_id.xy >= bloom_dims(1u)) || !bloom_allocated() {
+        return;
+    }
+
+    var sum = vec3(0.0);
+    var count = 0.0;
+    for (var i = 0u; i < 4u; i++) {
+        let texel = 2u * invocation_id.xy + vec2(i % 2u, i / 2u);
+        if all(texel < bloom_dims(0u)) {
+            sum += bloom[bloom_index(0u, texel)].rgb;
+            count += 1.0;
+        }
+    }
+    bloom[bloom_index(1u, invocation_id.xy)] = vec4(sum / count, 0.0);
+}
+
+fn bloom_weight(offset: i32) -> f32 {
+    let sigma = f32(BLOOM_RADIUS) * 0.5;
+    return exp(-f32(offset * offset) / (2.0 * sigma * sigma));
+}
+
+// Blurs the level in `invocation_id.z` into its scratch space.
+@compute @workgroup_size(16,16,1)
+fn bloom_horizontal(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let level = invocation_id.z;
+    let dims = bloom_dims(level);
+    if any(invocation_id.xy >= dims) || !bloom_allocated() {
+        return;
+    }
+
+    var sum = vec3(0.0);
+    var total = 0.0;
+    for (var offset = -BLOOM_RADIUS; offset <= BLOOM_RADIUS; offset++) {
+        let weight = bloom_weight(offset);
+        total += weight;
+        let x = i32(invocation_id.x) + offset;
+        if x >= 0 && x < i32(dims.x) {
+            sum += bloom[bloom_index(level, vec2(u32(x), invocation_id.y))].rgb * weight;
+        }
+    }
+    let scratch = dims.x * dims.y + bloom_index(level, invocation_id.xy);
+    bloom[scratch] = vec4(sum / total, 0.0);
+}
+
+// Blurs the scratch space of the level in `invocation_id.z` back into the level.
+@compute @workgroup_size(16,16,1)
+fn bloom_vertical(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let level = invocation_id.z;
+    let dims = bloom_dims(level);
+    if any(invocation_id.xy >= dims) || !bloom_allocated() {
+        return;
+    }
+
+    var sum = vec3(0.0);
+    var total = 0.0;
+    for (var offset = -BLOOM_RADIUS; offset <= BLOOM_RADIUS; offset++) {
+        let weight = bloom_weight(offset);
+        total += weight;
+        let y = i32(invocation_id.y) + offset;
+        if y >= 0 && y < i32(dims.y) {
+            let scratch = dims.x * dims.y + bloom_index(level, vec2(invocation_id.x, u32(y)));
+            sum += bloom[scratch].rgb * weight;
+        }
+    }
+    bloom[bloom_index(level, invocation_id.xy)] = vec4(sum / total, 0.0);
+}
+
+fn bloom_texel(level: u32, texel: vec2<i32>) -> vec3<f32> {
+    let dims = vec2<i32>(bloom_dims(level));
+    return bloom[bloom_index(level, vec2<u32>(clamp(texel, vec2(0), dims - 1)))].rgb;
+}
+
+// Scales a bloom level back up to the image with bilinear filtering.
+fn sample_bloom(level: u32, pixel: vec2<u32>) -> vec3<f32> {
+    let scale = f32(2u << level);
+    let position = (vec2<f32>(pixel) + 0.5) / scale - 0.5;
+    let texel = vec2<i32>(floor(position));
+    let t = position - floor(position);
+    let top = mix(bloom_texel(level, texel), bloom_texel(level, texel + vec2(1, 0)), t.x);
+    let bottom = mix(
+        bloom_texel(level, texel + vec2(0, 1)),
+        bloom_texel(level, texel + vec2(1, 1)),
+        t.x
+    );
+    return mix(top, bottom, t.y);
+}
+
+// Matches `Tonemap::apply` on the Rust side.
+fn tonemap(light: vec3<f32>) -> vec3<f32> {
+    if params.tonemap == TONEMAP_REINHARD {
+        return light / (1.0 + light);
+    } else if params.tonemap == TONEMAP_ACES {
+        let aces = light * (2.51 * light + 0.03) / (light * (2.43 * light + 0.59) + 0.14);
+        return clamp(aces, vec3(0.0), vec3(1.0));
+    }
+    return light;
+}
+
+// Rotates `color` around the gray axis by `turns` of the color wheel, matching `rotate_hue` on the
+// Rust side.
+fn rotate_hue(color: vec3<f32>, turns: f32) -> vec3<f32> {
+    let angle = turns * 6.28318;
+    let axis = vec3(0.57735);
+    return color * cos(angle) + cross(axis, color) * sin(angle)
+        + axis * dot(axis, color) * (1.0 - cos(angle));
+}
+
+// Darkens the corners and adds grain, as the final touch on the image.
+fn film_effects(color: vec3<f32>, pixel: vec2<u32>) -> vec3<f32> {
+    var result = color;
+    if params.vignette_strength > 0.0 {
+        let offset = (vec2<f32>(pixel) + 0.5) / vec2<f32>(params.size) - 0.5;
+        let reach = length(offset) / length(vec2(0.5));
+        let darkening = smoothstep(min(params.vignette_radius, 0.999), 1.0, reach);
+        result *= 1.0 - params.vignette_strength * darkening;
+    }
+    if params.grain_amount > 0.0 {
+        let seed = xxhash32(pixel.x + params.size.x * pixel.y) ^ xxhash32(sim.frame);
+        let noise = f32(xxhash32(seed)) / 4294967296.0 - 0.5;
+        result += params.grain_amount * noise;
+    }
+    return max(result, vec3(0.0));
 }
 
 @compute @workgroup_size(16,16,1)
 fn draw(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    let pxl_id = invocation_id.x + #{SCREEN_WIDTH}u * invocation_id.y;
-    let energy : u32 = atomicLoad(&energy_buffer[pxl_id]);
+    if any(invocation_id.xy >= params.size) {
+        return;
+    }
+    var light = pixel_light(invocation_id.xy);
+    if params.bloom_enabled != 0u && bloom_allocated() {
+        let blurred = BLOOM_HALF_WEIGHT * sample_bloom(0u, invocation_id.xy)
+            + BLOOM_QUARTER_WEIGHT * sample_bloom(1u, invocation_id.xy);
+        light += params.bloom_intensity * blurred;
+    }
+    if params.exposure > 0.0 {
+        light = 1.0 - exp(-light * params.exposure);
+    }
+    light = tonemap(light);
+    if params.gamma != 1.0 {
+        light = pow(light, vec3(1.0 / params.gamma));
+    }
 
-    textureStore(dst_image, vec2(invocation_id.x, invocation_id.y), vec4(vec3(0.0, 0.0, 0.01) + vec3(1.0) * f32(energy)/1000.0, 1.0));
+    var background = params.background_color.rgb;
+    var color = background + params.trail_color.rgb * light;
+    if sim.has_palette != 0u {
+        background = sample_palette(0.0);
+        color = sample_palette(dot(light, vec3(0.2126, 0.7152, 0.0722)));
+    }
+    // Trails that graze an obstacle stop at its edge.
+    if inside_obstacle(vec2<f32>(invocation_id.xy) + 0.5) {
+        color = background;
+    }
+    if params.hue_shift != 0.0 {
+        color = max(rotate_hue(color, params.hue_shift), vec3(0.0));
+    }
+    // Keeps overflowing energy from writing infinities into the float texture.
+    color = clamp(color, vec3(0.0), vec3(MAX_COLOR));
+    if params.invert != 0u {
+        color = max(1.0 - color, vec3(0.0));
+    }
+    if sim.film_effects != 0u {
+        color = film_effects(color, invocation_id.xy);
+    }
+    textureStore(dst_image, vec2(invocation_id.x, invocation_id.y), vec4(color, 1.0));
 }
 
 @compute @workgroup_size(16,16,1)
-fn clear(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    let location = vec2<u32>(invocation_id.xy);
-    let locationf = vec2<f32>(location) / 130.0;
-    textureStore(dst_image, location, vec4(vec3(0.0), 1.0));
+fn fade(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if params.fade <= 0.0 || any(invocation_id.xy >= energy_size()) {
+        return;
+    }
+
+    let keep = pow(1.0 - params.fade, sim.frame_dt * sim.speed * 60.0);
+    let pxl_id = 4u * (invocation_id.x + energy_size().x * invocation_id.y);
+    for (var channel = 0u; channel < 4u; channel++) {
+        let energy = f32(atomicLoad(&energy_buffer[pxl_id + channel]));
+        atomicStore(&energy_buffer[pxl_id + channel], u32(energy * keep));
+    }
 }
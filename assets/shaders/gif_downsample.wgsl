@@ -0,0 +1,35 @@
+// Box filters the output image down to the size of a GIF capture and encodes it as 8-bit sRGB, so
+// only the small image has to be read back.
+
+struct Sizes {
+  source: vec2<u32>,
+  output: vec2<u32>,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> pixels: array<u32>;
+@group(0) @binding(2) var<uniform> sizes: Sizes;
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+  let c = clamp(linear, vec3(0.0), vec3(1.0));
+  return select(1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, 12.92 * c, c <= vec3(0.0031308));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+  if any(id.xy >= sizes.output) {
+    return;
+  }
+
+  // Every source pixel ends up in exactly one output pixel.
+  let start = id.xy * sizes.source / sizes.output;
+  let end = max((id.xy + 1u) * sizes.source / sizes.output, start + 1u);
+  var sum = vec3(0.0);
+  for (var y = start.y; y < end.y; y++) {
+    for (var x = start.x; x < end.x; x++) {
+      sum += textureLoad(source, vec2(x, y), 0).rgb;
+    }
+  }
+  let count = f32((end.x - start.x) * (end.y - start.y));
+  pixels[id.x + id.y * sizes.output.x] = pack4x8unorm(vec4(linear_to_srgb(sum / count), 1.0));
+}
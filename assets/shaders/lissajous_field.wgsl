@@ -0,0 +1,9 @@
+// A custom field for the `custom_field` example: the particles circle around a center that
+// traces a Lissajous curve, with the direction of the flow swaying along a second one.
+fn field(pos: vec2<f32>, t: f32) -> vec2<f32> {
+    let center = vec2(sin(3.0 * t), sin(2.0 * t + 1.5708)) * 2.0;
+    let offset = pos - center;
+    let swirl = vec2(-offset.y, offset.x);
+    let sway = vec2(cos(5.0 * pos.y + t), sin(4.0 * pos.x - t));
+    return normalize(swirl + 0.5 * sway + vec2(1e-6, 0.0));
+}
@@ -0,0 +1,23 @@
+//! A field formula of our own instead of the noise: `assets/shaders/lissajous_field.wgsl` swirls
+//! the particles around a center tracing a Lissajous curve. Edit the shader while the example runs
+//! to see the flow change.
+
+use bevy::prelude::*;
+use creative_coding::{ComputePlugin, CustomField};
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins.set(AssetPlugin::default().watch_for_changes()))
+        .add_plugins(ComputePlugin::default().with_sprite())
+        .add_systems(Startup, setup)
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut custom_field: ResMut<CustomField>,
+) {
+    commands.spawn(Camera2dBundle::default());
+    custom_field.0 = Some(asset_server.load("shaders/lissajous_field.wgsl"));
+}
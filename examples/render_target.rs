@@ -0,0 +1,75 @@
+//! The flow field as the texture of a spinning quad in a 3D scene, rendered at 1024x1024 whatever
+//! the size of the window.
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+};
+use creative_coding::{ComputePlugin, OutputFormat};
+
+const TARGET_SIZE: u32 = 1024;
+
+const TARGET: HandleUntyped = HandleUntyped::weak_from_u64(Image::TYPE_UUID, 0x5f3a_07c9_b21e_d684);
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(
+            ComputePlugin::default()
+                .with_output_format(OutputFormat::Rgba8Unorm)
+                .with_target(TARGET.typed()),
+        )
+        .add_systems(Startup, setup)
+        .add_systems(Update, spin)
+        .run();
+}
+
+#[derive(Component)]
+struct Spin;
+
+fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: TARGET_SIZE,
+            height: TARGET_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8Unorm,
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC;
+    images.set_untracked(TARGET, image);
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(shape::Quad::new(Vec2::splat(2.0)).into()),
+            material: materials.add(StandardMaterial {
+                base_color_texture: Some(TARGET.typed()),
+                unlit: true,
+                double_sided: true,
+                cull_mode: None,
+                ..default()
+            }),
+            ..default()
+        },
+        Spin,
+    ));
+    commands.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(0.0, 0.5, 3.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
+
+fn spin(time: Res<Time>, mut quads: Query<&mut Transform, With<Spin>>) {
+    for mut transform in &mut quads {
+        transform.rotation = Quat::from_rotation_y(0.4 * time.elapsed_seconds().sin());
+    }
+}
@@ -0,0 +1,59 @@
+//! Two independently simulated flow fields, each with its own seed and look.
+
+use bevy::prelude::*;
+use creative_coding::{
+    ComputeInput, ComputePlugin, FlowFieldBundle, FlowFieldParams, FlowFieldSettings,
+    PrimaryFlowField,
+};
+
+const FIELD_SIZE: (u32, u32) = (640, 720);
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins.set(AssetPlugin::default().watch_for_changes()))
+        .add_plugins(
+            ComputePlugin::default()
+                .with_size(FIELD_SIZE.0, FIELD_SIZE.1)
+                .with_seed(1),
+        )
+        .add_systems(Startup, setup)
+        .add_systems(Update, spawn_sprites)
+        .run();
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+
+    commands.spawn(FlowFieldBundle {
+        settings: FlowFieldSettings {
+            seed: Some(2),
+            ..FlowFieldSettings::new(FIELD_SIZE.0, FIELD_SIZE.1)
+        },
+        params: FlowFieldParams {
+            noise_scale: 140.0,
+            trail_color: Color::rgb(1.0, 0.6, 0.3),
+            ..default()
+        },
+    });
+}
+
+fn spawn_sprites(
+    mut commands: Commands,
+    fields: Query<
+        (&FlowFieldSettings, &ComputeInput, Option<&PrimaryFlowField>),
+        Added<ComputeInput>,
+    >,
+) {
+    for (settings, input, primary) in &fields {
+        let offset = if primary.is_some() { -0.5 } else { 0.5 } * settings.width as f32;
+        commands.spawn(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(settings.width as f32, settings.height as f32)),
+                ..default()
+            },
+            texture: input.dst_image.clone(),
+            transform: Transform::from_xyz(offset, 0.0, 0.0),
+            ..default()
+        });
+    }
+}
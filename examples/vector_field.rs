@@ -0,0 +1,30 @@
+//! A circular vector field built on the CPU. With `y` pointing down, the particles should circle
+//! the center clockwise on screen.
+
+use bevy::prelude::*;
+use creative_coding::{ComputePlugin, VectorField};
+
+const GRID_SIZE: (u32, u32) = (64, 36);
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins.set(AssetPlugin::default().watch_for_changes()))
+        .add_plugins(ComputePlugin::default().with_sprite())
+        .insert_resource(circular_field())
+        .add_systems(Startup, setup)
+        .run();
+}
+
+fn circular_field() -> VectorField {
+    let (width, height) = GRID_SIZE;
+    let center = Vec2::new(width as f32, height as f32) / 2.0;
+    let data = (0..height)
+        .flat_map(|y| (0..width).map(move |x| Vec2::new(x as f32 + 0.5, y as f32 + 0.5)))
+        .map(|cell| (cell - center).perp().normalize_or_zero())
+        .collect();
+    VectorField::new(width, height, data)
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+}
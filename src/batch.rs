@@ -0,0 +1,174 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        renderer::{RenderDevice, RenderQueue},
+        texture::GpuImage,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    exr,
+    plot::{self, Plotter},
+    poll,
+    screenshot::Readback,
+    ComputeInput, FlowFieldOutput, FlowFieldSettings, ParticleBuffer, PlotStyle, SimulatedFrames,
+    Trajectories,
+};
+
+/// Frames between two progress messages.
+const PROGRESS_INTERVAL: u64 = 500;
+
+/// Outcome of a [`BatchRenderPlugin`], shared with the app so it can be checked after
+/// [`App::run`] has returned. `None` until the image has been saved or saving failed.
+#[derive(Resource, Clone, Default)]
+pub struct BatchStatus(Arc<Mutex<Option<Result<(), String>>>>);
+
+impl BatchStatus {
+    pub fn result(&self) -> Option<Result<(), String>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn finish(&self, result: Result<(), String>) {
+        *self.0.lock().unwrap() = Some(result);
+    }
+}
+
+/// Simulates the flow fields for `frames` frames, saves the results and exits the app: the
+/// [`FlowFieldOutput`] as a PNG to `out`, the raw energies as OpenEXR to `exr`, see
+/// [`EnergyExportRequest`](crate::EnergyExportRequest), and the paths of the particles as an SVG
+/// to `plot`, see [`Trajectories`], each if set. Frames only count once the compute
+/// pipelines have compiled, so the result doesn't depend on how long that takes. Works with or
+/// without a window.
+pub struct BatchRenderPlugin {
+    pub frames: u64,
+    pub out: Option<PathBuf>,
+    pub exr: Option<PathBuf>,
+    pub plot: Option<PathBuf>,
+    pub plot_style: PlotStyle,
+    pub status: BatchStatus,
+}
+
+/// Render world copy of the [`BatchRenderPlugin`] settings.
+#[derive(Resource)]
+struct Batch {
+    frames: u64,
+    out: Option<PathBuf>,
+    exr: Option<PathBuf>,
+    plot: Option<(PathBuf, PlotStyle)>,
+    status: BatchStatus,
+}
+
+impl Plugin for BatchRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.status.clone());
+        app.add_systems(Last, exit_when_done);
+        if self.plot.is_some() {
+            // Recorded from the first frame on.
+            app.world
+                .get_resource_or_insert_with(Trajectories::default)
+                .start();
+        }
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.insert_resource(Batch {
+            frames: self.frames,
+            out: self.out.clone(),
+            exr: self.exr.clone(),
+            plot: self
+                .plot
+                .clone()
+                .map(|path| (path, self.plot_style.clone())),
+            status: self.status.clone(),
+        });
+        // After the frame has been submitted.
+        render_app.add_systems(
+            Render,
+            save_batch
+                // The fields are only readable until bevy clears the render entities.
+                .before(World::clear_entities)
+                .after(plot::record_trajectories)
+                .in_set(RenderSet::Cleanup),
+        );
+    }
+}
+
+fn exit_when_done(status: Res<BatchStatus>, mut exit: EventWriter<AppExit>) {
+    if status.result().is_some() {
+        exit.send(AppExit);
+    }
+}
+
+/// Reports progress and, once enough frames have been simulated, reads the results back and saves
+/// them. Blocks the renderer, which is fine as this is the last frame.
+#[allow(clippy::too_many_arguments)]
+fn save_batch(
+    mut reported: Local<u64>,
+    batch: Res<Batch>,
+    frames: Res<SimulatedFrames>,
+    output: Option<Res<FlowFieldOutput>>,
+    fields: Query<(&FlowFieldSettings, &ParticleBuffer, &ComputeInput)>,
+    gpu_images: Res<RenderAssets<Image>>,
+    mut plotter: ResMut<Plotter>,
+    trajectories: Res<Trajectories>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if batch.status.result().is_some() {
+        return;
+    }
+    let frame = frames.0.min(batch.frames);
+    if frame > *reported && (frame.is_multiple_of(PROGRESS_INTERVAL) || frame == batch.frames) {
+        info!("simulated {frame} / {} frames", batch.frames);
+        *reported = frame;
+    }
+    if frame < batch.frames {
+        return;
+    }
+
+    let mut save = || -> Result<(), String> {
+        if let Some(path) = &batch.out {
+            let image = output.as_ref().and_then(|output| gpu_images.get(&output.0));
+            let image = image.ok_or("the flow field output doesn't exist")?;
+            save_image(image, path, &render_device, &render_queue)?;
+            info!("saved {}", path.display());
+        }
+        if let Some(path) = &batch.exr {
+            let field = exr::output_field(output.as_deref(), &fields);
+            let (settings, buffers) = field.ok_or("the flow field output has no energies")?;
+            exr::save_energies(settings, buffers, path, &render_device, &render_queue)?;
+            info!("saved {}", path.display());
+        }
+        if let Some((path, style)) = &batch.plot {
+            plotter.flush(&trajectories, &render_queue);
+            trajectories.stop();
+            trajectories
+                .write_svg(path, style)
+                .map_err(|err| err.to_string())?;
+            info!("saved {} paths to {}", trajectories.len(), path.display());
+        }
+        Ok(())
+    };
+    batch.status.finish(save());
+}
+
+fn save_image(
+    image: &GpuImage,
+    path: &Path,
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+) -> Result<(), String> {
+    let readback = Readback::new(image);
+    let staging = readback.create_buffer(render_device, "flow field batch readback");
+    readback.copy(image, &staging, render_device, render_queue);
+    let mapped = poll::map_read(render_device, &staging);
+    poll::wait_for_map(render_queue, &mapped).expect("the batch buffer was dropped")?;
+    readback.save_png(&staging, path)
+}
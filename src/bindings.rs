@@ -0,0 +1,315 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A key together with the modifiers held with it. Only matches while exactly these modifiers are
+/// held, so `Ctrl` + `S` doesn't also trigger `S`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+}
+
+impl KeyBinding {
+    pub const fn key(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    pub const fn ctrl(key: KeyCode) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::key(key)
+        }
+    }
+
+    pub const fn shift(key: KeyCode) -> Self {
+        Self {
+            shift: true,
+            ..Self::key(key)
+        }
+    }
+
+    pub fn just_pressed(&self, keys: &Input<KeyCode>) -> bool {
+        keys.just_pressed(self.key)
+            && self.ctrl == keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+            && self.shift == keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        let key = format!("{:?}", self.key);
+        // `Key1` is just `1`.
+        match key.strip_prefix("Key") {
+            Some(digit) if digit.len() == 1 => write!(f, "{digit}"),
+            _ => write!(f, "{key}"),
+        }
+    }
+}
+
+/// The keys of every action of the [`FlowFieldControlsPlugin`](crate::FlowFieldControlsPlugin)
+/// and the other interactive plugins, read from the `bindings` of the
+/// [`FlowFieldConfig`](crate::FlowFieldConfig). Actions missing from the config keep their default
+/// key.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputBindings {
+    /// Selects the matching entry in the [`Presets`](crate::Presets).
+    pub presets: [KeyBinding; 9],
+    /// Restarts from the matching [`SeedVariation`](crate::SeedVariation).
+    pub variations: [KeyBinding; 9],
+    pub pause: KeyBinding,
+    pub step: KeyBinding,
+    pub fewer_substeps: KeyBinding,
+    pub more_substeps: KeyBinding,
+    pub lower_exposure: KeyBinding,
+    pub raise_exposure: KeyBinding,
+    pub lower_gamma: KeyBinding,
+    pub raise_gamma: KeyBinding,
+    pub reset: KeyBinding,
+    pub speed_up: KeyBinding,
+    pub slow_down: KeyBinding,
+    pub wind_left: KeyBinding,
+    pub wind_right: KeyBinding,
+    pub wind_up: KeyBinding,
+    pub wind_down: KeyBinding,
+    pub remove_octave: KeyBinding,
+    pub add_octave: KeyBinding,
+    pub shrink_noise: KeyBinding,
+    pub grow_noise: KeyBinding,
+    pub less_drag: KeyBinding,
+    pub more_drag: KeyBinding,
+    pub slower_fade: KeyBinding,
+    pub faster_fade: KeyBinding,
+    pub toggle_gravity: KeyBinding,
+    pub cycle_boundary: KeyBinding,
+    pub cycle_field_type: KeyBinding,
+    pub cycle_color_mode: KeyBinding,
+    pub toggle_vsync: KeyBinding,
+    pub swap_speed_colors: KeyBinding,
+    pub invert: KeyBinding,
+    pub toggle_film_effects: KeyBinding,
+    pub cycle_display_mode: KeyBinding,
+    pub cycle_tonemap: KeyBinding,
+    pub cycle_palette: KeyBinding,
+    pub shuffle_params: KeyBinding,
+    pub previous_params: KeyBinding,
+    /// Only used by the [`AutoDriftPlugin`](crate::AutoDriftPlugin).
+    pub toggle_drift: KeyBinding,
+    pub reset_view: KeyBinding,
+    pub clear_painting: KeyBinding,
+    pub add_obstacle: KeyBinding,
+    pub toggle_recording: KeyBinding,
+    pub toggle_plotting: KeyBinding,
+    pub toggle_fullscreen: KeyBinding,
+    pub screenshot: KeyBinding,
+    pub export_energies: KeyBinding,
+    pub capture_gif: KeyBinding,
+    pub save_snapshot: KeyBinding,
+    pub load_snapshot: KeyBinding,
+    pub toggle_stats: KeyBinding,
+    pub toggle_magnifier: KeyBinding,
+    pub toggle_panel: KeyBinding,
+    pub toggle_help: KeyBinding,
+    /// Only used by the [`KioskPlugin`](crate::KioskPlugin).
+    pub exit: KeyBinding,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            presets: [
+                KeyCode::Key1,
+                KeyCode::Key2,
+                KeyCode::Key3,
+                KeyCode::Key4,
+                KeyCode::Key5,
+                KeyCode::Key6,
+                KeyCode::Key7,
+                KeyCode::Key8,
+                KeyCode::Key9,
+            ]
+            .map(KeyBinding::key),
+            variations: [
+                KeyCode::Key1,
+                KeyCode::Key2,
+                KeyCode::Key3,
+                KeyCode::Key4,
+                KeyCode::Key5,
+                KeyCode::Key6,
+                KeyCode::Key7,
+                KeyCode::Key8,
+                KeyCode::Key9,
+            ]
+            .map(KeyBinding::ctrl),
+            pause: KeyBinding::key(KeyCode::Space),
+            step: KeyBinding::key(KeyCode::Period),
+            fewer_substeps: KeyBinding::key(KeyCode::BracketLeft),
+            more_substeps: KeyBinding::key(KeyCode::BracketRight),
+            lower_exposure: KeyBinding::shift(KeyCode::BracketLeft),
+            raise_exposure: KeyBinding::shift(KeyCode::BracketRight),
+            lower_gamma: KeyBinding::ctrl(KeyCode::BracketLeft),
+            raise_gamma: KeyBinding::ctrl(KeyCode::BracketRight),
+            reset: KeyBinding::key(KeyCode::R),
+            speed_up: KeyBinding::key(KeyCode::Up),
+            slow_down: KeyBinding::key(KeyCode::Down),
+            wind_left: KeyBinding::shift(KeyCode::Left),
+            wind_right: KeyBinding::shift(KeyCode::Right),
+            wind_up: KeyBinding::shift(KeyCode::Up),
+            wind_down: KeyBinding::shift(KeyCode::Down),
+            remove_octave: KeyBinding::key(KeyCode::O),
+            add_octave: KeyBinding::key(KeyCode::P),
+            shrink_noise: KeyBinding::key(KeyCode::N),
+            grow_noise: KeyBinding::key(KeyCode::M),
+            less_drag: KeyBinding::key(KeyCode::K),
+            more_drag: KeyBinding::key(KeyCode::L),
+            slower_fade: KeyBinding::key(KeyCode::F),
+            faster_fade: KeyBinding::key(KeyCode::H),
+            toggle_gravity: KeyBinding::key(KeyCode::G),
+            cycle_boundary: KeyBinding::key(KeyCode::B),
+            cycle_field_type: KeyBinding::key(KeyCode::C),
+            cycle_color_mode: KeyBinding::key(KeyCode::V),
+            toggle_vsync: KeyBinding::ctrl(KeyCode::V),
+            swap_speed_colors: KeyBinding::key(KeyCode::Z),
+            invert: KeyBinding::key(KeyCode::I),
+            toggle_film_effects: KeyBinding::key(KeyCode::A),
+            cycle_display_mode: KeyBinding::key(KeyCode::D),
+            cycle_tonemap: KeyBinding::key(KeyCode::U),
+            cycle_palette: KeyBinding::key(KeyCode::T),
+            shuffle_params: KeyBinding::key(KeyCode::J),
+            previous_params: KeyBinding::shift(KeyCode::J),
+            toggle_drift: KeyBinding::key(KeyCode::W),
+            reset_view: KeyBinding::key(KeyCode::Home),
+            clear_painting: KeyBinding::key(KeyCode::E),
+            add_obstacle: KeyBinding::key(KeyCode::X),
+            toggle_recording: KeyBinding::key(KeyCode::F9),
+            toggle_plotting: KeyBinding::key(KeyCode::F10),
+            toggle_fullscreen: KeyBinding::key(KeyCode::F11),
+            screenshot: KeyBinding::key(KeyCode::F12),
+            export_energies: KeyBinding::shift(KeyCode::F12),
+            capture_gif: KeyBinding::ctrl(KeyCode::F12),
+            save_snapshot: KeyBinding::ctrl(KeyCode::S),
+            load_snapshot: KeyBinding::ctrl(KeyCode::L),
+            toggle_stats: KeyBinding::key(KeyCode::F3),
+            toggle_magnifier: KeyBinding::shift(KeyCode::Tab),
+            toggle_panel: KeyBinding::key(KeyCode::Tab),
+            toggle_help: KeyBinding::key(KeyCode::F1),
+            exit: KeyBinding {
+                key: KeyCode::Q,
+                ctrl: true,
+                shift: true,
+            },
+        }
+    }
+}
+
+impl InputBindings {
+    /// Every action with its binding.
+    pub fn actions(&self) -> Vec<(String, KeyBinding)> {
+        let mut actions: Vec<(String, KeyBinding)> = self
+            .presets
+            .iter()
+            .enumerate()
+            .map(|(index, binding)| (format!("preset {}", index + 1), *binding))
+            .chain(
+                self.variations
+                    .iter()
+                    .enumerate()
+                    .map(|(index, binding)| (format!("variation {}", index + 1), *binding)),
+            )
+            .collect();
+        actions.extend(
+            [
+                ("pause", self.pause),
+                ("step", self.step),
+                ("fewer_substeps", self.fewer_substeps),
+                ("more_substeps", self.more_substeps),
+                ("lower_exposure", self.lower_exposure),
+                ("raise_exposure", self.raise_exposure),
+                ("lower_gamma", self.lower_gamma),
+                ("raise_gamma", self.raise_gamma),
+                ("reset", self.reset),
+                ("speed_up", self.speed_up),
+                ("slow_down", self.slow_down),
+                ("wind_left", self.wind_left),
+                ("wind_right", self.wind_right),
+                ("wind_up", self.wind_up),
+                ("wind_down", self.wind_down),
+                ("remove_octave", self.remove_octave),
+                ("add_octave", self.add_octave),
+                ("shrink_noise", self.shrink_noise),
+                ("grow_noise", self.grow_noise),
+                ("less_drag", self.less_drag),
+                ("more_drag", self.more_drag),
+                ("slower_fade", self.slower_fade),
+                ("faster_fade", self.faster_fade),
+                ("toggle_gravity", self.toggle_gravity),
+                ("cycle_boundary", self.cycle_boundary),
+                ("cycle_field_type", self.cycle_field_type),
+                ("cycle_color_mode", self.cycle_color_mode),
+                ("toggle_vsync", self.toggle_vsync),
+                ("swap_speed_colors", self.swap_speed_colors),
+                ("invert", self.invert),
+                ("toggle_film_effects", self.toggle_film_effects),
+                ("cycle_display_mode", self.cycle_display_mode),
+                ("cycle_tonemap", self.cycle_tonemap),
+                ("cycle_palette", self.cycle_palette),
+                ("shuffle_params", self.shuffle_params),
+                ("previous_params", self.previous_params),
+                ("toggle_drift", self.toggle_drift),
+                ("reset_view", self.reset_view),
+                ("clear_painting", self.clear_painting),
+                ("add_obstacle", self.add_obstacle),
+                ("toggle_recording", self.toggle_recording),
+                ("toggle_plotting", self.toggle_plotting),
+                ("toggle_fullscreen", self.toggle_fullscreen),
+                ("screenshot", self.screenshot),
+                ("export_energies", self.export_energies),
+                ("capture_gif", self.capture_gif),
+                ("save_snapshot", self.save_snapshot),
+                ("load_snapshot", self.load_snapshot),
+                ("toggle_stats", self.toggle_stats),
+                ("toggle_magnifier", self.toggle_magnifier),
+                ("toggle_panel", self.toggle_panel),
+                ("toggle_help", self.toggle_help),
+                ("exit", self.exit),
+            ]
+            .map(|(name, binding)| (name.to_string(), binding)),
+        );
+        actions
+    }
+
+    /// Pairs of actions bound to the same keys.
+    pub fn conflicts(&self) -> Vec<(String, String)> {
+        let actions = self.actions();
+        let mut conflicts = Vec::new();
+        for (i, (first, binding)) in actions.iter().enumerate() {
+            for (second, other) in &actions[i + 1..] {
+                if binding == other {
+                    conflicts.push((first.clone(), second.clone()));
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+pub(crate) fn warn_conflicting_bindings(bindings: Res<InputBindings>) {
+    for (first, second) in bindings.conflicts() {
+        warn!("`{first}` and `{second}` are bound to the same keys");
+    }
+}
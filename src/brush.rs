@@ -0,0 +1,167 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    pointer::{self, FieldSprites},
+    ComputeInput, FlowFieldSettings, InputBindings, PrimaryFlowField, VectorField,
+};
+
+/// Pixels of the flow field per cell of the [`VectorField`] created for painting.
+const CELL_SIZE: u32 = 4;
+const RADIUS_STEP: f32 = 1.1;
+const STRENGTH_STEP: f32 = 0.05;
+
+/// Paints directions into the [`VectorField`] while dragging with `Shift` and the left mouse
+/// button held. A field the size of the [`PrimaryFlowField`] is created on the first stroke if
+/// there is none yet.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Brush {
+    /// Radius in pixels of the flow field.
+    pub radius: f32,
+    /// How far each splat moves the painted vectors towards the stroke direction, in `0.0..=1.0`.
+    pub strength: f32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            radius: 40.0,
+            strength: 0.5,
+        }
+    }
+}
+
+impl Brush {
+    pub const MIN_RADIUS: f32 = 2.0;
+    pub const MAX_RADIUS: f32 = 400.0;
+}
+
+/// Whether the keys held turn the left mouse button into the brush.
+pub(crate) fn painting(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+}
+
+/// Lines scrolled since the last frame. Both axes count, as some platforms turn vertical into
+/// horizontal scrolling while `Shift` is held.
+pub(crate) fn scroll_steps(wheel: &mut EventReader<MouseWheel>) -> f32 {
+    wheel
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y + event.x,
+            MouseScrollUnit::Pixel => (event.y + event.x) / 100.0,
+        })
+        .sum()
+}
+
+/// Scrolling while painting changes the radius, or the strength while `Ctrl` is held as well.
+pub(crate) fn adjust_brush(
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut brush: ResMut<Brush>,
+) {
+    let steps = scroll_steps(&mut wheel);
+    if steps == 0.0 || !painting(&keys) {
+        return;
+    }
+
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        brush.strength = (brush.strength + steps * STRENGTH_STEP).clamp(STRENGTH_STEP, 1.0);
+        info!("brush strength: {:.2}", brush.strength);
+    } else {
+        brush.radius =
+            (brush.radius * RADIUS_STEP.powf(steps)).clamp(Brush::MIN_RADIUS, Brush::MAX_RADIUS);
+        info!("brush radius: {:.0}", brush.radius);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn paint(
+    mut commands: Commands,
+    mut last_position: Local<Option<Vec2>>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    brush: Res<Brush>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    sprites: FieldSprites,
+    field: Query<(&FlowFieldSettings, &ComputeInput), With<PrimaryFlowField>>,
+    vector_field: Option<ResMut<VectorField>>,
+) {
+    let Ok((settings, input)) = field.get_single() else {
+        return;
+    };
+    let position = match (
+        pointer::cursor_world_position(&windows, &cameras),
+        pointer::field_sprite(&sprites, input),
+    ) {
+        (Some(cursor), Some((sprite, transform)))
+            if painting(&keys) && mouse.pressed(MouseButton::Left) =>
+        {
+            pointer::world_to_field(cursor, settings, sprite, transform)
+        }
+        _ => {
+            *last_position = None;
+            return;
+        }
+    };
+    let Some(last) = last_position.replace(position) else {
+        return;
+    };
+    let stroke = position - last;
+    if stroke.length_squared() < 1e-3 {
+        return;
+    }
+
+    let Some(mut vector_field) = vector_field else {
+        let (width, height) = (
+            settings.width.div_ceil(CELL_SIZE),
+            settings.height.div_ceil(CELL_SIZE),
+        );
+        let mut vector_field =
+            VectorField::new(width, height, vec![Vec2::ZERO; (width * height) as usize]);
+        splat_stroke(&mut vector_field, settings, *brush, last, position);
+        commands.insert_resource(vector_field);
+        return;
+    };
+    splat_stroke(&mut vector_field, settings, *brush, last, position);
+}
+
+/// Splats along the stroke closely enough that fast drags don't leave gaps.
+fn splat_stroke(
+    vector_field: &mut VectorField,
+    settings: &FlowFieldSettings,
+    brush: Brush,
+    from: Vec2,
+    to: Vec2,
+) {
+    let to_cells = Vec2::new(
+        vector_field.width() as f32 / settings.width as f32,
+        vector_field.height() as f32 / settings.height as f32,
+    );
+    let direction = (to - from).normalize();
+    let radius = brush.radius * to_cells.x.max(to_cells.y);
+    let splats = ((to - from).length() / (brush.radius * 0.25))
+        .ceil()
+        .max(1.0) as u32;
+    for i in 1..=splats {
+        let center = from.lerp(to, i as f32 / splats as f32) * to_cells;
+        vector_field.splat(center, radius, direction, brush.strength);
+    }
+}
+
+pub(crate) fn clear_painting(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    vector_field: Option<ResMut<VectorField>>,
+) {
+    if !bindings.clear_painting.just_pressed(&keys) {
+        return;
+    }
+    if let Some(mut vector_field) = vector_field {
+        vector_field.clear();
+        info!("cleared the painted field");
+    }
+}
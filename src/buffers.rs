@@ -0,0 +1,508 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_component::ExtractComponent,
+        render_resource::{
+            Buffer, BufferDescriptor, BufferInitDescriptor, BufferUsages, Extent3d, ShaderType,
+            TextureDimension, TextureUsages,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::TextureFormatPixelInfo,
+    },
+};
+
+use crate::{
+    particle::initial_particles,
+    target::{fit_target, validate_target},
+    FlowFieldOutput, FlowFieldParams, FlowFieldSettings, FlowFieldSpecies, FlowFieldTarget,
+    MemoryBudget, OutputFormat, ParamInterpolation, Particle, PrimaryFlowField, ResetSimulation,
+    Seed, WorkgroupSize,
+};
+
+#[derive(Component, Clone, ExtractComponent)]
+pub struct ComputeInput {
+    pub dst_image: Handle<Image>,
+}
+
+#[derive(Clone, Component, ExtractComponent)]
+pub struct ParticleBuffer {
+    pub particles: Buffer,
+    /// Four `u32` fixed point counters (RGBA, 1/256th units) per pixel, accumulated atomically.
+    pub energies: Buffer,
+    pub species: Buffer,
+    /// Start of every cell of the neighbor grid in `sorted_particles`, followed by the number of
+    /// particles.
+    pub grid_cells: Buffer,
+    /// Particle positions and velocities ordered by their cell in the neighbor grid.
+    pub sorted_particles: Buffer,
+    /// The peak energy of any pixel followed by a histogram of the energies, for the
+    /// [`DisplayMode`](crate::DisplayMode)s that normalize the image.
+    pub display_stats: Buffer,
+    /// Scratch space for the glow blur: the horizontally blurred energies followed by the fully
+    /// blurred ones, as four `f32`s per pixel. Only allocated while the glow is enabled.
+    pub glow: Buffer,
+    /// The bright parts of the image at half and then quarter resolution, each level followed by
+    /// scratch space for its blur, as four `f32`s per pixel. Only allocated while the bloom is
+    /// enabled.
+    pub bloom: Buffer,
+}
+
+impl ParticleBuffer {
+    fn total_size(&self) -> u64 {
+        [
+            &self.particles,
+            &self.energies,
+            &self.species,
+            &self.grid_cells,
+            &self.sorted_particles,
+            &self.display_stats,
+            &self.glow,
+            &self.bloom,
+        ]
+        .iter()
+        .map(|buffer| buffer.size())
+        .sum()
+    }
+}
+
+/// Read-only summary of the [`ParticleBuffer`] of a flow field for inspecting it through
+/// reflection, kept up to date whenever the buffers are recreated.
+#[derive(Component, Clone, Copy, Default, Debug, Reflect)]
+#[reflect(Component)]
+pub struct ParticleBufferInfo {
+    pub particles: u32,
+    /// Size in bytes of the trail energies.
+    pub energies_bytes: u64,
+    /// Size in bytes of all buffers together.
+    pub total_bytes: u64,
+}
+
+pub(crate) fn update_buffer_info(
+    mut commands: Commands,
+    fields: Query<(Entity, &FlowFieldSettings, &ParticleBuffer), Changed<ParticleBuffer>>,
+) {
+    for (entity, settings, buffers) in &fields {
+        commands.entity(entity).insert(ParticleBufferInfo {
+            particles: settings.nr_particles,
+            energies_bytes: buffers.energies.size(),
+            total_bytes: buffers.total_size(),
+        });
+    }
+}
+
+/// Must match `HISTOGRAM_BINS` in the shader.
+const HISTOGRAM_BINS: u64 = 256;
+
+fn create_display_stats(render_device: &RenderDevice) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("flow field display stats"),
+        size: 4 * (1 + HISTOGRAM_BINS),
+        // Cleared before every measurement.
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn glow_size(settings: &FlowFieldSettings, params: &FlowFieldParams) -> u64 {
+    if params.glow_strength > 0.0 {
+        2 * Vec4::min_size().get() * settings.width as u64 * settings.height as u64
+    } else {
+        Vec4::min_size().get()
+    }
+}
+
+fn create_glow(
+    settings: &FlowFieldSettings,
+    params: &FlowFieldParams,
+    render_device: &RenderDevice,
+) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("flow field glow"),
+        size: glow_size(settings, params),
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+fn bloom_size(settings: &FlowFieldSettings, params: &FlowFieldParams) -> u64 {
+    if !params.bloom_enabled {
+        return Vec4::min_size().get();
+    }
+    let half = UVec2::new(settings.width.div_ceil(2), settings.height.div_ceil(2));
+    let quarter = UVec2::new(half.x.div_ceil(2), half.y.div_ceil(2));
+    let pixels = half.x as u64 * half.y as u64 + quarter.x as u64 * quarter.y as u64;
+    2 * Vec4::min_size().get() * pixels
+}
+
+fn create_bloom(
+    settings: &FlowFieldSettings,
+    params: &FlowFieldParams,
+    render_device: &RenderDevice,
+) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("flow field bloom"),
+        size: bloom_size(settings, params),
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+/// Must match `NEIGHBOR_CELL_SIZE` in the shader.
+pub(crate) const NEIGHBOR_CELL_SIZE: u32 = 8;
+
+fn create_grid_cells(settings: &FlowFieldSettings, render_device: &RenderDevice) -> Buffer {
+    let cells = settings.width.div_ceil(NEIGHBOR_CELL_SIZE) as u64
+        * settings.height.div_ceil(NEIGHBOR_CELL_SIZE) as u64;
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("flow field grid cells"),
+        size: 4 * (cells + 1),
+        // Cleared before the particles are binned.
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_sorted_particles(settings: &FlowFieldSettings, render_device: &RenderDevice) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("flow field sorted particles"),
+        size: Vec4::min_size().get() * settings.nr_particles.max(1) as u64,
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+type UninitializedFlowField<'a> = (
+    Entity,
+    &'a mut FlowFieldSettings,
+    Option<&'a FlowFieldParams>,
+    Option<&'a ParamInterpolation>,
+    Option<&'a FlowFieldSpecies>,
+    Option<&'a PrimaryFlowField>,
+    Option<&'a FlowFieldTarget>,
+);
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn init_flow_fields(
+    mut commands: Commands,
+    mut fields: Query<UninitializedFlowField, Without<ComputeInput>>,
+    seed: Res<Seed>,
+    output_format: Res<OutputFormat>,
+    workgroup_size: Res<WorkgroupSize>,
+    memory_budget: Res<MemoryBudget>,
+    render_device: Res<RenderDevice>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (entity, mut settings, params, interpolation, species, primary, target) in &mut fields {
+        let target = match target {
+            Some(target) => {
+                let Some(image) = images.get(&target.0) else {
+                    continue;
+                };
+                validate_target(entity, image, *output_format);
+                fit_target(&mut settings, image);
+                Some(target.0.clone())
+            }
+            None => None,
+        };
+        settings.validate();
+        fit_device(
+            entity,
+            &mut settings,
+            target.is_some(),
+            *workgroup_size,
+            &render_device,
+        );
+        let settings = &*settings;
+        check_memory_budget(entity, settings, *output_format, *memory_budget);
+        let seed = seed.for_field(entity, settings);
+        let species = species.cloned().unwrap_or_default();
+        let image =
+            target.unwrap_or_else(|| create_output_image(settings, *output_format, &mut images));
+        let (input, particles) = create_flow_field(
+            settings,
+            &params.copied().unwrap_or_default(),
+            &species,
+            seed,
+            image,
+            &render_device,
+        );
+        if primary.is_some() {
+            commands.insert_resource(FlowFieldOutput(input.dst_image.clone()));
+        }
+
+        let mut entity = commands.entity(entity);
+        entity.insert((input, particles));
+        if params.is_none() {
+            entity.insert(FlowFieldParams::default());
+        }
+        if interpolation.is_none() {
+            entity.insert(ParamInterpolation::default());
+        }
+        entity.insert(species);
+    }
+}
+
+/// Shrinks the settings of a flow field that the GPU can't hold, rather than failing validation.
+/// The size of a flow field with a [`FlowFieldTarget`] is that of an image it doesn't own, so it
+/// panics instead of shrinking that.
+fn fit_device(
+    entity: Entity,
+    settings: &mut Mut<FlowFieldSettings>,
+    target: bool,
+    workgroup_size: WorkgroupSize,
+    render_device: &RenderDevice,
+) {
+    if let Some(fitted) = settings.fit_device(render_device, workgroup_size) {
+        assert!(
+            !target || (fitted.width, fitted.height) == (settings.width, settings.height),
+            "flow field {entity:?} target of {}x{} exceeds the limits of the GPU, which fit at \
+             most {}x{}",
+            settings.width,
+            settings.height,
+            fitted.width,
+            fitted.height
+        );
+        warn!(
+            "flow field {entity:?} exceeds the limits of the GPU, reduced from {} to {}",
+            settings.describe(),
+            fitted.describe()
+        );
+        **settings = fitted;
+    }
+}
+
+fn check_memory_budget(
+    entity: Entity,
+    settings: &FlowFieldSettings,
+    output_format: OutputFormat,
+    memory_budget: MemoryBudget,
+) {
+    let memory = settings.estimated_memory(output_format);
+    if memory > memory_budget.0 {
+        warn!(
+            "flow field {entity:?} needs about {} MB of GPU memory, over the budget of {} MB",
+            memory >> 20,
+            memory_budget.0 >> 20
+        );
+    }
+}
+
+fn create_output_image(
+    settings: &FlowFieldSettings,
+    output_format: OutputFormat,
+    images: &mut Assets<Image>,
+) -> Handle<Image> {
+    let format = output_format.texture_format();
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: settings.width,
+            height: settings.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &vec![0; format.pixel_size()],
+        format,
+    );
+
+    image.texture_descriptor.label = Some("flow field output");
+    // Copied from for screenshots.
+    image.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC;
+
+    images.add(image)
+}
+
+fn create_flow_field(
+    settings: &FlowFieldSettings,
+    params: &FlowFieldParams,
+    species: &FlowFieldSpecies,
+    seed: u64,
+    image: Handle<Image>,
+    render_device: &RenderDevice,
+) -> (ComputeInput, ParticleBuffer) {
+    (
+        ComputeInput { dst_image: image },
+        ParticleBuffer {
+            particles: create_particles(settings, params, species, seed, render_device),
+            energies: create_energies(settings, render_device),
+            species: species.create_buffer(render_device),
+            grid_cells: create_grid_cells(settings, render_device),
+            display_stats: create_display_stats(render_device),
+            sorted_particles: create_sorted_particles(settings, render_device),
+            glow: create_glow(settings, params, render_device),
+            bloom: create_bloom(settings, params, render_device),
+        },
+    )
+}
+
+fn energies_size(settings: &FlowFieldSettings) -> u64 {
+    // One RGBA set of fixed point counters per accumulated pixel.
+    4 * 4 * settings.width as u64 * settings.height as u64 * settings.supersample.pow(2) as u64
+}
+
+fn create_energies(settings: &FlowFieldSettings, render_device: &RenderDevice) -> Buffer {
+    let energies = render_device.create_buffer(&BufferDescriptor {
+        label: Some("flow field energies"),
+        size: energies_size(settings),
+        // Copied from for EXR exports.
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        mapped_at_creation: true,
+    });
+    // Zeroed explicitly, as the trails accumulate on whatever the first frame finds here.
+    energies.slice(..).get_mapped_range_mut().fill(0);
+    energies.unmap();
+    energies
+}
+
+fn create_particles(
+    settings: &FlowFieldSettings,
+    params: &FlowFieldParams,
+    species: &FlowFieldSpecies,
+    seed: u64,
+    render_device: &RenderDevice,
+) -> Buffer {
+    render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("flow field particles"),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        contents: &initial_particles(settings, params, species, seed),
+    })
+}
+
+/// Overwrites the existing buffers in place so the bind groups stay valid.
+pub(crate) fn reset_flow_fields(
+    mut events: EventReader<ResetSimulation>,
+    fields: Query<(
+        Entity,
+        &FlowFieldSettings,
+        &FlowFieldParams,
+        &FlowFieldSpecies,
+        &ParticleBuffer,
+    )>,
+    seed: Res<Seed>,
+    render_queue: Res<RenderQueue>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    for (entity, settings, params, species, buffers) in &fields {
+        let seed = seed.for_field(entity, settings);
+        let particles = initial_particles(settings, params, species, seed);
+        render_queue.write_buffer(&buffers.particles, 0, &particles);
+        let zeroes = vec![0; buffers.energies.size() as usize];
+        render_queue.write_buffer(&buffers.energies, 0, &zeroes);
+    }
+}
+
+type ResizedFlowField<'a> = (
+    Entity,
+    &'a mut FlowFieldSettings,
+    &'a FlowFieldParams,
+    &'a FlowFieldSpecies,
+    &'a ComputeInput,
+    &'a mut ParticleBuffer,
+    Option<&'a FlowFieldTarget>,
+);
+
+/// Reallocates the output image and the buffers that depend on the size of a flow field when it
+/// changes. The image is resized in place so the [`FlowFieldOutput`] handle stays valid, unless
+/// it's a [`FlowFieldTarget`] that the field follows, and the particles start over, spread across
+/// the new size.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn resize_flow_fields(
+    mut fields: Query<ResizedFlowField, Changed<FlowFieldSettings>>,
+    seed: Res<Seed>,
+    output_format: Res<OutputFormat>,
+    workgroup_size: Res<WorkgroupSize>,
+    memory_budget: Res<MemoryBudget>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (entity, mut settings, params, species, input, mut buffers, target) in &mut fields {
+        // Checked for every change, as `resize_particles` allocates for the particle count alone.
+        settings.validate();
+        fit_device(
+            entity,
+            &mut settings,
+            target.is_some(),
+            *workgroup_size,
+            &render_device,
+        );
+        let settings = &*settings;
+        check_memory_budget(entity, settings, *output_format, *memory_budget);
+
+        let Some(image) = images.get(&input.dst_image) else {
+            continue;
+        };
+        let size = image.texture_descriptor.size;
+        if size.width == settings.width
+            && size.height == settings.height
+            && buffers.energies.size() == energies_size(settings)
+        {
+            continue;
+        }
+
+        // A target already has the size of the field, see `follow_targets`.
+        if let (None, Some(image)) = (target, images.get_mut(&input.dst_image)) {
+            image.resize(Extent3d {
+                width: settings.width,
+                height: settings.height,
+                depth_or_array_layers: 1,
+            });
+        }
+        buffers.energies = create_energies(settings, &render_device);
+        buffers.grid_cells = create_grid_cells(settings, &render_device);
+        buffers.glow = create_glow(settings, params, &render_device);
+        buffers.bloom = create_bloom(settings, params, &render_device);
+        // A changed particle count is handled by `resize_particles`.
+        if buffers.particles.size() == Particle::min_size().get() * settings.nr_particles as u64 {
+            let seed = seed.for_field(entity, settings);
+            let particles = initial_particles(settings, params, species, seed);
+            render_queue.write_buffer(&buffers.particles, 0, &particles);
+        }
+    }
+}
+
+pub(crate) fn resize_particles(
+    mut fields: Query<
+        (
+            Entity,
+            &FlowFieldSettings,
+            &FlowFieldParams,
+            &FlowFieldSpecies,
+            &mut ParticleBuffer,
+        ),
+        Changed<FlowFieldSettings>,
+    >,
+    seed: Res<Seed>,
+    render_device: Res<RenderDevice>,
+) {
+    for (entity, settings, params, species, mut particles) in &mut fields {
+        let size = Particle::min_size().get() * settings.nr_particles as u64;
+        if particles.particles.size() != size {
+            let seed = seed.for_field(entity, settings);
+            particles.particles = create_particles(settings, params, species, seed, &render_device);
+            particles.sorted_particles = create_sorted_particles(settings, &render_device);
+        }
+    }
+}
+
+/// Allocates the scratch buffers of the post effects when they are enabled and frees them again
+/// when they are disabled.
+pub(crate) fn resize_post_buffers(
+    mut fields: Query<
+        (&FlowFieldSettings, &FlowFieldParams, &mut ParticleBuffer),
+        Changed<FlowFieldParams>,
+    >,
+    render_device: Res<RenderDevice>,
+) {
+    for (settings, params, mut buffers) in &mut fields {
+        if buffers.glow.size() != glow_size(settings, params) {
+            buffers.glow = create_glow(settings, params, &render_device);
+        }
+        if buffers.bloom.size() != bloom_size(settings, params) {
+            buffers.bloom = create_bloom(settings, params, &render_device);
+        }
+    }
+}
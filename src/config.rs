@@ -0,0 +1,284 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::prelude::{Color, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AgeCurve, AutoDrift, BoundaryMode, ColorMode, ComputePlugin, DisplayMode, FieldPrimitive,
+    FieldType, FlowFieldParams, FlowFieldSettings, InitialVelocity, InputBindings,
+    MassDistribution, OutputFormat, ParticleColoring, Tonemap, WindowSizing, WorkgroupSize,
+};
+
+/// Everything needed to start a simulation, as stored in a `.ron` config file. Fields missing from
+/// the file keep their default value.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlowFieldConfig {
+    /// Size of the simulation without a window, with one it is sized from the window instead.
+    pub width: u32,
+    pub height: u32,
+    /// See [`WindowSizing::scale`].
+    pub render_scale: f32,
+    /// See [`WindowSizing::max_dimension`].
+    pub max_dimension: Option<u32>,
+    pub particles: u32,
+    pub workgroup_size: u32,
+    pub output_format: OutputFormat,
+    pub seed: Option<u64>,
+    pub mass: MassDistribution,
+    pub velocity: InitialVelocity,
+    pub coloring: ParticleColoring,
+    pub supersample: u32,
+    pub noise_scale: f32,
+    pub field_evolution_speed: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub noise_mix: f32,
+    pub image_influence: f32,
+    pub vector_field_influence: f32,
+    pub sdf_threshold: f32,
+    pub primitives: Vec<FieldPrimitive>,
+    pub field_type: FieldType,
+    pub curl_frequency: f32,
+    pub curl_epsilon: f32,
+    pub field_rotation: f32,
+    pub noise_offset: Vec2,
+    pub boundary: BoundaryMode,
+    pub particle_lifetime: f32,
+    pub lifetime_variance: f32,
+    pub field_strength: f32,
+    pub drag: f32,
+    pub max_speed: f32,
+    pub gravity: Vec2,
+    pub wind: Vec2,
+    pub repulsion: bool,
+    pub repulsion_radius: f32,
+    pub repulsion_strength: f32,
+    pub alignment_strength: f32,
+    pub cohesion_strength: f32,
+    pub neighbor_radius: f32,
+    pub fade: f32,
+    pub deposit: f32,
+    pub splat_radius: f32,
+    pub trail_lines: bool,
+    pub color_mode: ColorMode,
+    pub color_saturation: f32,
+    pub color_value: f32,
+    pub color_slow: Color,
+    pub color_fast: Color,
+    pub speed_gamma: f32,
+    pub age_curve: AgeCurve,
+    pub display_mode: DisplayMode,
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    pub gamma: f32,
+    pub invert: bool,
+    pub hue_shift: f32,
+    pub glow_strength: f32,
+    pub glow_radius: f32,
+    pub bloom_enabled: bool,
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    pub vignette_strength: f32,
+    pub vignette_radius: f32,
+    pub grain_amount: f32,
+    pub background_color: Color,
+    pub trail_color: Color,
+    pub bindings: InputBindings,
+    pub drift: AutoDrift,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read config: {err}"),
+            ConfigError::Parse(err) => write!(f, "could not parse config: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for FlowFieldConfig {
+    fn default() -> Self {
+        let settings = FlowFieldSettings::default();
+        let params = FlowFieldParams::default();
+        Self {
+            width: settings.width,
+            height: settings.height,
+            render_scale: WindowSizing::default().scale,
+            max_dimension: WindowSizing::default().max_dimension,
+            particles: settings.nr_particles,
+            workgroup_size: WorkgroupSize::default().0,
+            output_format: OutputFormat::default(),
+            seed: settings.seed,
+            mass: settings.mass,
+            velocity: settings.velocity,
+            coloring: settings.coloring,
+            supersample: settings.supersample,
+            noise_scale: params.noise_scale,
+            field_evolution_speed: params.field_evolution_speed,
+            octaves: params.octaves,
+            lacunarity: params.lacunarity,
+            gain: params.gain,
+            noise_mix: params.noise_mix,
+            image_influence: params.image_influence,
+            vector_field_influence: params.vector_field_influence,
+            sdf_threshold: params.sdf_threshold,
+            primitives: Vec::new(),
+            field_type: params.field_type,
+            curl_frequency: params.curl_frequency,
+            curl_epsilon: params.curl_epsilon,
+            field_rotation: params.field_rotation,
+            noise_offset: params.noise_offset,
+            boundary: params.boundary,
+            particle_lifetime: params.particle_lifetime,
+            lifetime_variance: params.lifetime_variance,
+            field_strength: params.field_strength,
+            drag: params.drag,
+            max_speed: params.max_speed,
+            gravity: params.gravity,
+            wind: params.wind,
+            repulsion: params.repulsion,
+            repulsion_radius: params.repulsion_radius,
+            repulsion_strength: params.repulsion_strength,
+            alignment_strength: params.alignment_strength,
+            cohesion_strength: params.cohesion_strength,
+            neighbor_radius: params.neighbor_radius,
+            fade: params.fade,
+            deposit: params.deposit,
+            splat_radius: params.splat_radius,
+            trail_lines: params.trail_lines,
+            color_mode: params.color_mode,
+            color_saturation: params.color_saturation,
+            color_value: params.color_value,
+            color_slow: params.color_slow,
+            color_fast: params.color_fast,
+            speed_gamma: params.speed_gamma,
+            age_curve: params.age_curve,
+            display_mode: params.display_mode,
+            exposure: params.exposure,
+            tonemap: params.tonemap,
+            gamma: params.gamma,
+            invert: params.invert,
+            hue_shift: params.hue_shift,
+            glow_strength: params.glow_strength,
+            glow_radius: params.glow_radius,
+            bloom_enabled: params.bloom_enabled,
+            bloom_threshold: params.bloom_threshold,
+            bloom_intensity: params.bloom_intensity,
+            vignette_strength: params.vignette_strength,
+            vignette_radius: params.vignette_radius,
+            grain_amount: params.grain_amount,
+            background_color: params.background_color,
+            trail_color: params.trail_color,
+            bindings: InputBindings::default(),
+            drift: AutoDrift::default(),
+        }
+    }
+}
+
+impl FlowFieldConfig {
+    /// Reads the config at `path`, returning `None` when the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Option<Self>, ConfigError> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(ConfigError::Io(err)),
+        };
+        ron::from_str(&source).map(Some).map_err(ConfigError::Parse)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap()
+    }
+
+    pub fn params(&self) -> FlowFieldParams {
+        FlowFieldParams {
+            noise_scale: self.noise_scale,
+            field_evolution_speed: self.field_evolution_speed,
+            octaves: self.octaves,
+            lacunarity: self.lacunarity,
+            gain: self.gain,
+            noise_mix: self.noise_mix,
+            image_influence: self.image_influence,
+            vector_field_influence: self.vector_field_influence,
+            sdf_threshold: self.sdf_threshold,
+            field_type: self.field_type,
+            curl_frequency: self.curl_frequency,
+            curl_epsilon: self.curl_epsilon,
+            field_rotation: self.field_rotation,
+            noise_offset: self.noise_offset,
+            boundary: self.boundary,
+            particle_lifetime: self.particle_lifetime,
+            lifetime_variance: self.lifetime_variance,
+            field_strength: self.field_strength,
+            drag: self.drag,
+            max_speed: self.max_speed,
+            gravity: self.gravity,
+            wind: self.wind,
+            repulsion: self.repulsion,
+            repulsion_radius: self.repulsion_radius,
+            repulsion_strength: self.repulsion_strength,
+            alignment_strength: self.alignment_strength,
+            cohesion_strength: self.cohesion_strength,
+            neighbor_radius: self.neighbor_radius,
+            fade: self.fade,
+            deposit: self.deposit,
+            splat_radius: self.splat_radius,
+            trail_lines: self.trail_lines,
+            color_mode: self.color_mode,
+            color_saturation: self.color_saturation,
+            color_value: self.color_value,
+            color_slow: self.color_slow,
+            color_fast: self.color_fast,
+            speed_gamma: self.speed_gamma,
+            age_curve: self.age_curve,
+            display_mode: self.display_mode,
+            exposure: self.exposure,
+            tonemap: self.tonemap,
+            gamma: self.gamma,
+            invert: self.invert,
+            hue_shift: self.hue_shift,
+            glow_strength: self.glow_strength,
+            glow_radius: self.glow_radius,
+            bloom_enabled: self.bloom_enabled,
+            bloom_threshold: self.bloom_threshold,
+            bloom_intensity: self.bloom_intensity,
+            vignette_strength: self.vignette_strength,
+            vignette_radius: self.vignette_radius,
+            grain_amount: self.grain_amount,
+            background_color: self.background_color,
+            trail_color: self.trail_color,
+        }
+    }
+
+    pub fn plugin(&self) -> ComputePlugin {
+        let plugin = ComputePlugin::default()
+            .with_size(self.width, self.height)
+            .with_particles(self.particles)
+            .with_mass(self.mass)
+            .with_velocity(self.velocity)
+            .with_coloring(self.coloring)
+            .with_supersample(self.supersample)
+            .with_workgroup_size(self.workgroup_size)
+            .with_output_format(self.output_format)
+            .with_params(self.params())
+            .with_primitives(self.primitives.clone())
+            .with_window_sizing(WindowSizing {
+                scale: self.render_scale,
+                max_dimension: self.max_dimension,
+            });
+        match self.seed {
+            Some(seed) => plugin.with_seed(seed),
+            None => plugin,
+        }
+    }
+}
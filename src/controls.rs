@@ -0,0 +1,689 @@
+use std::path::PathBuf;
+
+use bevy::{
+    prelude::*,
+    window::{PresentMode, PrimaryWindow, WindowMode},
+};
+
+use crate::{
+    bindings, brush, help, obstacles, pointer, screenshot, shuffle, view, Brush,
+    EnergyExportRequest, FilmEffects, FlowFieldParams, GifRequest, GifSettings, InputBindings,
+    KeyBinding, Palette, PlotStyle, Presets, Recording, ResetSimulation, ScreenshotRequest, Seed,
+    SeedVariation, SimulationSpeed, SimulationState, Snapshots, StepRequest, Substeps,
+    Trajectories, SCREENSHOT_DIR, SNAPSHOT_FILE,
+};
+
+/// Keyboard controls for the simulation, with these default keys which the [`InputBindings`]
+/// remap:
+///
+/// - `1`-`9`: switch to the matching entry in [`Presets`]
+/// - `Ctrl` + `1`-`9`: restart from the matching [`SeedVariation`] of the current parameters
+/// - `Space`: pause or resume, see [`SimulationState`]
+/// - `.`: advance a paused simulation by one [`Timestep`](crate::Timestep)
+/// - `[` / `]`: decrease or increase the number of [`Substeps`]
+/// - `Shift` + `[` / `]`: decrease or increase the exposure
+/// - `Ctrl` + `[` / `]`: decrease or increase the gamma
+/// - `R`: restart from new particles, see [`ResetSimulation`]
+/// - `Up` / `Down`: speed the simulation up or down, see [`SimulationSpeed`]
+/// - `Shift` + arrow keys: push the wind in that direction
+/// - `O` / `P`: remove or add a noise octave
+/// - `N` / `M`: shrink or grow the noise features
+/// - `K` / `L`: decrease or increase the drag on the particles
+/// - `F` / `H`: make trails fade slower or faster
+/// - `G`: toggle gravity pulling the particles down
+/// - `B`: cycle through the [`BoundaryMode`](crate::BoundaryMode)s
+/// - `C`: cycle through the [`FieldType`](crate::FieldType)s
+/// - `V`: cycle through the [`ColorMode`](crate::ColorMode)s
+/// - `Ctrl` + `V`: toggle vsync
+/// - `Z`: swap the slow and fast colors of [`ColorMode::Speed`](crate::ColorMode::Speed)
+/// - `I`: invert the image, for dark trails on a light background
+/// - `A`: show or hide the vignette and grain, see [`FilmEffects`]
+/// - `D`: cycle through the [`DisplayMode`](crate::DisplayMode)s
+/// - `U`: cycle through the [`Tonemap`](crate::Tonemap) curves
+/// - `T`: cycle through the built-in [`Palette`](crate::Palette)s and back to the trail color
+/// - `J`: randomize the noise, speed, fade, field type and palette, logging the new values as RON
+/// - `Shift` + `J`: go back to the parameters before the last randomization
+/// - `W`: let the parameters drift by themselves, see [`AutoDriftPlugin`](crate::AutoDriftPlugin)
+/// - left / right mouse button: attract or repel particles around the cursor, see
+///   [`PointerForce`](crate::PointerForce)
+/// - touch: attract particles around every finger
+/// - `Shift` + left mouse drag: paint directions into the field with the [`Brush`](crate::Brush)
+/// - `Shift` + scroll / `Ctrl` + `Shift` + scroll: change the brush radius or strength
+/// - scroll: zoom the view around the cursor
+/// - middle mouse drag: pan the view
+/// - `Home`: reset the view
+/// - `E`: clear the painted field
+/// - `X`: place an [`Obstacle`](crate::Obstacle) under the cursor
+/// - `F1`: list every action and its key, see [`HelpOverlayPlugin`](crate::HelpOverlayPlugin)
+/// - `F9`: start or stop writing every frame to disk, see [`Recording`]
+/// - `F10`: start recording the paths of the particles, and on the second press save them as an
+///   SVG, see [`Trajectories`]
+/// - `F11`: toggle borderless fullscreen
+/// - `F12`: save a screenshot, see [`ScreenshotRequest`]
+/// - `Shift` + `F12`: save the raw trail energies as OpenEXR, see [`EnergyExportRequest`]
+/// - `Ctrl` + `F12`: capture a looping GIF, see [`GifRequest`]
+/// - `Ctrl` + `S` / `L`: save the simulation to [`SNAPSHOT_FILE`] or restore it from there, see
+///   [`Snapshots`]
+pub struct FlowFieldControlsPlugin;
+
+impl Plugin for FlowFieldControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputBindings>();
+        app.init_resource::<UiFocus>();
+        #[cfg(any(feature = "ui", feature = "inspector"))]
+        app.add_systems(
+            PreUpdate,
+            track_ui_focus.after(bevy_egui::EguiSet::BeginFrame),
+        );
+        app.add_systems(Startup, bindings::warn_conflicting_bindings);
+        app.add_systems(
+            Update,
+            (
+                select_preset,
+                toggle_pause,
+                step_frame,
+                save_screenshot,
+                toggle_recording,
+                toggle_plotting,
+                save_or_load_snapshot,
+                toggle_fullscreen,
+                change_substeps,
+                reset,
+                change_speed,
+                change_noise,
+                cycle_boundary,
+                cycle_field_type,
+                (
+                    cycle_color_mode,
+                    swap_speed_colors,
+                    cycle_palette,
+                    cycle_display_mode,
+                    cycle_tonemap,
+                    toggle_invert,
+                    toggle_film_effects,
+                    toggle_vsync,
+                ),
+                change_drag,
+                change_fade,
+                change_exposure,
+                toggle_gravity,
+                change_wind,
+            )
+                .run_if(help::help_closed.and_then(keyboard_free)),
+        );
+        app.init_resource::<Brush>();
+        app.init_resource::<shuffle::ParamShuffle>();
+        app.add_systems(
+            Update,
+            (
+                pointer::update_pointer_force,
+                (
+                    brush::adjust_brush,
+                    view::zoom_view,
+                    view::pan_view,
+                    brush::paint,
+                )
+                    .run_if(pointer_free),
+                (
+                    view::reset_view,
+                    brush::clear_painting,
+                    obstacles::add_obstacle_at_cursor,
+                    select_variation,
+                    shuffle::shuffle_params,
+                    shuffle::restore_params,
+                )
+                    .run_if(keyboard_free),
+            )
+                .run_if(help::help_closed),
+        );
+    }
+}
+
+/// Whether an egui panel or window, like the [`ControlPanelPlugin`](crate::ControlPanelPlugin)
+/// or the world inspector, uses the mouse or keyboard this frame. The controls leave that input
+/// to egui, so dragging a slider doesn't also attract particles under the panel and typing into a
+/// field doesn't switch presets.
+#[derive(Resource, Default, PartialEq)]
+pub(crate) struct UiFocus {
+    pub pointer: bool,
+    pub keyboard: bool,
+}
+
+#[cfg(any(feature = "ui", feature = "inspector"))]
+fn track_ui_focus(mut focus: ResMut<UiFocus>, mut contexts: Query<&mut bevy_egui::EguiContext>) {
+    let (mut pointer, mut keyboard) = (false, false);
+    for mut context in &mut contexts {
+        let ctx = context.get_mut();
+        pointer |= ctx.wants_pointer_input();
+        keyboard |= ctx.wants_keyboard_input();
+    }
+    focus.set_if_neq(UiFocus { pointer, keyboard });
+}
+
+/// Run condition for the systems reacting to the mouse, false while egui uses it.
+pub(crate) fn pointer_free(focus: Res<UiFocus>) -> bool {
+    !focus.pointer
+}
+
+/// Run condition for the systems reacting to keys, false while egui uses them.
+pub(crate) fn keyboard_free(focus: Res<UiFocus>) -> bool {
+    !focus.keyboard
+}
+
+fn select_preset(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut presets: ResMut<Presets>,
+) {
+    for (index, binding) in bindings.presets.iter().enumerate() {
+        if binding.just_pressed(&keys) {
+            presets.select(index);
+        }
+    }
+}
+
+fn select_variation(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut variation: ResMut<SeedVariation>,
+    mut seed: ResMut<Seed>,
+    mut resets: EventWriter<ResetSimulation>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    let Some(index) = bindings
+        .variations
+        .iter()
+        .position(|binding| binding.just_pressed(&keys))
+    else {
+        return;
+    };
+    seed.0 = variation.select(index as u32 + 1);
+    let noise_offset = SeedVariation::noise_offset(seed.0);
+    for mut params in &mut fields {
+        params.noise_offset = noise_offset;
+    }
+    resets.send(ResetSimulation);
+    info!("variation {}, seed {}", index + 1, seed.0);
+}
+
+fn toggle_pause(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut state: ResMut<SimulationState>,
+) {
+    if bindings.pause.just_pressed(&keys) {
+        state.toggle();
+        info!("simulation {:?}", *state);
+    }
+}
+
+fn step_frame(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    state: Res<SimulationState>,
+    step: Res<StepRequest>,
+) {
+    if *state == SimulationState::Paused && bindings.step.just_pressed(&keys) {
+        step.request();
+    }
+}
+
+fn save_screenshot(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    screenshot: Res<ScreenshotRequest>,
+    energy_export: Res<EnergyExportRequest>,
+    gif: Res<GifRequest>,
+    gif_settings: Res<GifSettings>,
+    variation: Res<SeedVariation>,
+) {
+    if bindings.export_energies.just_pressed(&keys) {
+        energy_export.request();
+    }
+    if bindings.capture_gif.just_pressed(&keys) {
+        let path = PathBuf::from(SCREENSHOT_DIR).join(screenshot::file_name(
+            "flow",
+            Some(&variation),
+            "gif",
+        ));
+        gif.request(path, gif_settings.clone());
+    }
+    if bindings.screenshot.just_pressed(&keys) {
+        screenshot.request();
+    }
+}
+
+fn toggle_fullscreen(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !bindings.toggle_fullscreen.just_pressed(&keys) {
+        return;
+    }
+    for mut window in &mut windows {
+        window.mode = match window.mode {
+            WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+            _ => WindowMode::Windowed,
+        };
+    }
+}
+
+fn toggle_vsync(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !bindings.toggle_vsync.just_pressed(&keys) {
+        return;
+    }
+    for mut window in &mut windows {
+        window.present_mode = match window.present_mode {
+            PresentMode::AutoVsync => PresentMode::AutoNoVsync,
+            _ => PresentMode::AutoVsync,
+        };
+        info!("present mode: {:?}", window.present_mode);
+    }
+}
+
+fn toggle_plotting(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    trajectories: Res<Trajectories>,
+    variation: Res<SeedVariation>,
+) {
+    if !bindings.toggle_plotting.just_pressed(&keys) {
+        return;
+    }
+    if !trajectories.is_recording() {
+        trajectories.start();
+        info!("recording particle paths");
+        return;
+    }
+
+    trajectories.stop();
+    let path =
+        PathBuf::from(SCREENSHOT_DIR).join(screenshot::file_name("plot", Some(&variation), "svg"));
+    match trajectories.write_svg(&path, &PlotStyle::default()) {
+        Ok(()) => info!("saved {} paths to {}", trajectories.len(), path.display()),
+        Err(err) => error!("could not save paths to {}: {err}", path.display()),
+    }
+    trajectories.clear();
+}
+
+fn save_or_load_snapshot(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut snapshots: ResMut<Snapshots>,
+) {
+    if bindings.save_snapshot.just_pressed(&keys) {
+        snapshots.save(SNAPSHOT_FILE);
+    }
+    if bindings.load_snapshot.just_pressed(&keys) {
+        snapshots.load(SNAPSHOT_FILE);
+    }
+}
+
+fn toggle_recording(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut recording: ResMut<Recording>,
+) {
+    if bindings.toggle_recording.just_pressed(&keys) {
+        if cfg!(not(feature = "export-png")) {
+            warn!("built without export-png, no frames are recorded");
+            return;
+        }
+        recording.active = !recording.active;
+        let state = if recording.active {
+            "started"
+        } else {
+            "stopped"
+        };
+        info!(
+            "recording {state}, frames go to {}",
+            recording.directory.display()
+        );
+    }
+}
+
+fn change_substeps(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut substeps: ResMut<Substeps>,
+) {
+    let current = substeps.0;
+    if bindings.fewer_substeps.just_pressed(&keys) {
+        substeps.0 = current.saturating_sub(1).max(1);
+    }
+    if bindings.more_substeps.just_pressed(&keys) {
+        substeps.0 = (current + 1).min(Substeps::MAX);
+    }
+    if substeps.0 != current {
+        info!("{} substeps per frame", substeps.0);
+    }
+}
+
+fn reset(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut events: EventWriter<ResetSimulation>,
+) {
+    if bindings.reset.just_pressed(&keys) {
+        events.send(ResetSimulation);
+    }
+}
+
+const SPEED_STEP: f32 = 1.25;
+
+fn change_speed(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    let mut new_speed = speed.0;
+    if bindings.speed_up.just_pressed(&keys) {
+        new_speed *= SPEED_STEP;
+    }
+    if bindings.slow_down.just_pressed(&keys) {
+        new_speed /= SPEED_STEP;
+    }
+    new_speed = new_speed.clamp(SimulationSpeed::MIN, SimulationSpeed::MAX);
+    if new_speed != speed.0 {
+        speed.0 = new_speed;
+        info!("simulation speed {:.2}x", new_speed);
+    }
+}
+
+const NOISE_SCALE_STEP: f32 = 1.25;
+
+fn change_noise(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    let octaves = steps(&keys, bindings.remove_octave, bindings.add_octave);
+    let scale = steps(&keys, bindings.shrink_noise, bindings.grow_noise);
+    if octaves == 0 && scale == 0 {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.octaves = params
+            .octaves
+            .saturating_add_signed(octaves)
+            .clamp(1, FlowFieldParams::MAX_OCTAVES);
+        params.noise_scale *= NOISE_SCALE_STEP.powi(scale);
+        info!(
+            "noise: {} octaves, scale {:.0}",
+            params.octaves, params.noise_scale
+        );
+    }
+}
+
+fn cycle_boundary(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.cycle_boundary.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.boundary = params.boundary.next();
+        info!("boundary mode: {:?}", params.boundary);
+    }
+}
+
+fn cycle_field_type(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.cycle_field_type.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.field_type = params.field_type.next();
+        info!("field type: {:?}", params.field_type);
+    }
+}
+
+fn cycle_color_mode(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.cycle_color_mode.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.color_mode = params.color_mode.next();
+        info!("color mode: {:?}", params.color_mode);
+    }
+}
+
+fn swap_speed_colors(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.swap_speed_colors.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        let params = &mut *params;
+        std::mem::swap(&mut params.color_slow, &mut params.color_fast);
+        info!(
+            "speed colors: {:?} to {:?}",
+            params.color_slow, params.color_fast
+        );
+    }
+}
+
+fn cycle_display_mode(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.cycle_display_mode.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.display_mode = params.display_mode.next();
+        info!("display mode: {:?}", params.display_mode);
+    }
+}
+
+fn cycle_tonemap(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.cycle_tonemap.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.tonemap = params.tonemap.next();
+        info!("tonemap: {:?}", params.tonemap);
+    }
+}
+
+fn toggle_invert(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.invert.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.invert = !params.invert;
+        info!("inverted: {}", params.invert);
+    }
+}
+
+fn toggle_film_effects(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut film_effects: ResMut<FilmEffects>,
+) {
+    if bindings.toggle_film_effects.just_pressed(&keys) {
+        film_effects.0 = !film_effects.0;
+        info!("film effects: {}", film_effects.0);
+    }
+}
+
+fn cycle_palette(
+    mut commands: Commands,
+    mut index: Local<usize>,
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+) {
+    if !bindings.cycle_palette.just_pressed(&keys) {
+        return;
+    }
+
+    // Index zero is no palette at all.
+    let builtins = Palette::builtins();
+    *index = (*index + 1) % (builtins.len() + 1);
+    match index.checked_sub(1).and_then(|i| builtins.get(i)) {
+        Some(palette) => {
+            info!("palette: {}", palette.name());
+            commands.insert_resource(palette.clone());
+        }
+        None => {
+            info!("palette: none");
+            commands.remove_resource::<Palette>();
+        }
+    }
+}
+
+const DRAG_STEP: f32 = 0.01;
+
+fn change_drag(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    let steps = steps(&keys, bindings.less_drag, bindings.more_drag);
+    if steps == 0 {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.drag =
+            (params.drag + steps as f32 * DRAG_STEP).clamp(0.0, FlowFieldParams::MAX_DRAG);
+        info!("drag: {:.2}", params.drag);
+    }
+}
+
+const GRAVITY: Vec2 = Vec2::new(0.0, 1.0);
+
+fn toggle_gravity(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.toggle_gravity.just_pressed(&keys) {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.gravity = if params.gravity == Vec2::ZERO {
+            GRAVITY
+        } else {
+            Vec2::ZERO
+        };
+        info!("gravity: {}", params.gravity);
+    }
+}
+
+const WIND_STEP: f32 = 0.1;
+
+fn change_wind(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    let step = Vec2::new(
+        steps(&keys, bindings.wind_left, bindings.wind_right) as f32,
+        steps(&keys, bindings.wind_up, bindings.wind_down) as f32,
+    );
+    if step == Vec2::ZERO {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.wind += step * WIND_STEP;
+        info!("wind: {:.1}", params.wind);
+    }
+}
+
+/// `1` when `up` was pressed, `-1` for `down` and `0` for both or neither.
+fn steps(keys: &Input<KeyCode>, down: KeyBinding, up: KeyBinding) -> i32 {
+    up.just_pressed(keys) as i32 - down.just_pressed(keys) as i32
+}
+
+const EXPOSURE_STEP: f32 = 1.25;
+const MIN_EXPOSURE: f32 = 0.05;
+const MAX_EXPOSURE: f32 = 64.0;
+const GAMMA_STEP: f32 = 0.1;
+
+fn change_exposure(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    let exposure = steps(&keys, bindings.lower_exposure, bindings.raise_exposure);
+    let gamma = steps(&keys, bindings.lower_gamma, bindings.raise_gamma);
+    if exposure == 0 && gamma == 0 {
+        return;
+    }
+
+    for mut params in &mut fields {
+        if exposure != 0 {
+            // Steps down from the lowest exposure to linear and back up.
+            params.exposure = match (params.exposure > 0.0, exposure > 0) {
+                (false, true) => 1.0,
+                (false, false) => 0.0,
+                (true, _) => params.exposure * EXPOSURE_STEP.powi(exposure),
+            };
+            if params.exposure < MIN_EXPOSURE {
+                params.exposure = 0.0;
+            }
+            params.exposure = params.exposure.min(MAX_EXPOSURE);
+            info!("exposure: {:.2}", params.exposure);
+        }
+        if gamma != 0 {
+            params.gamma = (params.gamma + gamma as f32 * GAMMA_STEP).max(GAMMA_STEP);
+            info!("gamma: {:.1}", params.gamma);
+        }
+    }
+}
+
+const FADE_STEP: f32 = 0.005;
+const MAX_FADE: f32 = 0.2;
+
+fn change_fade(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    let steps = steps(&keys, bindings.slower_fade, bindings.faster_fade);
+    if steps == 0 {
+        return;
+    }
+
+    for mut params in &mut fields {
+        params.fade = (params.fade + steps as f32 * FADE_STEP).clamp(0.0, MAX_FADE);
+        info!("fade: {:.3}", params.fade);
+    }
+}
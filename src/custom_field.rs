@@ -0,0 +1,78 @@
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{extract_resource::ExtractResource, render_resource::Source},
+};
+
+/// Import path of the module the compute shader takes `field` from.
+pub const CUSTOM_FIELD_IMPORT_PATH: &str = "flow_field::custom_field";
+
+/// The module imported by the compute shader, a copy of the [`CustomField`] or a stub while there
+/// is none.
+pub const CUSTOM_FIELD_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x2d6e_91b4_c03a_f517);
+
+/// A WGSL shader replacing the noise of every flow field with its own formula. It defines
+/// `fn field(pos: vec2<f32>, t: f32) -> vec2<f32>`, which gets the position of a particle in
+/// noise space and the evolution of the field, and returns the direction of the flow there. The
+/// [`FlowFieldParams::field_rotation`](crate::FlowFieldParams::field_rotation) and the mixing with
+/// the primitives, images and vector fields still apply.
+///
+/// The shader is imported as [`CUSTOM_FIELD_IMPORT_PATH`], so it must not define an import path
+/// itself. The programs are recompiled whenever it changes or hot reloads, see
+/// [`ComputePlugin::with_custom_field`](crate::ComputePlugin::with_custom_field).
+#[derive(Resource, Clone, Default, PartialEq, Eq, Debug, ExtractResource)]
+pub struct CustomField(pub Option<Handle<Shader>>);
+
+fn stub() -> Shader {
+    Shader::from_wgsl(
+        include_str!("../assets/shaders/custom_field.wgsl"),
+        "shaders/custom_field.wgsl",
+    )
+}
+
+/// The `source` as the module imported by the compute shader.
+fn module(source: &str, path: &str) -> Shader {
+    let mut wgsl = format!("#define_import_path {CUSTOM_FIELD_IMPORT_PATH}\n");
+    for line in source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("#define_import_path"))
+    {
+        wgsl.push_str(line);
+        wgsl.push('\n');
+    }
+    Shader::from_wgsl(wgsl, path.to_string())
+}
+
+/// Copies the [`CustomField`] into the imported module whenever it is set, loaded or modified.
+pub(crate) fn update_custom_field(
+    mut events: EventReader<AssetEvent<Shader>>,
+    custom_field: Res<CustomField>,
+    mut shaders: ResMut<Assets<Shader>>,
+) {
+    let reloaded = events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+            Some(handle) == custom_field.0.as_ref()
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+    if !reloaded && !custom_field.is_changed() {
+        return;
+    }
+    let Some(handle) = &custom_field.0 else {
+        shaders.set_untracked(CUSTOM_FIELD_SHADER_HANDLE, stub());
+        return;
+    };
+    let Some(shader) = shaders.get(handle) else {
+        return;
+    };
+    let module = match &shader.source {
+        Source::Wgsl(source) => module(source, &shader.path),
+        _ => {
+            warn!("custom flow fields must be WGSL shaders");
+            return;
+        }
+    };
+    info!("compiling the custom flow field {}", shader.path);
+    shaders.set_untracked(CUSTOM_FIELD_SHADER_HANDLE, module);
+}
@@ -0,0 +1,347 @@
+use std::borrow::Cow;
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        camera::RenderTarget,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+            BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType,
+            BufferDescriptor, BufferInitDescriptor, BufferUsages, CachedComputePipelineId,
+            CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor, Extent3d,
+            PipelineCache, ShaderStages, StorageTextureAccess, TextureDimension, TextureFormat,
+            TextureUsages, TextureViewDimension,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        view::RenderLayers,
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+    window::WindowRef,
+};
+
+use crate::{
+    exr, ComputeInput, FlowFieldOutput, FlowFieldSettings, ParticleBuffer, PrimaryFlowField,
+};
+
+/// The embedded shader of the [`DebugViewPlugin`].
+pub const DEBUG_VIEW_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x8c27_e5f0_4ab9_1d63);
+
+/// Render layer of the debug view sprite, so only its own camera draws it.
+const DEBUG_VIEW_LAYER: u8 = 1;
+
+/// Opens a second window showing the raw trail energies of the [`PrimaryFlowField`] before any
+/// tone mapping, every channel scaled linearly so the brightest one is white. Either window can be
+/// closed without affecting the other; closing this one frees its image and stops its dispatches.
+pub struct DebugViewPlugin;
+
+impl Plugin for DebugViewPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            DEBUG_VIEW_SHADER_HANDLE,
+            "../assets/shaders/debug_view.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_systems(PostStartup, open_debug_view);
+        app.add_systems(PostUpdate, update_debug_view);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(ExtractSchedule, extract_debug_view);
+        // After the frame has been submitted, the view shows the energies of this frame on the
+        // next one. The fields are only readable until bevy clears the render entities.
+        render_app.add_systems(
+            Render,
+            draw_debug_view
+                .before(World::clear_entities)
+                .in_set(RenderSet::Cleanup),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<DebugViewPipeline>();
+    }
+}
+
+/// The entities of the open debug view.
+#[derive(Resource)]
+struct DebugView {
+    window: Entity,
+    camera: Entity,
+    sprite: Entity,
+    image: Handle<Image>,
+}
+
+fn field_image(settings: &FlowFieldSettings) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: settings.width,
+            height: settings.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8Unorm,
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    image
+}
+
+fn open_debug_view(
+    mut commands: Commands,
+    field: Query<&FlowFieldSettings, With<PrimaryFlowField>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Ok(settings) = field.get_single() else {
+        return;
+    };
+    let image = images.add(field_image(settings));
+    let window = commands
+        .spawn(Window {
+            title: "flow field energies".to_string(),
+            resolution: (
+                640.0,
+                640.0 * settings.height as f32 / settings.width as f32,
+            )
+                .into(),
+            ..default()
+        })
+        .id();
+    let camera = commands
+        .spawn((
+            Camera2dBundle {
+                camera: Camera {
+                    target: RenderTarget::Window(WindowRef::Entity(window)),
+                    // Apart from the cameras of the primary window.
+                    order: 2,
+                    ..default()
+                },
+                ..default()
+            },
+            UiCameraConfig { show_ui: false },
+            RenderLayers::layer(DEBUG_VIEW_LAYER),
+        ))
+        .id();
+    let sprite = commands
+        .spawn((
+            SpriteBundle {
+                texture: image.clone(),
+                ..default()
+            },
+            RenderLayers::layer(DEBUG_VIEW_LAYER),
+        ))
+        .id();
+    commands.insert_resource(DebugView {
+        window,
+        camera,
+        sprite,
+        image,
+    });
+}
+
+/// Keeps the image at the size of the field and the sprite filling the window, and cleans up once
+/// the window has been closed.
+fn update_debug_view(
+    mut commands: Commands,
+    view: Option<Res<DebugView>>,
+    windows: Query<&Window>,
+    field: Query<&FlowFieldSettings, With<PrimaryFlowField>>,
+    mut sprites: Query<&mut Sprite>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(view) = view else {
+        return;
+    };
+    let Ok(window) = windows.get(view.window) else {
+        commands.entity(view.camera).despawn();
+        commands.entity(view.sprite).despawn();
+        images.remove(&view.image);
+        commands.remove_resource::<DebugView>();
+        return;
+    };
+
+    if let Ok(mut sprite) = sprites.get_mut(view.sprite) {
+        let size = Vec2::new(window.width(), window.height());
+        if sprite.custom_size != Some(size) {
+            sprite.custom_size = Some(size);
+        }
+    }
+    let Ok(settings) = field.get_single() else {
+        return;
+    };
+    let resized = images.get(&view.image).is_some_and(|image| {
+        let size = image.texture_descriptor.size;
+        size.width != settings.width || size.height != settings.height
+    });
+    if resized {
+        if let Some(image) = images.get_mut(&view.image) {
+            *image = field_image(settings);
+        }
+    }
+}
+
+/// Render world copy of the image of the open debug view.
+#[derive(Resource)]
+struct DebugViewImage(Handle<Image>);
+
+fn extract_debug_view(mut commands: Commands, view: Extract<Option<Res<DebugView>>>) {
+    match view.as_ref() {
+        Some(view) => commands.insert_resource(DebugViewImage(view.image.clone())),
+        None => commands.remove_resource::<DebugViewImage>(),
+    }
+}
+
+#[derive(Resource)]
+struct DebugViewPipeline {
+    bind_group_layout: BindGroupLayout,
+    find_peak: CachedComputePipelineId,
+    composite: CachedComputePipelineId,
+    /// The brightest channel of the energies, found anew every frame.
+    peak: Buffer,
+}
+
+impl FromWorld for DebugViewPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("flow field debug view"),
+                entries: &[
+                    storage(0, true),
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: TextureFormat::Rgba8Unorm,
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    storage(2, false),
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let peak = render_device.create_buffer(&BufferDescriptor {
+            label: Some("flow field debug view peak"),
+            size: 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = DEBUG_VIEW_SHADER_HANDLE.typed();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let queue = |entry_point: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(format!("flow field debug view {entry_point}").into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: Vec::new(),
+                shader: shader.clone(),
+                shader_defs: Vec::new(),
+                entry_point: Cow::from(entry_point),
+            })
+        };
+        let find_peak = queue("find_peak");
+        let composite = queue("composite");
+        Self {
+            bind_group_layout,
+            find_peak,
+            composite,
+            peak,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_debug_view(
+    view: Option<Res<DebugViewImage>>,
+    pipeline: Res<DebugViewPipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    output: Option<Res<FlowFieldOutput>>,
+    fields: Query<(&FlowFieldSettings, &ParticleBuffer, &ComputeInput)>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let image = view.and_then(|view| gpu_images.get(&view.0));
+    let field = exr::output_field(output.as_deref(), &fields);
+    let find_peak = pipeline_cache.get_compute_pipeline(pipeline.find_peak);
+    let composite = pipeline_cache.get_compute_pipeline(pipeline.composite);
+    let (Some(image), Some((settings, buffers)), Some(find_peak), Some(composite)) =
+        (image, field, find_peak, composite)
+    else {
+        return;
+    };
+    // Waits for the image to follow a resized field.
+    let size = UVec2::new(settings.width, settings.height);
+    if image.size != size.as_vec2() {
+        return;
+    }
+
+    let sizes = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("flow field debug view sizes"),
+        contents: bytemuck::cast_slice(&[size.x, size.y, settings.supersample, 0]),
+        usage: BufferUsages::UNIFORM,
+    });
+    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some("flow field debug view"),
+        layout: &pipeline.bind_group_layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: buffers.energies.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&image.texture_view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: pipeline.peak.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: sizes.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.clear_buffer(&pipeline.peak, 0, None);
+    {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("flow field debug view"),
+        });
+        pass.set_bind_group(0, &bind_group, &[]);
+        let groups = (size.x.div_ceil(16), size.y.div_ceil(16));
+        pass.set_pipeline(find_peak);
+        pass.dispatch_workgroups(groups.0, groups.1, 1);
+        pass.set_pipeline(composite);
+        pass.dispatch_workgroups(groups.0, groups.1, 1);
+    }
+    render_queue.submit([encoder.finish()]);
+}
@@ -0,0 +1,153 @@
+use bevy::{
+    input::mouse::{MouseButtonInput, MouseWheel},
+    prelude::*,
+};
+use rand::random;
+use serde::{Deserialize, Serialize};
+
+use crate::{help, FlowFieldParams, InputBindings, ResetSimulation, Seed, SimulationSpeed};
+
+/// Seconds the parameters take to settle on the drifting values, so the drift blends in when it
+/// starts or resumes after manual input instead of jumping.
+const RESPONSE: f32 = 5.0;
+
+/// Lets the parameters of every flow field wander by themselves for always-on displays: the noise
+/// scale, the simulation speed, the hue of the image and the rotation of the flow follow slow
+/// smooth curves, and the particles start over from a new seed now and then. Stored in the `drift`
+/// of the [`FlowFieldConfig`](crate::FlowFieldConfig).
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoDrift {
+    pub enabled: bool,
+    /// Seconds in which the curves roughly go through one cycle.
+    pub period: f32,
+    /// Range of the [`FlowFieldParams::noise_scale`].
+    pub noise_scale: (f32, f32),
+    /// Range of the [`SimulationSpeed`].
+    pub speed: (f32, f32),
+    /// Turns of the color wheel the [`FlowFieldParams::hue_shift`] sweeps through.
+    pub hue_range: f32,
+    /// Largest [`FlowFieldParams::field_rotation`] either way, in radians.
+    pub rotation_range: f32,
+    /// Seconds between new seeds for the particles, `0.0` keeps the seed.
+    pub reseed_interval: f32,
+    /// Seconds the drift waits after any key press, click or scroll before it resumes.
+    pub grace_period: f32,
+}
+
+impl Default for AutoDrift {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            period: 240.0,
+            noise_scale: (150.0, 600.0),
+            speed: (0.5, 1.5),
+            hue_range: 1.0,
+            rotation_range: std::f32::consts::FRAC_PI_2,
+            reseed_interval: 600.0,
+            grace_period: 30.0,
+        }
+    }
+}
+
+/// Runs the [`AutoDrift`], which [`InputBindings::toggle_drift`] turns on and off.
+pub struct AutoDriftPlugin;
+
+impl Plugin for AutoDriftPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AutoDrift>();
+        app.init_resource::<InputBindings>();
+        app.add_systems(
+            Update,
+            (toggle_drift.run_if(help::help_closed), drift_params).chain(),
+        );
+    }
+}
+
+/// Smooth curve in `0.0..=1.0`, from two sines whose ratio of frequencies is irrational so the
+/// curve never repeats.
+fn curve(t: f32, phase: f32) -> f32 {
+    let wave = 0.6 * (t + phase).sin() + 0.4 * (t * 1.618 + 2.0 * phase).sin();
+    0.5 + 0.5 * wave
+}
+
+fn toggle_drift(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut drift: ResMut<AutoDrift>,
+) {
+    if bindings.toggle_drift.just_pressed(&keys) {
+        drift.enabled = !drift.enabled;
+        info!("auto drift: {}", drift.enabled);
+    }
+}
+
+#[derive(Default)]
+struct DriftState {
+    /// Seconds spent drifting, which the curves follow.
+    elapsed: f32,
+    since_reseed: f32,
+    /// Time of the last manual input.
+    last_input: Option<f32>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drift_params(
+    mut state: Local<DriftState>,
+    drift: Res<AutoDrift>,
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mut clicks: EventReader<MouseButtonInput>,
+    mut wheel: EventReader<MouseWheel>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut seed: ResMut<Seed>,
+    mut resets: EventWriter<ResetSimulation>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    let now = time.raw_elapsed_seconds();
+    let input = keys.get_just_pressed().next().is_some()
+        || clicks.iter().count() > 0
+        || wheel.iter().count() > 0;
+    if input {
+        state.last_input = Some(now);
+    }
+    // Turning the drift on starts it right away, rather than after the key that did it.
+    if drift.is_changed() {
+        state.last_input = None;
+    }
+    let waiting = state
+        .last_input
+        .is_some_and(|last| now - last < drift.grace_period);
+    if !drift.enabled || waiting {
+        return;
+    }
+
+    let dt = time.delta_seconds();
+    state.elapsed += dt;
+    let t = state.elapsed * std::f32::consts::TAU / drift.period.max(1.0);
+    let blend = 1.0 - (-dt / RESPONSE).exp();
+    let approach = |value: &mut f32, target: f32| *value += (target - *value) * blend;
+
+    // Log space, so the curve spends as long at small scales as at large ones.
+    let (min, max) = drift.noise_scale;
+    let noise_scale = (min.ln() + (max.ln() - min.ln()) * curve(t, 0.0)).exp();
+    let hue_shift = drift.hue_range * curve(t * 0.5, 1.0);
+    let rotation = drift.rotation_range * (2.0 * curve(t * 0.7, 2.0) - 1.0);
+    for mut params in &mut fields {
+        approach(&mut params.noise_scale, noise_scale);
+        approach(&mut params.hue_shift, hue_shift);
+        approach(&mut params.field_rotation, rotation);
+    }
+    let (min, max) = drift.speed;
+    let target =
+        (min + (max - min) * curve(t * 1.3, 3.0)).clamp(SimulationSpeed::MIN, SimulationSpeed::MAX);
+    approach(&mut speed.0, target);
+
+    state.since_reseed += dt;
+    if drift.reseed_interval > 0.0 && state.since_reseed >= drift.reseed_interval {
+        state.since_reseed = 0.0;
+        seed.0 = random();
+        info!("auto drift reseeded: {}", seed.0);
+        resets.send(ResetSimulation);
+    }
+}
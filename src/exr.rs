@@ -0,0 +1,279 @@
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+#[cfg(feature = "export-exr")]
+use std::{
+    fs,
+    io::{self, Seek, Write},
+    path::PathBuf,
+    sync::mpsc::Receiver,
+    thread,
+};
+
+#[cfg(feature = "export-exr")]
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor,
+};
+use bevy::{
+    prelude::*,
+    render::renderer::{RenderDevice, RenderQueue},
+};
+
+#[cfg(feature = "export-exr")]
+use crate::{
+    poll,
+    shutdown::{Exiting, PendingExports},
+};
+use crate::{ComputeInput, FlowFieldOutput, FlowFieldSettings, ParticleBuffer};
+
+/// Fixed point scale of the energy counters, `ENERGY_SCALE` in the shader.
+#[cfg(feature = "export-exr")]
+const ENERGY_SCALE: f32 = 256.0;
+
+/// Asks for the trail energies of the [`FlowFieldOutput`]'s field to be saved as a 32-bit float
+/// OpenEXR file in [`SCREENSHOT_DIR`](crate::SCREENSHOT_DIR). Unlike screenshots these are the raw
+/// accumulated values before exposure, tonemapping and palettes, so nothing is clipped. The file
+/// has `R`, `G` and `B` channels with the colored energy and a `density` channel with the energy
+/// regardless of color. Without the `export-exr` feature requests are logged and dropped.
+#[derive(Resource, Clone, Default)]
+pub struct EnergyExportRequest(Arc<AtomicBool>);
+
+impl EnergyExportRequest {
+    pub fn request(&self) {
+        if cfg!(not(feature = "export-exr")) {
+            warn!("built without export-exr, no energies are exported");
+            return;
+        }
+        self.0.store(true, Ordering::Release);
+    }
+
+    #[cfg(feature = "export-exr")]
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// A copy of the energies of a field on its way to the CPU.
+#[cfg(feature = "export-exr")]
+pub(crate) struct EnergyReadback {
+    staging: Buffer,
+    mapped: Receiver<Result<(), String>>,
+    size: UVec2,
+    supersample: u32,
+    render_queue: RenderQueue,
+}
+
+#[cfg(feature = "export-exr")]
+impl EnergyReadback {
+    pub(crate) fn start(
+        settings: &FlowFieldSettings,
+        buffers: &ParticleBuffer,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+    ) -> Self {
+        let size = buffers.energies.size();
+        let staging = render_device.create_buffer(&BufferDescriptor {
+            label: Some("flow field energy readback"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(&buffers.energies, 0, &staging, 0, size);
+        render_queue.submit([encoder.finish()]);
+
+        let mapped = poll::map_read(render_device, &staging);
+        Self {
+            staging,
+            mapped,
+            size: UVec2::new(settings.width, settings.height),
+            supersample: settings.supersample,
+            render_queue: render_queue.clone(),
+        }
+    }
+
+    /// Waits for the copy and writes it to `path`, summing supersampled pixels the way the display
+    /// does.
+    pub(crate) fn save(self, path: &Path) -> Result<(), String> {
+        poll::wait_for_map(&self.render_queue, &self.mapped)
+            .expect("the energy buffer was dropped")?;
+
+        let (width, height) = (self.size.x as usize, self.size.y as usize);
+        let s = self.supersample as usize;
+        let mut channels = [(); 4].map(|_| vec![0.0; width * height]);
+        {
+            let data = self.staging.slice(..).get_mapped_range();
+            let counters: &[u32] = bytemuck::cast_slice(&data);
+            for (i, pixel) in counters.chunks_exact(4).enumerate() {
+                let (x, y) = (i % (width * s) / s, i / (width * s) / s);
+                for (channel, value) in channels.iter_mut().zip(pixel) {
+                    channel[x + width * y] += *value as f32 / ENERGY_SCALE;
+                }
+            }
+        }
+        self.staging.unmap();
+
+        let [r, g, b, density] = channels;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+        }
+        let file = io::BufWriter::new(fs::File::create(path).map_err(|err| err.to_string())?);
+        write_exr(
+            file,
+            self.size,
+            [("R", r), ("G", g), ("B", b), ("density", density)],
+        )
+        .map_err(|err| err.to_string())
+    }
+}
+
+/// Reads the energies of a field back and saves them to `path`, blocking until they are written.
+#[cfg(feature = "export-exr")]
+pub(crate) fn save_energies(
+    settings: &FlowFieldSettings,
+    buffers: &ParticleBuffer,
+    path: &Path,
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+) -> Result<(), String> {
+    EnergyReadback::start(settings, buffers, render_device, render_queue).save(path)
+}
+
+#[cfg(not(feature = "export-exr"))]
+pub(crate) fn save_energies(
+    _settings: &FlowFieldSettings,
+    _buffers: &ParticleBuffer,
+    _path: &Path,
+    _render_device: &RenderDevice,
+    _render_queue: &RenderQueue,
+) -> Result<(), String> {
+    Err("built without export-exr, no energies are exported".to_string())
+}
+
+/// The energies of the field rendering into the [`FlowFieldOutput`].
+pub(crate) fn output_field<'a>(
+    output: Option<&FlowFieldOutput>,
+    fields: impl IntoIterator<Item = (&'a FlowFieldSettings, &'a ParticleBuffer, &'a ComputeInput)>,
+) -> Option<(&'a FlowFieldSettings, &'a ParticleBuffer)> {
+    let output = output?;
+    fields
+        .into_iter()
+        .find(|(_, _, input)| input.dst_image == output.0)
+        .map(|(settings, buffers, _)| (settings, buffers))
+}
+
+#[cfg(feature = "export-exr")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn capture_energies(
+    request: Res<EnergyExportRequest>,
+    exiting: Res<Exiting>,
+    pending: Res<PendingExports>,
+    output: Option<Res<FlowFieldOutput>>,
+    variation: Option<Res<crate::SeedVariation>>,
+    fields: Query<(&FlowFieldSettings, &ParticleBuffer, &ComputeInput)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    // No new exports are started once the app exits.
+    if exiting.0 || !request.take() {
+        return;
+    }
+    let Some((settings, buffers)) = output_field(output.as_deref(), &fields) else {
+        warn!("the flow field output isn't ready yet, no energies exported");
+        return;
+    };
+
+    let readback = EnergyReadback::start(settings, buffers, &render_device, &render_queue);
+    let path = PathBuf::from(crate::SCREENSHOT_DIR).join(crate::screenshot::file_name(
+        "energy",
+        variation.as_deref(),
+        "exr",
+    ));
+    let pending = pending.start();
+    thread::spawn(move || {
+        let _pending = pending;
+        match readback.save(&path) {
+            Ok(()) => info!("saved energies to {}", path.display()),
+            Err(err) => error!("could not save energies to {}: {err}", path.display()),
+        }
+    });
+}
+
+/// Writes a single layer OpenEXR image with one 32-bit float channel per entry of `channels`,
+/// compressed losslessly.
+#[cfg(feature = "export-exr")]
+fn write_exr(
+    out: impl Write + Seek,
+    size: UVec2,
+    channels: [(&str, Vec<f32>); 4],
+) -> exr::error::UnitResult {
+    use exr::prelude::{
+        AnyChannel, AnyChannels, Encoding, FlatSamples, Layer, LayerAttributes, WritableImage,
+    };
+
+    let channels = channels
+        .into_iter()
+        .map(|(name, values)| AnyChannel::new(name, FlatSamples::F32(values)))
+        .collect();
+    let layer = Layer::new(
+        (size.x as usize, size.y as usize),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    exr::prelude::Image::from_layer(layer)
+        .write()
+        .to_buffered(out)
+}
+
+#[cfg(all(test, feature = "export-exr"))]
+mod tests {
+    use std::io::Cursor;
+
+    use exr::prelude::{FlatSamples, ReadChannels, ReadLayers};
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let size = UVec2::new(3, 2);
+        let ramp = |scale: f32| (0..6).map(|i| i as f32 * scale).collect::<Vec<_>>();
+        let (r, g, b, density) = (ramp(0.5), ramp(1.0), ramp(0.0), ramp(40.0));
+        let mut bytes = Cursor::new(Vec::new());
+        write_exr(
+            &mut bytes,
+            size,
+            [
+                ("R", r.clone()),
+                ("G", g.clone()),
+                ("B", b.clone()),
+                ("density", density.clone()),
+            ],
+        )
+        .unwrap();
+
+        bytes.set_position(0);
+        let image = exr::prelude::read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(bytes)
+            .unwrap();
+        let layer = &image.layer_data;
+        assert_eq!(layer.size, exr::prelude::Vec2(3, 2));
+        let channels = &layer.channel_data.list;
+        let names: Vec<_> = channels.iter().map(|c| c.name.to_string()).collect();
+        assert_eq!(names, ["B", "G", "R", "density"]);
+        for (channel, expected) in channels.iter().zip([b, g, r, density]) {
+            let FlatSamples::F32(values) = &channel.sample_data else {
+                panic!("channel {} isn't 32-bit float", channel.name);
+            };
+            assert_eq!(*values, expected);
+        }
+    }
+}
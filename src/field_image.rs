@@ -0,0 +1,42 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        texture::{FallbackImage, GpuImage},
+        Extract,
+    },
+};
+
+/// An image whose brightness contours the particles follow, blended with the noise according to
+/// [`FlowFieldParams::image_influence`](crate::FlowFieldParams::image_influence). The noise is used
+/// on its own until the image has loaded.
+#[derive(Resource, Clone, Debug)]
+pub struct FieldImage(pub Handle<Image>);
+
+/// Mirrors the main world resource, including its removal.
+pub(crate) fn extract_field_image(
+    mut commands: Commands,
+    field_image: Extract<Option<Res<FieldImage>>>,
+) {
+    match &*field_image {
+        Some(field_image) => commands.insert_resource(FieldImage::clone(field_image)),
+        None => commands.remove_resource::<FieldImage>(),
+    }
+}
+
+/// The GPU image to sample, `None` while there is no image or it hasn't been uploaded yet.
+pub(crate) fn loaded<'a>(
+    image: Option<&Handle<Image>>,
+    gpu_images: &'a RenderAssets<Image>,
+) -> Option<&'a GpuImage> {
+    gpu_images.get(image?)
+}
+
+/// The texture view to bind, falling back to a blank image that the shader ignores.
+pub(crate) fn view<'a>(
+    image: Option<&Handle<Image>>,
+    gpu_images: &'a RenderAssets<Image>,
+    fallback: &'a FallbackImage,
+) -> &'a GpuImage {
+    loaded(image, gpu_images).unwrap_or(&fallback.d2)
+}
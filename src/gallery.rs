@@ -0,0 +1,221 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_resource::{CommandEncoderDescriptor, Extent3d, TextureUsages},
+        renderer::{RenderDevice, RenderQueue},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{FlowFieldOutput, Presets, ResetSimulation};
+
+/// Switches to the next of the [`Presets`] every `interval` seconds, restarting the particles, and
+/// crossfades from the last image of the previous preset to the new one as it builds up over
+/// `crossfade` seconds. Needs the [`FlowFieldOutput`] to be shown as a sprite, see
+/// [`ComputePlugin::with_sprite`](crate::ComputePlugin::with_sprite).
+pub struct GalleryPlugin {
+    pub interval: f32,
+    pub crossfade: f32,
+}
+
+impl Default for GalleryPlugin {
+    fn default() -> Self {
+        Self {
+            interval: 30.0,
+            crossfade: 2.5,
+        }
+    }
+}
+
+impl Plugin for GalleryPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Gallery {
+            interval: self.interval,
+            crossfade: self.crossfade.max(f32::EPSILON),
+            since_switch: 0.0,
+            fade_started: None,
+        });
+        app.add_systems(PostStartup, spawn_fade);
+        app.add_systems(
+            Update,
+            (next_preset.before(crate::presets::apply_preset), fade_out).chain(),
+        );
+        app.add_plugins(ExtractResourcePlugin::<GalleryFade>::default());
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            capture_previous.in_set(RenderSet::PrepareBindGroups),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct Gallery {
+    interval: f32,
+    crossfade: f32,
+    since_switch: f32,
+    /// When the image of the previous preset started fading out.
+    fade_started: Option<f32>,
+}
+
+/// The last image of the previous preset, shown over the [`FlowFieldOutput`] while fading out.
+#[derive(Resource, Clone, ExtractResource)]
+pub(crate) struct GalleryFade {
+    image: Handle<Image>,
+    /// Copies the output into `image` this frame, before the new preset draws into it.
+    capture: bool,
+}
+
+#[derive(Component)]
+struct FadeSprite;
+
+/// An image sized and formatted like the output, which is copied into it whole.
+fn fade_image(output: &Image) -> Image {
+    let mut image = output.clone();
+    image.texture_descriptor.label = Some("flow field gallery fade");
+    image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST;
+    image
+}
+
+fn spawn_fade(
+    mut commands: Commands,
+    output: Res<FlowFieldOutput>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(output) = images.get(&output.0) else {
+        return;
+    };
+    let image = fade_image(output);
+    let image = images.add(image);
+    commands.spawn((
+        SpriteBundle {
+            texture: image.clone(),
+            // Over the sprite of the flow field.
+            transform: Transform::from_xyz(0.0, 0.0, 1.0),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        FadeSprite,
+    ));
+    commands.insert_resource(GalleryFade {
+        image,
+        capture: false,
+    });
+}
+
+fn next_preset(
+    mut gallery: ResMut<Gallery>,
+    fade: Option<ResMut<GalleryFade>>,
+    mut presets: ResMut<Presets>,
+    mut resets: EventWriter<ResetSimulation>,
+    time: Res<Time>,
+    output: Option<Res<FlowFieldOutput>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(mut fade) = fade else {
+        return;
+    };
+    fade.capture = false;
+    gallery.since_switch += time.delta_seconds();
+    if gallery.since_switch < gallery.interval || presets.presets.len() < 2 {
+        return;
+    }
+    let Some(output) = output.as_ref().and_then(|output| images.get(&output.0)) else {
+        return;
+    };
+    gallery.since_switch = 0.0;
+
+    // The window was resized since the last switch.
+    let size = output.texture_descriptor.size;
+    if images
+        .get(&fade.image)
+        .map(|image| image.texture_descriptor.size)
+        != Some(size)
+    {
+        let image = fade_image(output);
+        fade.image = images.add(image);
+    }
+    fade.capture = true;
+    gallery.fade_started = Some(time.elapsed_seconds());
+
+    let next = (presets.active + 1) % presets.presets.len();
+    presets.select(next);
+    resets.send(ResetSimulation);
+}
+
+#[allow(clippy::type_complexity)]
+fn fade_out(
+    mut gallery: ResMut<Gallery>,
+    time: Res<Time>,
+    fade: Option<Res<GalleryFade>>,
+    output: Option<Res<FlowFieldOutput>>,
+    field_sprites: Query<(&Sprite, &Handle<Image>), Without<FadeSprite>>,
+    mut fade_sprites: Query<(&mut Sprite, &mut Handle<Image>, &mut Visibility), With<FadeSprite>>,
+) {
+    let (Some(fade), Some(output)) = (fade, output) else {
+        return;
+    };
+    let size = field_sprites
+        .iter()
+        .find(|(_, texture)| **texture == output.0)
+        .and_then(|(sprite, _)| sprite.custom_size);
+    let progress = gallery
+        .fade_started
+        .map(|started| (time.elapsed_seconds() - started) / gallery.crossfade);
+    let alpha = match progress {
+        Some(progress) if progress < 1.0 => 1.0 - progress * progress * (3.0 - 2.0 * progress),
+        _ => {
+            gallery.fade_started = None;
+            0.0
+        }
+    };
+
+    for (mut sprite, mut texture, mut visibility) in &mut fade_sprites {
+        if *texture != fade.image {
+            *texture = fade.image.clone();
+        }
+        sprite.custom_size = size;
+        sprite.color.set_a(alpha);
+        *visibility = match alpha > 0.0 {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+    }
+}
+
+/// Copies the image of the previous preset before the graph draws the first frame of the next.
+fn capture_previous(
+    fade: Option<Res<GalleryFade>>,
+    output: Option<Res<FlowFieldOutput>>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let (Some(fade), Some(output)) = (fade, output) else {
+        return;
+    };
+    if !fade.capture {
+        return;
+    }
+    let (Some(source), Some(destination)) =
+        (gpu_images.get(&output.0), gpu_images.get(&fade.image))
+    else {
+        return;
+    };
+    if source.size != destination.size {
+        return;
+    }
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_texture(
+        source.texture.as_image_copy(),
+        destination.texture.as_image_copy(),
+        Extent3d {
+            width: source.size.x as u32,
+            height: source.size.y as u32,
+            depth_or_array_layers: 1,
+        },
+    );
+    render_queue.submit([encoder.finish()]);
+}
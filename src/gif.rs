@@ -0,0 +1,520 @@
+#[cfg(feature = "export-png")]
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bevy::prelude::*;
+#[cfg(feature = "export-png")]
+use bevy::{
+    reflect::TypeUuid,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+            BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType,
+            BufferDescriptor, BufferInitDescriptor, BufferUsages, CachedComputePipelineId,
+            CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor,
+            PipelineCache, ShaderStages, TextureSampleType, TextureViewDimension,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+#[cfg(feature = "export-png")]
+use crate::{
+    poll,
+    shutdown::{Exiting, PendingExports},
+    FlowFieldOutput, SimulatedFrames,
+};
+
+/// The embedded downsampling shader of GIF captures.
+#[cfg(feature = "export-png")]
+pub const GIF_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x3d6a_91c4_f08e_2b57);
+
+/// How [`GifRequest`]s capture the [`FlowFieldOutput`](crate::FlowFieldOutput).
+#[derive(Resource, Clone, Debug)]
+pub struct GifSettings {
+    pub seconds: f32,
+    pub fps: u32,
+    /// Wider outputs are scaled down to this width, keeping their aspect ratio.
+    pub max_width: u32,
+    /// Captures whose frames would take more bytes than this are refused. The frames are kept
+    /// in memory until the GIF is encoded, at 4 bytes per pixel.
+    pub max_memory: u64,
+}
+
+impl Default for GifSettings {
+    fn default() -> Self {
+        Self {
+            seconds: 5.0,
+            fps: 25,
+            max_width: 640,
+            max_memory: 512 << 20,
+        }
+    }
+}
+
+#[cfg(feature = "export-png")]
+impl GifSettings {
+    /// Size of the frames of an output image of `size`.
+    fn frame_size(&self, size: UVec2) -> UVec2 {
+        let width = size.x.min(self.max_width.max(1));
+        let height = (size.y as u64 * width as u64 / size.x as u64).max(1) as u32;
+        UVec2::new(width, height)
+    }
+
+    fn frame_count(&self) -> u64 {
+        ((self.seconds * self.fps as f32).round() as u64).max(1)
+    }
+}
+
+/// Asks for the next [`GifSettings::seconds`] of the [`FlowFieldOutput`](crate::FlowFieldOutput) to be captured and saved
+/// to a path as a looping GIF. The frames are scaled down on the GPU, read back into memory and
+/// quantized to a single palette once the capture is over, on a background thread. Shared with the
+/// render world like the [`StepRequest`](crate::StepRequest). Without the `export-png` feature
+/// requests are logged and dropped.
+#[derive(Resource, Clone, Default)]
+pub struct GifRequest(Arc<Mutex<Option<(PathBuf, GifSettings)>>>);
+
+impl GifRequest {
+    pub fn request(&self, path: impl Into<PathBuf>, settings: GifSettings) {
+        if cfg!(not(feature = "export-png")) {
+            warn!("built without export-png, no GIF is captured");
+            return;
+        }
+        *self.0.lock().unwrap() = Some((path.into(), settings));
+    }
+
+    #[cfg(feature = "export-png")]
+    fn take(&self) -> Option<(PathBuf, GifSettings)> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+#[cfg(feature = "export-png")]
+#[derive(Resource)]
+pub(crate) struct GifPipeline {
+    bind_group_layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+#[cfg(feature = "export-png")]
+impl FromWorld for GifPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("gif downsample"),
+                    entries: &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let shader = GIF_SHADER_HANDLE.typed();
+        let pipeline =
+            world
+                .resource::<PipelineCache>()
+                .queue_compute_pipeline(ComputePipelineDescriptor {
+                    label: Some("gif downsample".into()),
+                    layout: vec![bind_group_layout.clone()],
+                    push_constant_ranges: Vec::new(),
+                    shader,
+                    shader_defs: Vec::new(),
+                    entry_point: Cow::from("downsample"),
+                });
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+}
+
+/// Render world side of the [`GifRequest`]s: the capture in progress and the threads encoding
+/// finished ones.
+#[cfg(feature = "export-png")]
+#[derive(Resource, Default)]
+pub(crate) struct GifCapture {
+    capture: Option<Capture>,
+    encoders: Vec<JoinHandle<()>>,
+}
+
+#[cfg(feature = "export-png")]
+struct Capture {
+    fps: u32,
+    frame_count: u64,
+    size: UVec2,
+    /// When the first frame was captured, the GIF time is measured from there.
+    start: Option<Instant>,
+    /// GIF frames captured so far, repeats included.
+    emitted: u64,
+    /// The downsampled pixels, written by the shader.
+    pixels: Buffer,
+    sizes: Buffer,
+    frames: Sender<Frame>,
+}
+
+#[cfg(feature = "export-png")]
+struct Frame {
+    staging: Buffer,
+    mapped: Receiver<Result<(), String>>,
+    /// How many GIF frames this one fills.
+    repeats: u64,
+}
+
+#[cfg(feature = "export-png")]
+impl GifCapture {
+    fn start(
+        &mut self,
+        path: PathBuf,
+        settings: &GifSettings,
+        source: UVec2,
+        pending: &PendingExports,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+    ) {
+        let size = settings.frame_size(source);
+        let frame_count = settings.frame_count();
+        let memory = frame_count * 4 * size.x as u64 * size.y as u64;
+        if memory > settings.max_memory {
+            error!(
+                "a {} s GIF at {} fps and {}x{} needs {} MB for its frames, more than the limit of \
+                 {} MB, nothing is captured",
+                settings.seconds,
+                settings.fps,
+                size.x,
+                size.y,
+                memory >> 20,
+                settings.max_memory >> 20
+            );
+            return;
+        }
+
+        let pixels = render_device.create_buffer(&BufferDescriptor {
+            label: Some("gif pixels"),
+            size: 4 * size.x as u64 * size.y as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let sizes = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gif sizes"),
+            contents: bytemuck::cast_slice(&[source.x, source.y, size.x, size.y]),
+            usage: BufferUsages::UNIFORM,
+        });
+        let (sender, receiver) = mpsc::channel();
+        let fps = settings.fps.max(1);
+        let render_queue = render_queue.clone();
+        let pending = pending.start();
+        self.encoders.push(thread::spawn(move || {
+            let _pending = pending;
+            encode(receiver, &path, size, fps, &render_queue)
+        }));
+        self.capture = Some(Capture {
+            fps,
+            frame_count,
+            size,
+            start: None,
+            emitted: 0,
+            pixels,
+            sizes,
+            frames: sender,
+        });
+        info!("capturing a GIF at {}x{}", size.x, size.y);
+    }
+
+    /// Waits until every finished capture has been written.
+    fn flush(&mut self) {
+        self.capture = None;
+        for encoder in self.encoders.drain(..) {
+            if encoder.join().is_err() {
+                error!("a GIF thread panicked");
+            }
+        }
+    }
+
+    /// Ends the capture in progress and lets the encoders finish on their own, counted in the
+    /// [`PendingExports`].
+    fn shutdown(&mut self) {
+        self.capture = None;
+        self.encoders.clear();
+    }
+}
+
+#[cfg(feature = "export-png")]
+impl Drop for GifCapture {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Downsamples and copies the output image after the frame has been submitted, paced to the
+/// frame rate of the GIF.
+#[cfg(feature = "export-png")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn capture_gif(
+    mut gif: ResMut<GifCapture>,
+    request: Res<GifRequest>,
+    pipeline: Res<GifPipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    exiting: Res<Exiting>,
+    pending: Res<PendingExports>,
+    frames: Res<SimulatedFrames>,
+    output: Option<Res<FlowFieldOutput>>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    gif.encoders.retain(|encoder| !encoder.is_finished());
+    if exiting.0 {
+        // Encodes what has been captured so far.
+        gif.shutdown();
+        return;
+    }
+    let image = output.and_then(|output| gpu_images.get(&output.0));
+    let program = pipeline_cache.get_compute_pipeline(pipeline.pipeline);
+    // Waits for the simulation to start, so a capture requested on startup doesn't begin with
+    // the empty image.
+    let (Some(image), Some(program), true) = (image, program, frames.0 > 0) else {
+        return;
+    };
+
+    if let Some((path, settings)) = request.take() {
+        let source = UVec2::new(image.size.x as u32, image.size.y as u32);
+        gif.start(
+            path,
+            &settings,
+            source,
+            &pending,
+            &render_device,
+            &render_queue,
+        );
+    }
+    let Some(capture) = gif.capture.as_mut() else {
+        return;
+    };
+
+    let now = Instant::now();
+    let elapsed = now - *capture.start.get_or_insert(now);
+    let due = ((elapsed.as_secs_f64() * capture.fps as f64) as u64 + 1).min(capture.frame_count);
+    let repeats = due.saturating_sub(capture.emitted);
+    if repeats == 0 {
+        if capture.emitted == capture.frame_count {
+            // Closing the channel lets the thread encode the GIF.
+            gif.capture = None;
+        }
+        return;
+    }
+
+    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some("gif downsample"),
+        layout: &pipeline.bind_group_layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&image.texture_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: capture.pixels.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: capture.sizes.as_entire_binding(),
+            },
+        ],
+    });
+    let staging = render_device.create_buffer(&BufferDescriptor {
+        label: Some("gif readback"),
+        size: capture.pixels.size(),
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+    {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("gif downsample"),
+        });
+        pass.set_pipeline(program);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(capture.size.x.div_ceil(8), capture.size.y.div_ceil(8), 1);
+    }
+    encoder.copy_buffer_to_buffer(&capture.pixels, 0, &staging, 0, staging.size());
+    render_queue.submit([encoder.finish()]);
+
+    let mapped = poll::map_read(&render_device, &staging);
+    let frame = Frame {
+        staging,
+        mapped,
+        repeats,
+    };
+    // The encoder only stops early if it panicked.
+    let _ = capture.frames.send(frame);
+    capture.emitted = due;
+}
+
+/// Collects the frames of a capture and writes the GIF once the capture is over.
+#[cfg(feature = "export-png")]
+fn encode(frames: Receiver<Frame>, path: &Path, size: UVec2, fps: u32, render_queue: &RenderQueue) {
+    let mut stash = Vec::new();
+    for frame in frames {
+        // Blocks this thread rather than the renderer until the copy is done and mapped.
+        match poll::wait_for_map(render_queue, &frame.mapped).expect("the GIF buffer was dropped") {
+            Ok(()) => {
+                let pixels = frame.staging.slice(..).get_mapped_range().to_vec();
+                frame.staging.unmap();
+                stash.push((pixels, frame.repeats));
+            }
+            Err(err) => error!("could not read a GIF frame back: {err}"),
+        }
+    }
+    if stash.is_empty() {
+        return;
+    }
+
+    let saved = (|| -> Result<(), gif::EncodingError> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        write_gif(&mut file, size, fps, &stash)?;
+        Ok(file.flush()?)
+    })();
+    match saved {
+        Ok(()) => info!("saved {} GIF frames to {}", stash.len(), path.display()),
+        Err(err) => error!("could not save GIF to {}: {err}", path.display()),
+    }
+}
+
+/// Pixels sampled from all frames to build the palette from, enough for
+/// [`color_quant::NeuQuant`] to see every part of the animation.
+#[cfg(feature = "export-png")]
+const PALETTE_SAMPLES: usize = 1 << 20;
+
+/// Writes a looping GIF of `frames`, RGBA pixels each shown for their number of repeats at `fps`,
+/// with a single palette quantized from all of them.
+#[cfg(feature = "export-png")]
+fn write_gif(
+    out: &mut impl Write,
+    size: UVec2,
+    fps: u32,
+    frames: &[(Vec<u8>, u64)],
+) -> Result<(), gif::EncodingError> {
+    let pixel_count: usize = frames.iter().map(|(pixels, _)| pixels.len() / 4).sum();
+    let step = pixel_count.div_ceil(PALETTE_SAMPLES).max(1);
+    let samples: Vec<u8> = frames
+        .iter()
+        .flat_map(|(pixels, _)| pixels.chunks_exact(4))
+        .step_by(step)
+        .flatten()
+        .copied()
+        .collect();
+    let quantizer = color_quant::NeuQuant::new(10, 256, &samples);
+
+    let (width, height) = (size.x as u16, size.y as u16);
+    let mut encoder = gif::Encoder::new(out, width, height, &quantizer.color_map_rgb())?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    let mut indices = HashMap::new();
+    let mut shown = 0;
+    for (pixels, repeats) in frames {
+        // Delays are in hundredths of a second, rounded so they add up to the right total.
+        let centiseconds = |frames: u64| (frames * 100 + fps as u64 / 2) / fps as u64;
+        let delay = centiseconds(shown + repeats) - centiseconds(shown);
+        shown += repeats;
+
+        let buffer: Vec<u8> = pixels
+            .chunks_exact(4)
+            .map(|pixel| {
+                let key = u32::from_le_bytes(pixel.try_into().unwrap());
+                *indices
+                    .entry(key)
+                    .or_insert_with(|| quantizer.index_of(pixel) as u8)
+            })
+            .collect();
+        encoder.write_frame(&gif::Frame {
+            width,
+            height,
+            delay: delay.min(u16::MAX as u64) as u16,
+            dispose: gif::DisposalMethod::Keep,
+            buffer: Cow::Owned(buffer),
+            ..default()
+        })?;
+    }
+    encoder.into_inner()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "export-png"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let size = UVec2::new(40, 30);
+        let solid = |color: [u8; 4]| color.repeat((size.x * size.y) as usize);
+        let frames = [
+            (solid([255, 0, 0, 255]), 1),
+            (solid([0, 0, 255, 255]), 2),
+            (solid([255, 255, 255, 255]), 1),
+        ];
+        let mut bytes = Vec::new();
+        write_gif(&mut bytes, size, 10, &frames).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(bytes.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (40, 30));
+        let mut delays = Vec::new();
+        for (pixels, _) in &frames {
+            let frame = decoder.read_next_frame().unwrap().unwrap();
+            delays.push(frame.delay);
+            for (decoded, expected) in frame.buffer.chunks_exact(4).zip(pixels.chunks_exact(4)) {
+                for (decoded, expected) in decoded.iter().zip(expected) {
+                    assert!(decoded.abs_diff(*expected) <= 8, "{decoded} vs {expected}");
+                }
+            }
+        }
+        assert_eq!(delays, [10, 20, 10]);
+        assert!(decoder.read_next_frame().unwrap().is_none());
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::{FlowFieldSettings, InputBindings, Presets, PrimaryFlowField, Seed, SeedVariation};
+
+/// Columns the actions are spread over.
+const COLUMNS: usize = 3;
+
+/// Whether the help overlay is open. While it is, the keyboard controls ignore every key.
+#[derive(Resource, Clone, Copy, Default, Debug)]
+pub struct HelpOverlay {
+    pub open: bool,
+}
+
+/// A semi-transparent overlay listing every action with its key from the [`InputBindings`], and the
+/// active preset and seed below them. [`InputBindings::toggle_help`] opens it, the same key or
+/// `Escape` closes it.
+pub struct HelpOverlayPlugin;
+
+impl Plugin for HelpOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HelpOverlay>();
+        app.init_resource::<InputBindings>();
+        app.add_systems(Startup, spawn_overlay);
+        app.add_systems(Update, (toggle_help, update_overlay).chain());
+    }
+}
+
+/// Run condition for the systems reacting to keys, false while the help overlay is open.
+pub(crate) fn help_closed(help: Option<Res<HelpOverlay>>) -> bool {
+    help.is_none_or(|help| !help.open)
+}
+
+#[derive(Component)]
+struct HelpRoot;
+
+#[derive(Component)]
+struct HelpColumn(usize);
+
+#[derive(Component)]
+struct HelpFooter;
+
+fn text() -> TextBundle {
+    TextBundle::from_section(
+        "",
+        TextStyle {
+            font_size: 16.0,
+            color: Color::WHITE,
+            ..default()
+        },
+    )
+}
+
+fn spawn_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+                visibility: Visibility::Hidden,
+                // Over the other overlays.
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            HelpRoot,
+        ))
+        .with_children(|root| {
+            root.spawn(NodeBundle {
+                style: Style {
+                    column_gap: Val::Px(32.0),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|columns| {
+                for column in 0..COLUMNS {
+                    columns.spawn((text(), HelpColumn(column)));
+                }
+            });
+            root.spawn((
+                text().with_style(Style {
+                    margin: UiRect::top(Val::Px(16.0)),
+                    ..default()
+                }),
+                HelpFooter,
+            ));
+        });
+}
+
+fn toggle_help(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut help: ResMut<HelpOverlay>,
+) {
+    if bindings.toggle_help.just_pressed(&keys) || (help.open && keys.just_pressed(KeyCode::Escape))
+    {
+        help.open = !help.open;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_overlay(
+    help: Res<HelpOverlay>,
+    bindings: Res<InputBindings>,
+    presets: Res<Presets>,
+    seed: Res<Seed>,
+    variation: Res<SeedVariation>,
+    field: Query<&FlowFieldSettings, With<PrimaryFlowField>>,
+    mut root: Query<&mut Visibility, With<HelpRoot>>,
+    mut columns: Query<(&mut Text, &HelpColumn), Without<HelpFooter>>,
+    mut footer: Query<&mut Text, With<HelpFooter>>,
+) {
+    if !help.is_changed() {
+        return;
+    }
+    for mut visibility in &mut root {
+        *visibility = match help.open {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+    }
+    if !help.open {
+        return;
+    }
+
+    // Written when opening, so the overlay shows the bindings and seed of that moment.
+    let actions = bindings.actions();
+    let per_column = actions.len().div_ceil(COLUMNS);
+    for (mut text, column) in &mut columns {
+        let value = &mut text.sections[0].value;
+        value.clear();
+        for (name, binding) in actions.iter().skip(column.0 * per_column).take(per_column) {
+            let _ = writeln!(value, "{binding}: {}", name.replace('_', " "));
+        }
+    }
+    for mut text in &mut footer {
+        let value = &mut text.sections[0].value;
+        value.clear();
+        let preset = presets.active().map_or("none", |preset| &preset.name);
+        let _ = write!(value, "preset: {preset}");
+        if let Some(index) = variation.index {
+            let _ = write!(value, ", variation {index}");
+        }
+        // A seed of the field itself takes precedence over the global one.
+        let field_seed = field.get_single().ok().and_then(|settings| settings.seed);
+        let _ = write!(value, "\nseed: {}", field_seed.unwrap_or(seed.0));
+    }
+}
@@ -0,0 +1,62 @@
+use bevy::{
+    app::AppExit,
+    input::mouse::MouseMotion,
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+
+use crate::{help, InputBindings};
+
+/// Seconds without mouse movement before the cursor is hidden.
+const CURSOR_TIMEOUT: f32 = 3.0;
+
+/// Installation mode for unattended screens: switches the primary window to borderless
+/// fullscreen, hides the cursor once the mouse has been still for a few seconds and exits only on
+/// `Ctrl` + `Shift` + `Q`, see [`InputBindings::exit`]. Closing the window should be disabled as well, with
+/// [`WindowPlugin::close_when_requested`].
+pub struct KioskPlugin;
+
+impl Plugin for KioskPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputBindings>();
+        app.add_systems(Startup, enter_fullscreen);
+        app.add_systems(
+            Update,
+            (hide_idle_cursor, exit_on_shortcut.run_if(help::help_closed)),
+        );
+    }
+}
+
+fn enter_fullscreen(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    for mut window in &mut windows {
+        window.mode = WindowMode::BorderlessFullscreen;
+    }
+}
+
+fn hide_idle_cursor(
+    mut last_motion: Local<f32>,
+    mut motion: EventReader<MouseMotion>,
+    time: Res<Time>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let now = time.raw_elapsed_seconds();
+    if motion.iter().count() > 0 {
+        *last_motion = now;
+    }
+    let visible = now - *last_motion < CURSOR_TIMEOUT;
+    for mut window in &mut windows {
+        if window.cursor.visible != visible {
+            window.cursor.visible = visible;
+        }
+    }
+}
+
+fn exit_on_shortcut(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if bindings.exit.just_pressed(&keys) {
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,117 @@
+mod batch;
+mod bindings;
+mod brush;
+mod buffers;
+mod config;
+mod controls;
+mod custom_field;
+mod debug_view;
+mod drift;
+mod exr;
+mod field_image;
+mod gallery;
+mod gif;
+mod help;
+mod interpolation;
+mod kiosk;
+mod magnifier;
+mod node;
+mod obstacles;
+mod pacing;
+mod palette;
+#[cfg(feature = "ui")]
+mod panel;
+mod params;
+mod particle;
+mod pipeline;
+mod plot;
+mod plugin;
+mod pointer;
+mod poll;
+mod presets;
+mod primitives;
+mod recording;
+mod replay;
+mod screenshot;
+mod sdf;
+mod shuffle;
+mod shutdown;
+mod snapshot;
+mod species;
+mod sprite;
+mod stats;
+mod target;
+#[cfg(feature = "debug-validate")]
+mod validate;
+mod vector_field;
+#[cfg(feature = "export-video")]
+mod video;
+mod view;
+
+pub use batch::{BatchRenderPlugin, BatchStatus};
+pub use bindings::{InputBindings, KeyBinding};
+pub use brush::Brush;
+pub use buffers::{ComputeInput, ParticleBuffer, ParticleBufferInfo};
+pub use config::{ConfigError, FlowFieldConfig};
+pub use controls::FlowFieldControlsPlugin;
+pub use custom_field::{CustomField, CUSTOM_FIELD_IMPORT_PATH, CUSTOM_FIELD_SHADER_HANDLE};
+pub use debug_view::{DebugViewPlugin, DEBUG_VIEW_SHADER_HANDLE};
+pub use drift::{AutoDrift, AutoDriftPlugin};
+pub use exr::EnergyExportRequest;
+pub use field_image::FieldImage;
+pub use gallery::GalleryPlugin;
+#[cfg(feature = "export-png")]
+pub use gif::GIF_SHADER_HANDLE;
+pub use gif::{GifRequest, GifSettings};
+pub use help::{HelpOverlay, HelpOverlayPlugin};
+pub use interpolation::ParamInterpolation;
+pub use kiosk::KioskPlugin;
+pub use magnifier::{Magnifier, MagnifierPlugin};
+pub use node::{ComputeNode, NODE_NAME};
+pub use obstacles::{Obstacle, Obstacles};
+pub use pacing::{FramePacingPlugin, FrameRateLimit};
+pub use palette::Palette;
+#[cfg(feature = "ui")]
+pub use panel::ControlPanelPlugin;
+pub use params::{
+    AgeCurve, BoundaryMode, ColorMode, DisplayMode, FieldType, FlowFieldParams, FlowFieldUniform,
+    Tonemap,
+};
+pub use particle::{InitialVelocity, MassDistribution, Particle, ParticleColoring};
+pub use pipeline::{ComputeBindGroup, ComputePipeline, SHADER_HANDLE};
+pub use plot::{PlotStyle, Trajectories};
+pub use plugin::{
+    ComputePlugin, FilmEffects, FlowFieldBundle, FlowFieldOutput, FlowFieldReadyEvent,
+    FlowFieldSet, FlowFieldSettings, FlowFieldStatus, MemoryBudget, OutputFormat, PrimaryFlowField,
+    ResetSimulation, Seed, SeedVariation, SimulationSpeed, SimulationState, StepRequest, Substeps,
+    Timestep, WindowSizing, WorkgroupSize,
+};
+pub use pointer::{field_to_world, world_to_field, Pointer, PointerForce};
+pub use presets::{Preset, Presets};
+pub use primitives::{FieldPrimitive, FieldPrimitives, PrimitiveKind};
+pub use recording::Recording;
+pub use replay::{Change, Session, SessionChange, SessionRecorderPlugin, SessionReplayPlugin};
+pub use screenshot::{ScreenshotRequest, SCREENSHOT_DIR};
+pub use sdf::{mask_from_image, sdf_image, signed_distance_field, ObstacleMask, ObstacleSdf};
+pub use shutdown::{PendingExports, SHUTDOWN_TIMEOUT};
+pub use snapshot::{SnapshotError, Snapshots, SNAPSHOT_FILE};
+pub use species::{FlowFieldSpecies, Species};
+pub use stats::StatsOverlayPlugin;
+pub use target::FlowFieldTarget;
+#[cfg(feature = "debug-validate")]
+pub use validate::ValidationInterval;
+pub use vector_field::VectorField;
+#[cfg(feature = "export-video")]
+pub use video::VideoExportPlugin;
+
+use obstacles::ObstaclesBuffer;
+use palette::PaletteTexture;
+use params::{SimParams, SimParamsBuffer};
+use plugin::{SharedStatus, SimulatedFrames};
+use primitives::FieldPrimitivesBuffer;
+use vector_field::VectorFieldTexture;
+
+/// Location of the compute shader in the asset folder of this repository. The shader is embedded
+/// in the library, pass this to [`ComputePlugin::with_shader_path`] to hot reload it while working
+/// on it.
+pub const SHADER_ASSET_PATH: &str = "shaders/flow_field.wgsl";
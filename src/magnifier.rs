@@ -0,0 +1,143 @@
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
+
+use crate::{
+    help,
+    pointer::{self, FieldSprites},
+    ComputeInput, FlowFieldSettings, InputBindings, PrimaryFlowField,
+};
+
+/// Logical pixels between the inset and the corner of the window.
+const MARGIN: f32 = 16.0;
+
+/// A picture-in-picture view in the bottom right corner of the primary window, magnifying the
+/// sprite of the [`PrimaryFlowField`] around the cursor.
+#[derive(Resource, Clone, Debug)]
+pub struct Magnifier {
+    pub enabled: bool,
+    /// How many times the region around the cursor is enlarged.
+    pub zoom: f32,
+    /// Side of the square inset as a fraction of the shorter side of the window.
+    pub size: f32,
+}
+
+impl Default for Magnifier {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            zoom: 4.0,
+            size: 0.3,
+        }
+    }
+}
+
+/// Draws the [`Magnifier`] with a second camera looking at the same sprite, `Shift` + `Tab` shows
+/// or hides it. The magnified region follows the cursor and stays within the flow field.
+pub struct MagnifierPlugin;
+
+impl Plugin for MagnifierPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Magnifier>();
+        app.init_resource::<InputBindings>();
+        app.add_systems(Startup, spawn_camera);
+        app.add_systems(
+            Update,
+            (toggle_magnifier.run_if(help::help_closed), update_magnifier).chain(),
+        );
+    }
+}
+
+#[derive(Component)]
+struct MagnifierCamera;
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                // Drawn over the main view.
+                order: 1,
+                is_active: false,
+                ..default()
+            },
+            ..default()
+        },
+        UiCameraConfig { show_ui: false },
+        MagnifierCamera,
+    ));
+}
+
+fn toggle_magnifier(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut magnifier: ResMut<Magnifier>,
+) {
+    if bindings.toggle_magnifier.just_pressed(&keys) {
+        magnifier.enabled = !magnifier.enabled;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_magnifier(
+    magnifier: Res<Magnifier>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), Without<MagnifierCamera>>,
+    sprites: FieldSprites,
+    field: Query<(&FlowFieldSettings, &ComputeInput), With<PrimaryFlowField>>,
+    mut inset: Query<
+        (&mut Camera, &mut Transform, &mut OrthographicProjection),
+        With<MagnifierCamera>,
+    >,
+) {
+    let (Ok(window), Ok((mut camera, mut transform, mut projection))) =
+        (windows.get_single(), inset.get_single_mut())
+    else {
+        return;
+    };
+    if camera.is_active != magnifier.enabled {
+        camera.is_active = magnifier.enabled;
+    }
+    if !magnifier.enabled {
+        return;
+    }
+
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+    let margin = (MARGIN * window.scale_factor() as f32) as u32;
+    let side = ((window_size.min_element() as f32 * magnifier.size) as u32).clamp(
+        1,
+        window_size.min_element().saturating_sub(2 * margin).max(1),
+    );
+    let position = window_size.saturating_sub(UVec2::splat(side + margin));
+    let moved = camera.viewport.as_ref().is_none_or(|viewport| {
+        viewport.physical_position != position || viewport.physical_size != UVec2::splat(side)
+    });
+    if moved {
+        camera.viewport = Some(Viewport {
+            physical_position: position,
+            physical_size: UVec2::splat(side),
+            ..default()
+        });
+    }
+    let scale = 1.0 / magnifier.zoom.max(1.0);
+    if projection.scale != scale {
+        projection.scale = scale;
+    }
+
+    let Some(cursor) = pointer::cursor_world_position(&windows, &cameras) else {
+        return;
+    };
+    let Ok((settings, input)) = field.get_single() else {
+        return;
+    };
+    let Some((sprite, sprite_transform)) = pointer::field_sprite(&sprites, input) else {
+        return;
+    };
+    // Half the magnified region in pixels of the field, assuming the sprite isn't rotated.
+    let field_size = Vec2::new(settings.width as f32, settings.height as f32);
+    let (sprite_scale, _, _) = sprite_transform.to_scale_rotation_translation();
+    let sprite_size = sprite.custom_size.unwrap_or(field_size) * sprite_scale.truncate();
+    let view = side as f32 / window.scale_factor() as f32 * scale;
+    let half = (Vec2::splat(view / 2.0) * field_size / sprite_size.abs()).min(field_size / 2.0);
+    let center = pointer::world_to_field(cursor, settings, sprite, sprite_transform)
+        .clamp(half, field_size - half);
+    let center = pointer::field_to_world(center, settings, sprite, sprite_transform);
+    transform.translation = center.extend(transform.translation.z);
+}
@@ -1,332 +1,458 @@
-use std::borrow::Cow;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use bevy::{
+    app::ScheduleRunnerPlugin,
     prelude::*,
-    render::{
-        extract_resource::{ExtractResource, ExtractResourcePlugin},
-        render_asset::RenderAssets,
-        render_graph::{self, RenderGraph},
-        render_resource::{
-            encase, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
-            BufferBinding, BufferBindingType, BufferInitDescriptor, BufferUsages,
-            CachedComputePipelineId, CachedPipelineState, ComputePassDescriptor,
-            ComputePipelineDescriptor, Extent3d, PipelineCache, ShaderDefVal, ShaderStages,
-            ShaderType, StorageTextureAccess, TextureDimension, TextureFormat, TextureUsages,
-            TextureViewDimension, BufferDescriptor,
-        },
-        renderer::RenderDevice,
-        Render, RenderApp, RenderSet,
-    },
+    window::{ExitCondition, WindowMode},
+    winit::WinitPlugin,
+};
+use creative_coding::{
+    AutoDriftPlugin, BatchRenderPlugin, BatchStatus, DebugViewPlugin, FieldImage, FlowFieldConfig,
+    FlowFieldControlsPlugin, FramePacingPlugin, GalleryPlugin, GifRequest, GifSettings,
+    HelpOverlayPlugin, KioskPlugin, MagnifierPlugin, ObstacleMask, Palette, PlotStyle, Recording,
+    Session, SessionRecorderPlugin, SessionReplayPlugin, Snapshots, StatsOverlayPlugin,
 };
 
-const SIZE: (u32, u32) = (1280, 720);
-const WORKGROUP_SIZE: u32 = 256;
-const NR_PARTICLES: u32 = WORKGROUP_SIZE * 128;
+const DEFAULT_CONFIG_FILE: &str = "flow_field.ron";
+const DEFAULT_OUT_FILE: &str = "flow_field.png";
+const DEFAULT_PLOT_FILE: &str = "flow_field.svg";
+/// Time step of recorded sessions.
+const SESSION_DT: f32 = 1.0 / 60.0;
+#[cfg(feature = "export-video")]
+const DEFAULT_VIDEO_FPS: u32 = 60;
+
+const USAGE: &str = "usage: flow_fields [options]
+
+options:
+    --config <path>      config file to load (default: flow_field.ron next to the binary)
+    --dump-config        print the default config and exit
+    --width <pixels>     initial window width, or the simulation width without one
+    --height <pixels>    initial window height, or the simulation height without one
+    --scale <factor>     simulate at this fraction of the window resolution, e.g. 0.5 for half
+    --particles <count>  number of particles
+    --supersample <n>    accumulate the trails at 1, 2 or 4 times the resolution
+    --seed <seed>        seed for the initial particle state
+    --image <path>       follow the contours of an image in the assets folder
+    --obstacles <path>   flow around the dark shapes of an image in the assets folder
+    --palette <path>     color the trails with a .hex file, or an image strip in the assets folder
+    --fullscreen         start in borderless fullscreen, F11 toggles it
+    --kiosk              installation mode: fullscreen, the cursor hides when idle and only
+                         Ctrl+Shift+Q exits
+    --max-fps <fps>      cap the frame rate, Ctrl+V toggles vsync
+    --ambient            let the parameters drift slowly by themselves, W toggles it
+    --gallery <seconds>  switch to the next preset this often, crossfading between them
+    --debug-view         open a second window showing the raw trail energies
+    --shader-path <path> load the compute shader from the assets folder instead of the built-in
+                         one and reload it on changes, e.g. shaders/flow_field.wgsl
+    --record             write every frame to the frames folder from the start, F9 toggles it
+    --load-state <path>  resume a simulation saved with Ctrl+S, its frames count towards --frames
+    --record-session <path>
+                         log every parameter change at a fixed time step, for --replay
+    --replay <path>      play a session recorded with --record-session back
+    --frames <count>     simulate this many frames, save the image and exit
+    --out <path>         where --frames saves the image (default: flow_field.png)
+    --exr <path>         also save the raw trail energies of --frames as OpenEXR
+    --plot-frames <n>    like --frames, but saves the paths of the particles as an SVG for plotters
+    --plot-out <path>    where the SVG is saved (default: flow_field.svg)
+    --headless           run without a window, needs --frames or --plot-frames
+    --video <path>       encode the output with ffmpeg while running, needs the export-video feature
+    --video-fps <fps>    frame rate of --video (default: 60)
+    --gif <path>         capture a looping GIF from the start, Ctrl+F12 captures one to screenshots
+    --gif-seconds <s>    length of GIF captures (default: 5)
+    --gif-fps <fps>      frame rate of GIF captures (default: 25)
+    --help               print this message";
 
-#[derive(Resource, Clone, ExtractResource)]
-pub struct ComputeInput {
-    dst_image: Handle<Image>,
+#[derive(Default)]
+struct Args {
+    config: Option<PathBuf>,
+    dump_config: bool,
+    width: Option<u32>,
+    height: Option<u32>,
+    scale: Option<f32>,
+    particles: Option<u32>,
+    supersample: Option<u32>,
+    seed: Option<u64>,
+    image: Option<String>,
+    obstacles: Option<String>,
+    palette: Option<String>,
+    fullscreen: bool,
+    kiosk: bool,
+    ambient: bool,
+    gallery: Option<f32>,
+    max_fps: Option<f32>,
+    debug_view: bool,
+    shader_path: Option<String>,
+    record: bool,
+    load_state: Option<PathBuf>,
+    record_session: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    frames: Option<u64>,
+    out: Option<PathBuf>,
+    exr: Option<PathBuf>,
+    plot: Option<PathBuf>,
+    headless: bool,
+    video: Option<PathBuf>,
+    video_fps: Option<u32>,
+    gif: Option<PathBuf>,
+    gif_seconds: Option<f32>,
+    gif_fps: Option<u32>,
 }
 
-pub struct ComputePlugin;
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut plot_frames = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => parsed.config = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--dump-config" => parsed.dump_config = true,
+                "--width" => parsed.width = Some(non_zero(&arg, args.next())?),
+                "--height" => parsed.height = Some(non_zero(&arg, args.next())?),
+                "--scale" => match number(&arg, args.next())? {
+                    scale if scale > 0.0 => parsed.scale = Some(scale),
+                    _ => return Err(format!("`{arg}` must be greater than zero")),
+                },
+                "--particles" => parsed.particles = Some(non_zero(&arg, args.next())?),
+                "--supersample" => parsed.supersample = Some(non_zero(&arg, args.next())?),
+                "--seed" => parsed.seed = Some(number(&arg, args.next())?),
+                "--image" => parsed.image = Some(value(&arg, args.next())?),
+                "--obstacles" => parsed.obstacles = Some(value(&arg, args.next())?),
+                "--palette" => parsed.palette = Some(value(&arg, args.next())?),
+                "--fullscreen" => parsed.fullscreen = true,
+                "--kiosk" => parsed.kiosk = true,
+                "--ambient" => parsed.ambient = true,
+                "--gallery" => match number(&arg, args.next())? {
+                    seconds if seconds > 0.0 => parsed.gallery = Some(seconds),
+                    _ => return Err(format!("`{arg}` must be greater than zero")),
+                },
+                "--max-fps" => match number(&arg, args.next())? {
+                    fps if fps > 0.0 => parsed.max_fps = Some(fps),
+                    _ => return Err(format!("`{arg}` must be greater than zero")),
+                },
+                "--debug-view" => parsed.debug_view = true,
+                "--shader-path" => parsed.shader_path = Some(value(&arg, args.next())?),
+                "--record" => parsed.record = true,
+                "--load-state" => {
+                    parsed.load_state = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--record-session" => {
+                    parsed.record_session = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--replay" => parsed.replay = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--frames" => parsed.frames = Some(non_zero(&arg, args.next())?.into()),
+                "--out" => parsed.out = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--exr" => parsed.exr = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--plot-frames" => plot_frames = Some(non_zero(&arg, args.next())?.into()),
+                "--plot-out" => parsed.plot = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--headless" => parsed.headless = true,
+                "--video" => parsed.video = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--video-fps" => parsed.video_fps = Some(non_zero(&arg, args.next())?),
+                "--gif" => parsed.gif = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--gif-seconds" => match number(&arg, args.next())? {
+                    seconds if seconds > 0.0 => parsed.gif_seconds = Some(seconds),
+                    _ => return Err(format!("`{arg}` must be greater than zero")),
+                },
+                "--gif-fps" => parsed.gif_fps = Some(non_zero(&arg, args.next())?),
+                "--help" => return Err(String::new()),
+                _ => return Err(format!("unknown argument `{arg}`")),
+            }
+        }
+        if let Some(frames) = plot_frames {
+            if parsed.frames.is_some() {
+                return Err("`--frames` and `--plot-frames` can't be combined".to_string());
+            }
+            parsed.frames = Some(frames);
+            parsed
+                .plot
+                .get_or_insert_with(|| PathBuf::from(DEFAULT_PLOT_FILE));
+        }
+        if parsed.headless && parsed.frames.is_none() {
+            return Err("`--headless` needs `--frames` or `--plot-frames`".to_string());
+        }
+        if parsed.headless && parsed.kiosk {
+            return Err("`--headless` and `--kiosk` can't be combined".to_string());
+        }
+        if parsed.headless && parsed.gallery.is_some() {
+            return Err("`--headless` and `--gallery` can't be combined".to_string());
+        }
+        if parsed.headless && parsed.debug_view {
+            return Err("`--headless` and `--debug-view` can't be combined".to_string());
+        }
+        if parsed.plot.is_some() && parsed.frames.is_none() {
+            return Err("`--plot-out` needs `--frames` or `--plot-frames`".to_string());
+        }
+        if parsed.exr.is_some() && parsed.frames.is_none() {
+            return Err("`--exr` needs `--frames`".to_string());
+        }
+        if parsed.record_session.is_some() && parsed.replay.is_some() {
+            return Err("`--record-session` and `--replay` can't be combined".to_string());
+        }
+        if cfg!(not(feature = "export-video")) && parsed.video.is_some() {
+            return Err("`--video` needs the export-video feature".to_string());
+        }
+        if cfg!(not(feature = "export-png")) && parsed.record {
+            return Err("`--record` needs the export-png feature".to_string());
+        }
+        if cfg!(not(feature = "export-png")) && parsed.gif.is_some() {
+            return Err("`--gif` needs the export-png feature".to_string());
+        }
+        if cfg!(not(feature = "export-exr")) && parsed.exr.is_some() {
+            return Err("`--exr` needs the export-exr feature".to_string());
+        }
+        Ok(parsed)
+    }
 
-#[derive(Resource)]
-pub struct ComputePipeline {
-    bind_group_layout: BindGroupLayout,
-    update_program: CachedComputePipelineId,
-    draw_program: CachedComputePipelineId,
-    clear_program: CachedComputePipelineId,
+    fn apply(&self, config: &mut FlowFieldConfig) {
+        if let Some(width) = self.width {
+            config.width = width;
+        }
+        if let Some(height) = self.height {
+            config.height = height;
+        }
+        if let Some(scale) = self.scale {
+            config.render_scale = scale;
+        }
+        if let Some(particles) = self.particles {
+            config.particles = particles;
+        }
+        if let Some(supersample) = self.supersample {
+            config.supersample = supersample;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = Some(seed);
+        }
+        if self.ambient {
+            config.drift.enabled = true;
+        }
+    }
 }
 
-#[derive(Resource)]
-pub struct ComputeBindGroup(BindGroup);
-
-#[derive(Default)]
-pub struct ComputeNode {
-    ready: bool,
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("`{flag}` expects a value"))
 }
 
-#[derive(Clone, Resource, ExtractResource)]
-pub struct ParticleBuffer {
-    particles: Buffer,
-    energies: Buffer,
+fn number<T: std::str::FromStr>(flag: &str, arg: Option<String>) -> Result<T, String> {
+    let arg = value(flag, arg)?;
+    arg.parse()
+        .map_err(|_| format!("`{flag}` expects a number, got `{arg}`"))
 }
 
-#[derive(Clone, Copy, ShaderType)]
-pub struct Particle {
-    position: Vec2,
-    velocity: Vec2,
-    seed: u32,
+fn non_zero(flag: &str, arg: Option<String>) -> Result<u32, String> {
+    match number(flag, arg)? {
+        0 => Err(format!("`{flag}` must be greater than zero")),
+        n => Ok(n),
+    }
 }
 
 pub fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(AssetPlugin::default().watch_for_changes()))
-        .add_plugins(ComputePlugin)
-        .add_systems(Startup, setup)
-        .run();
-}
-
-fn setup(
-    mut commands: Commands,
-    render_device: Res<RenderDevice>,
-    mut images: ResMut<Assets<Image>>,
-) {
-    let mut image = Image::new_fill(
-        Extent3d {
-            width: SIZE.0,
-            height: SIZE.1,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        &[0; 4 * 4],
-        TextureFormat::Rgba32Float,
-    );
-
-    image.texture_descriptor.usage =
-        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("error: {err}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
 
-    let image = images.add(image);
+    if args.dump_config {
+        println!("{}", FlowFieldConfig::default().to_ron());
+        return;
+    }
 
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            custom_size: Some(Vec2::new(SIZE.0 as f32, SIZE.1 as f32)),
-            ..default()
+    let config_path = args.config.clone().unwrap_or_else(default_config_path);
+    let mut config = match FlowFieldConfig::load(&config_path) {
+        Ok(config) => config.unwrap_or_default(),
+        Err(err) => {
+            eprintln!("{}: {err}", config_path.display());
+            std::process::exit(1);
+        }
+    };
+    args.apply(&mut config);
+
+    let window = Window {
+        resolution: (config.width as f32, config.height as f32).into(),
+        mode: if args.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
         },
-        texture: image.clone(),
         ..default()
-    });
-
-    let mut particles = vec![Particle {
-        position: Vec2::ZERO,
-        velocity: Vec2::ZERO,
-        seed: 0,
-    }; NR_PARTICLES as usize];
-
-    for (i, p) in &mut particles.iter_mut().enumerate() {
-        p.position = Vec2::new(
-            rand::random::<f32>() * SIZE.0 as f32,
-            rand::random::<f32>() * SIZE.1 as f32,
-        );
-        p.velocity = Vec2::new(
-            rand::random::<f32>(),
-            rand::random::<f32>(),
-        );
+    };
 
-        p.seed = i as u32;
+    let mut plugin = config.plugin();
+    if let Some(path) = &args.shader_path {
+        plugin = plugin.with_shader_path(path);
+    }
+    if let Err(err) = plugin.check() {
+        eprintln!("error: {err}\n");
+        eprintln!("{USAGE}");
+        std::process::exit(2);
     }
 
-    let mut particle_byte_buffer: Vec<u8> = Vec::new();
-    let mut particle_buffer = encase::StorageBuffer::new(&mut particle_byte_buffer);
-    particle_buffer.write(&particles).unwrap();
-    let particle_storage = render_device.create_buffer_with_data(&BufferInitDescriptor {
-        label: None,
-        usage: BufferUsages::STORAGE,
-        contents: particle_buffer.into_inner(),
-    });
-
-    let energy_storage = render_device.create_buffer(&BufferDescriptor {
-        label: None,
-        size: (4 * SIZE.0 * SIZE.1) as u64,
-        usage: BufferUsages::STORAGE,
-        mapped_at_creation: false,
-    });
-
-    commands.spawn(Camera2dBundle::default());
-
-    commands.insert_resource(ParticleBuffer {
-        particles: particle_storage,
-        energies: energy_storage,
-    });
-    commands.insert_resource(ComputeInput { dst_image: image });
-}
-
-fn prepare_bind_group(
-    mut commands: Commands,
-    pipeline: Res<ComputePipeline>,
-    gpu_images: Res<RenderAssets<Image>>,
-    inputs: Res<ComputeInput>,
-    particles: Res<ParticleBuffer>,
-    render_device: Res<RenderDevice>,
-) {
-    let view = gpu_images.get(&inputs.dst_image).unwrap();
-    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
-        label: None,
-        layout: &pipeline.bind_group_layout,
-        entries: &[
-            BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(&view.texture_view),
-            },
-            BindGroupEntry {
-                binding: 1,
-                resource: BindingResource::Buffer(BufferBinding {
-                    buffer: &particles.particles,
-                    offset: 0,
-                    size: None,
-                }),
-            },
-            BindGroupEntry {
-                binding: 2,
-                resource: BindingResource::Buffer(BufferBinding {
-                    buffer: &particles.energies,
-                    offset: 0,
-                    size: None,
+    let mut app = App::new();
+    if args.headless {
+        app.add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::ZERO),
+            plugin,
+        ));
+    } else {
+        app.add_plugins(
+            DefaultPlugins
+                .set(AssetPlugin::default().watch_for_changes())
+                .set(WindowPlugin {
+                    primary_window: Some(window),
+                    close_when_requested: !args.kiosk,
+                    ..default()
                 }),
+        )
+        .add_plugins((
+            plugin.with_sprite(),
+            FlowFieldControlsPlugin,
+            StatsOverlayPlugin,
+            MagnifierPlugin,
+            HelpOverlayPlugin,
+            AutoDriftPlugin,
+        ))
+        .insert_resource(config.bindings.clone())
+        .insert_resource(config.drift.clone())
+        .add_systems(Startup, setup);
+        if args.kiosk {
+            app.add_plugins(KioskPlugin);
+        }
+        if let Some(interval) = args.gallery {
+            app.add_plugins(GalleryPlugin {
+                interval,
+                ..default()
+            });
+        }
+        if args.debug_view {
+            app.add_plugins(DebugViewPlugin);
+        }
+        #[cfg(feature = "ui")]
+        app.add_plugins(creative_coding::ControlPanelPlugin);
+        #[cfg(feature = "inspector")]
+        app.add_plugins(bevy_inspector_egui::quick::WorldInspectorPlugin::new());
+    }
+    app.add_plugins(FramePacingPlugin {
+        max_fps: args.max_fps,
+    });
+    #[cfg(feature = "export-video")]
+    if let Some(path) = &args.video {
+        app.add_plugins(creative_coding::VideoExportPlugin {
+            path: path.clone(),
+            fps: args.video_fps.unwrap_or(DEFAULT_VIDEO_FPS),
+        });
+    }
+    let batch = args.frames.map(|frames| {
+        let status = BatchStatus::default();
+        app.add_plugins(BatchRenderPlugin {
+            frames,
+            // Plots only save an image when asked to.
+            out: match (&args.out, &args.plot) {
+                (Some(out), _) => Some(out.clone()),
+                (None, Some(_)) => None,
+                (None, None) => Some(PathBuf::from(DEFAULT_OUT_FILE)),
             },
-        ],
+            exr: args.exr.clone(),
+            plot: args.plot.clone(),
+            plot_style: PlotStyle::default(),
+            status: status.clone(),
+        });
+        status
     });
-    commands.insert_resource(ComputeBindGroup(bind_group));
-}
-
-impl Plugin for ComputePlugin {
-    fn build(&self, app: &mut App) {
-        app.add_plugins(ExtractResourcePlugin::<ParticleBuffer>::default());
-        app.add_plugins(ExtractResourcePlugin::<ComputeInput>::default());
-
-        let render_app = app.sub_app_mut(RenderApp);
-        render_app.add_systems(
-            Render,
-            prepare_bind_group.in_set(RenderSet::PrepareBindGroups),
-        );
-
-        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
-        render_graph.add_node("compute", ComputeNode::default());
-        render_graph.add_node_edge("compute", bevy::render::main_graph::node::CAMERA_DRIVER);
+    let mut gif_settings = GifSettings::default();
+    if let Some(seconds) = args.gif_seconds {
+        gif_settings.seconds = seconds;
     }
-
-    fn finish(&self, app: &mut App) {
-        let render_app = app.sub_app_mut(RenderApp);
-        render_app.init_resource::<ComputePipeline>();
+    if let Some(fps) = args.gif_fps {
+        gif_settings.fps = fps;
     }
-}
-
-impl FromWorld for ComputePipeline {
-    fn from_world(world: &mut World) -> Self {
-        let bind_group_layout =
-            world
-                .resource::<RenderDevice>()
-                .create_bind_group_layout(&BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &[
-                        BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: ShaderStages::COMPUTE,
-                            ty: BindingType::StorageTexture {
-                                access: StorageTextureAccess::ReadWrite,
-                                format: TextureFormat::Rgba32Float,
-                                view_dimension: TextureViewDimension::D2,
-                            },
-                            count: None,
-                        },
-                        BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: ShaderStages::COMPUTE,
-                            ty: BindingType::Buffer {
-                                ty: BufferBindingType::Storage { read_only: false },
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                        BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: ShaderStages::COMPUTE,
-                            ty: BindingType::Buffer {
-                                ty: BufferBindingType::Storage { read_only: false },
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                    ],
-                });
-        let shader = world
-            .resource::<AssetServer>()
-            .load("shaders/flow_field.wgsl");
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let from_entrypoint = |entry_point: &'static str| -> ComputePipelineDescriptor {
-            ComputePipelineDescriptor {
-                label: None,
-                layout: vec![bind_group_layout.clone()],
-                push_constant_ranges: Vec::new(),
-                shader: shader.clone(),
-                shader_defs: vec![
-                    ShaderDefVal::UInt("NR_PARTICLES".to_string(), NR_PARTICLES),
-                    ShaderDefVal::UInt("NR_PIXELS".to_string(), SIZE.0 * SIZE.1),
-                    ShaderDefVal::UInt("SCREEN_WIDTH".to_string(), SIZE.0),
-                ],
-                entry_point: Cow::from(entry_point),
+    if let Some(path) = &args.gif {
+        let request = app.world.resource::<GifRequest>();
+        request.request(path, gif_settings.clone());
+    }
+    app.insert_resource(gif_settings);
+    if let Some(path) = &args.record_session {
+        app.add_plugins(SessionRecorderPlugin {
+            path: path.clone(),
+            dt: SESSION_DT,
+        });
+    }
+    if let Some(path) = &args.replay {
+        match Session::load(path) {
+            Ok(session) => app.add_plugins(SessionReplayPlugin { session }),
+            Err(err) => {
+                eprintln!("error: could not load session {}: {err}", path.display());
+                std::process::exit(1);
             }
         };
-
-        let update_program = pipeline_cache.queue_compute_pipeline(from_entrypoint("update"));
-        let draw_program = pipeline_cache.queue_compute_pipeline(from_entrypoint("draw"));
-        let clear_program = pipeline_cache.queue_compute_pipeline(from_entrypoint("clear"));
-
-        ComputePipeline {
-            bind_group_layout,
-            update_program,
-            draw_program,
-            clear_program,
-        }
     }
-}
-
-impl render_graph::Node for ComputeNode {
-    fn update(&mut self, world: &mut World) {
-        let pipeline = world.resource::<ComputePipeline>();
-        let pipeline_cache = world.resource::<PipelineCache>();
-
-        if !self.ready {
-            if let CachedPipelineState::Ok(_) =
-                pipeline_cache.get_compute_pipeline_state(pipeline.update_program)
-            {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.draw_program)
-                {
-                    self.ready = true;
+    if let Some(path) = &args.load_state {
+        app.world.resource_mut::<Snapshots>().load(path);
+    }
+    if args.record {
+        app.insert_resource(Recording {
+            active: true,
+            ..default()
+        });
+    }
+    app.add_systems(
+        Startup,
+        move |mut commands: Commands, asset_server: Res<AssetServer>| {
+            if let Some(image) = &args.image {
+                commands.insert_resource(FieldImage(asset_server.load(image.as_str())));
+            }
+            if let Some(obstacles) = &args.obstacles {
+                commands.insert_resource(ObstacleMask(asset_server.load(obstacles.as_str())));
+            }
+            match &args.palette {
+                Some(path) if path.ends_with(".hex") => {
+                    match Palette::from_hex_file(Path::new(path)) {
+                        Ok(palette) => commands.insert_resource(palette),
+                        Err(err) => error!("could not load palette {path}: {err}"),
+                    }
+                }
+                Some(path) => {
+                    commands.insert_resource(Palette::from_image(asset_server.load(path.as_str())))
                 }
+                None => {}
             }
-        }
-    }
+        },
+    );
+    app.run();
 
-    fn run(
-        &self,
-        _graph: &mut render_graph::RenderGraphContext,
-        render_context: &mut bevy::render::renderer::RenderContext,
-        world: &World,
-    ) -> Result<(), render_graph::NodeRunError> {
-        if !self.ready {
-            return Ok(());
+    match batch.map(|status| status.result()) {
+        Some(Some(Err(err))) => {
+            eprintln!("error: {err}");
+            std::process::exit(1);
         }
+        Some(None) => {
+            eprintln!("error: exited before all frames were simulated");
+            std::process::exit(1);
+        }
+        _ => {}
+    }
+}
 
-        let bind_group = &world.resource::<ComputeBindGroup>().0;
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let pipeline = world.resource::<ComputePipeline>();
-        let update_program = pipeline_cache
-            .get_compute_pipeline(pipeline.update_program)
-            .unwrap();
-        let clear_program = pipeline_cache
-            .get_compute_pipeline(pipeline.clear_program)
-            .unwrap();
-        let draw_program = pipeline_cache
-            .get_compute_pipeline(pipeline.draw_program)
-            .unwrap();
-
-        let mut pass = render_context
-            .command_encoder()
-            .begin_compute_pass(&ComputePassDescriptor::default());
-
-        pass.set_bind_group(0, bind_group, &[]);
-        pass.set_pipeline(update_program);
-        pass.dispatch_workgroups(NR_PARTICLES / WORKGROUP_SIZE, 1, 1);
-        pass.set_pipeline(clear_program);
-        pass.dispatch_workgroups(SIZE.0 / 16, SIZE.1 / 16, 1);
-        pass.set_pipeline(draw_program);
-        pass.dispatch_workgroups(SIZE.0 / 16, SIZE.1 / 16, 1);
+fn default_config_path() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(DEFAULT_CONFIG_FILE)))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE))
+}
 
-        Ok(())
-    }
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
 }
@@ -0,0 +1,213 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_component::DynamicUniformIndex,
+        render_graph,
+        render_resource::{
+            CachedPipelineState, ComputePassDescriptor, PipelineCache, PipelineCacheError,
+        },
+    },
+};
+
+use crate::{
+    pipeline::{Program, ENTRY_POINTS},
+    ComputeBindGroup, ComputePipeline, DisplayMode, FlowFieldSettings, FlowFieldStatus,
+    FlowFieldUniform, ParticleBuffer, SharedStatus, SimParamsBuffer, SimulatedFrames, Substeps,
+};
+
+/// Name of the [`ComputeNode`] in the main render graph, which runs before the camera driver.
+pub const NODE_NAME: &str = "flow_field";
+
+pub struct ComputeNode {
+    ready: bool,
+    /// Whether a program failed to compile, so the error is logged once rather than every frame.
+    failed: bool,
+    fields: QueryState<(
+        &'static ComputeBindGroup,
+        &'static DynamicUniformIndex<FlowFieldUniform>,
+        &'static FlowFieldSettings,
+        &'static FlowFieldUniform,
+        &'static ParticleBuffer,
+    )>,
+}
+
+impl FromWorld for ComputeNode {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            ready: false,
+            failed: false,
+            fields: world.query(),
+        }
+    }
+}
+
+impl render_graph::Node for ComputeNode {
+    fn update(&mut self, world: &mut World) {
+        self.fields.update_archetypes(world);
+
+        let pipeline = world.resource::<ComputePipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        // Checked every frame, so the node pauses while requeued pipelines compile.
+        let states = pipeline
+            .programs()
+            .map(|id| pipeline_cache.get_compute_pipeline_state(id));
+        self.ready = states
+            .iter()
+            .all(|state| matches!(state, CachedPipelineState::Ok(_)));
+        let errors: Vec<_> = ENTRY_POINTS
+            .iter()
+            .zip(states)
+            .filter_map(|(entry_point, state)| match state {
+                // Still loading, not broken.
+                CachedPipelineState::Err(
+                    PipelineCacheError::ShaderNotLoaded(_)
+                    | PipelineCacheError::ShaderImportNotYetAvailable,
+                ) => None,
+                CachedPipelineState::Err(err) => Some((entry_point, err)),
+                _ => None,
+            })
+            .collect();
+        if !errors.is_empty() && !self.failed {
+            for (entry_point, err) in &errors {
+                error!(
+                    "flow field program `{entry_point}` failed to compile, the image stays frozen \
+                     until the shader is fixed: {err}"
+                );
+            }
+        }
+        self.failed = !errors.is_empty();
+
+        let status = if self.ready {
+            FlowFieldStatus::Ready
+        } else if self.failed {
+            // Every program comes from the same shader, so most share their error.
+            let mut messages: Vec<String> = Vec::new();
+            for (_, err) in &errors {
+                let message = err.to_string();
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+            FlowFieldStatus::Error(messages.join("\n"))
+        } else {
+            FlowFieldStatus::Compiling
+        };
+        if let Ok(mut shared) = world.resource::<SharedStatus>().0.lock() {
+            *shared = status;
+        }
+
+        if self.ready
+            && world.resource::<SimParamsBuffer>().advance
+            && self.fields.iter_manual(world).next().is_some()
+        {
+            world.resource_mut::<SimulatedFrames>().0 += 1;
+        }
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut bevy::render::renderer::RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        if !self.ready {
+            return Ok(());
+        }
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<ComputePipeline>();
+        let workgroup_size = pipeline.workgroup_size;
+        let sim_params = world.resource::<SimParamsBuffer>();
+        let updates = sim_params.steps * world.resource::<Substeps>().0.clamp(1, Substeps::MAX);
+        // Fetched again rather than trusting `ready`, a reloaded shader requeues them at any time.
+        let Some(programs) = pipeline
+            .programs()
+            .iter()
+            .map(|&id| pipeline_cache.get_compute_pipeline(id))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(());
+        };
+        let program = |program: Program| programs[program as usize];
+
+        const PASS: ComputePassDescriptor = ComputePassDescriptor {
+            label: Some("flow field"),
+        };
+        let encoder = render_context.command_encoder();
+        for (bind_group, uniform_index, settings, uniform, buffers) in
+            self.fields.iter_manual(world)
+        {
+            let particle_groups = settings.nr_particles.div_ceil(workgroup_size.0);
+            // Rounded up, the shaders skip the pixels past the edges.
+            let pixel_groups = (settings.width.div_ceil(16), settings.height.div_ceil(16));
+            let mut pass = encoder.begin_compute_pass(&PASS);
+            pass.set_bind_group(0, &bind_group.0, &[uniform_index.index()]);
+            // Without a step only the display is redrawn, so changes to the look show while paused.
+            if sim_params.advance {
+                pass.set_pipeline(program(Program::Fade));
+                pass.dispatch_workgroups(
+                    (settings.width * settings.supersample).div_ceil(16),
+                    (settings.height * settings.supersample).div_ceil(16),
+                    1,
+                );
+            }
+            for _ in 0..updates {
+                if uniform.neighbors_enabled != 0 {
+                    // The grid is rebuilt from the positions of the previous update.
+                    drop(pass);
+                    encoder.clear_buffer(&buffers.grid_cells, 0, None);
+                    pass = encoder.begin_compute_pass(&PASS);
+                    pass.set_bind_group(0, &bind_group.0, &[uniform_index.index()]);
+                    pass.set_pipeline(program(Program::BinParticles));
+                    pass.dispatch_workgroups(particle_groups, 1, 1);
+                    pass.set_pipeline(program(Program::PrefixSum));
+                    pass.dispatch_workgroups(1, 1, 1);
+                    pass.set_pipeline(program(Program::ScatterParticles));
+                    pass.dispatch_workgroups(particle_groups, 1, 1);
+                }
+                pass.set_pipeline(program(Program::Update));
+                pass.dispatch_workgroups(particle_groups, 1, 1);
+            }
+            if uniform.display_mode != DisplayMode::Linear as u32 {
+                drop(pass);
+                encoder.clear_buffer(&buffers.display_stats, 0, None);
+                pass = encoder.begin_compute_pass(&PASS);
+                pass.set_bind_group(0, &bind_group.0, &[uniform_index.index()]);
+                pass.set_pipeline(program(Program::EnergyMax));
+                pass.dispatch_workgroups(pixel_groups.0, pixel_groups.1, 1);
+                if uniform.display_mode == DisplayMode::Equalized as u32 {
+                    pass.set_pipeline(program(Program::EnergyHistogram));
+                    pass.dispatch_workgroups(pixel_groups.0, pixel_groups.1, 1);
+                    pass.set_pipeline(program(Program::EnergyCdf));
+                    pass.dispatch_workgroups(1, 1, 1);
+                }
+            }
+            if uniform.glow_strength > 0.0 {
+                pass.set_pipeline(program(Program::GlowHorizontal));
+                pass.dispatch_workgroups(pixel_groups.0, pixel_groups.1, 1);
+                pass.set_pipeline(program(Program::GlowVertical));
+                pass.dispatch_workgroups(pixel_groups.0, pixel_groups.1, 1);
+            }
+            if uniform.bloom_enabled != 0 {
+                // Both levels are blurred in the same dispatch, the quarter resolution one in the
+                // second layer.
+                let half_groups = (settings.width.div_ceil(32), settings.height.div_ceil(32));
+                let quarter_groups = (settings.width.div_ceil(64), settings.height.div_ceil(64));
+                pass.set_pipeline(program(Program::BloomThreshold));
+                pass.dispatch_workgroups(half_groups.0, half_groups.1, 1);
+                pass.set_pipeline(program(Program::BloomDownsample));
+                pass.dispatch_workgroups(quarter_groups.0, quarter_groups.1, 1);
+                pass.set_pipeline(program(Program::BloomHorizontal));
+                pass.dispatch_workgroups(half_groups.0, half_groups.1, 2);
+                pass.set_pipeline(program(Program::BloomVertical));
+                pass.dispatch_workgroups(half_groups.0, half_groups.1, 2);
+            }
+            // Particles deposit their energy in `update`, so a single draw shows every substep.
+            pass.set_pipeline(program(Program::Draw));
+            pass.dispatch_workgroups(pixel_groups.0, pixel_groups.1, 1);
+        }
+
+        Ok(())
+    }
+}
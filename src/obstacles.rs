@@ -0,0 +1,91 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::{ShaderType, StorageBuffer},
+        renderer::{RenderDevice, RenderQueue},
+    },
+    window::PrimaryWindow,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pointer::{self, FieldSprites},
+    ComputeInput, FlowFieldSettings, InputBindings, PrimaryFlowField,
+};
+
+/// A circle particles flow around, in pixels of the flow field.
+#[derive(Clone, Copy, Debug, ShaderType, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+/// The obstacles in every flow field. Particles slide along their edges and their interiors are
+/// drawn as empty background. Uploaded again whenever the resource changes.
+#[derive(Resource, ExtractResource, Clone, Default, Debug)]
+pub struct Obstacles(pub Vec<Obstacle>);
+
+impl Obstacles {
+    pub fn add(&mut self, center: Vec2, radius: f32) {
+        self.0.push(Obstacle { center, radius });
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct ObstaclesBuffer {
+    pub(crate) buffer: StorageBuffer<Vec<Obstacle>>,
+}
+
+pub(crate) fn prepare_obstacles(
+    obstacles: Res<Obstacles>,
+    mut buffer: ResMut<ObstaclesBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !obstacles.is_changed() {
+        return;
+    }
+
+    let mut gpu_obstacles = obstacles.0.clone();
+    // Bindings can't be empty, the shader only looks at the first `obstacle_count` entries.
+    if gpu_obstacles.is_empty() {
+        gpu_obstacles.push(Obstacle {
+            center: Vec2::ZERO,
+            radius: 0.0,
+        });
+    }
+    buffer.buffer.set(gpu_obstacles);
+    buffer.buffer.set_label(Some("flow field obstacles"));
+    buffer.buffer.write_buffer(&render_device, &render_queue);
+}
+
+const OBSTACLE_RADIUS: f32 = 40.0;
+
+/// Places an obstacle under the cursor when `X` is pressed.
+pub(crate) fn add_obstacle_at_cursor(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    sprites: FieldSprites,
+    field: Query<(&FlowFieldSettings, &ComputeInput), With<PrimaryFlowField>>,
+    mut obstacles: ResMut<Obstacles>,
+) {
+    if !bindings.add_obstacle.just_pressed(&keys) {
+        return;
+    }
+    let Ok((settings, input)) = field.get_single() else {
+        return;
+    };
+    let (Some(cursor), Some((sprite, transform))) = (
+        pointer::cursor_world_position(&windows, &cameras),
+        pointer::field_sprite(&sprites, input),
+    ) else {
+        return;
+    };
+
+    let center = pointer::world_to_field(cursor, settings, sprite, transform);
+    obstacles.add(center, OBSTACLE_RADIUS);
+    info!("added obstacle at {:.0}", center);
+}
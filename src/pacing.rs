@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    prelude::*,
+    window::{PresentMode, PrimaryWindow},
+};
+
+/// Highest number of frames per second the app runs at, `None` for no limit. As the render world
+/// extracts every main world frame once, this also caps how often the flow fields are simulated.
+#[derive(Resource, Clone, Copy, Default, Debug)]
+pub struct FrameRateLimit(pub Option<f32>);
+
+/// Sleeps at the end of every frame to stay under the [`FrameRateLimit`], and logs the frame rate
+/// once per second while neither the limit nor vsync hold it back.
+pub struct FramePacingPlugin {
+    pub max_fps: Option<f32>,
+}
+
+impl Plugin for FramePacingPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(max_fps) = self.max_fps {
+            assert!(max_fps > 0.0, "the frame rate limit must be positive");
+        }
+        app.insert_resource(FrameRateLimit(self.max_fps));
+        app.add_systems(Last, (log_frame_rate, limit_frame_rate).chain());
+    }
+}
+
+/// Sleeps until the [`FrameRateLimit`] allows the next frame to start.
+fn limit_frame_rate(mut next_frame: Local<Option<Instant>>, limit: Res<FrameRateLimit>) {
+    let Some(max_fps) = limit.0 else {
+        *next_frame = None;
+        return;
+    };
+    let interval = Duration::from_secs_f32(1.0 / max_fps);
+    let now = Instant::now();
+    let deadline = match *next_frame {
+        // Don't make up for frames that took too long.
+        Some(deadline) if deadline > now => {
+            std::thread::sleep(deadline - now);
+            deadline
+        }
+        _ => now,
+    };
+    *next_frame = Some(deadline + interval);
+}
+
+#[derive(Default)]
+struct FrameCounter {
+    frames: u32,
+    since: Option<Instant>,
+}
+
+fn log_frame_rate(
+    mut counter: Local<FrameCounter>,
+    limit: Res<FrameRateLimit>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let vsync = windows.get_single().is_ok_and(|window| {
+        matches!(
+            window.present_mode,
+            PresentMode::AutoVsync | PresentMode::Fifo
+        )
+    });
+    if limit.0.is_some() || vsync {
+        *counter = FrameCounter::default();
+        return;
+    }
+
+    let now = Instant::now();
+    let Some(since) = counter.since else {
+        counter.since = Some(now);
+        return;
+    };
+    counter.frames += 1;
+    let elapsed = now - since;
+    if elapsed >= Duration::from_secs(1) {
+        info!("{:.1} fps", counter.frames as f32 / elapsed.as_secs_f32());
+        *counter = FrameCounter {
+            frames: 0,
+            since: Some(now),
+        };
+    }
+}
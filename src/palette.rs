@@ -0,0 +1,285 @@
+use std::{fs, io, path::Path};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Texture, TextureAspect,
+            TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+            TextureViewDescriptor,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        Extract,
+    },
+};
+
+/// A color ramp the trail energy of every flow field is looked up in, replacing the background and
+/// trail colors. No energy maps to the first color and the energy of a fully lit pixel to the last,
+/// anything brighter is clamped. Defaults to [`Palette::grayscale`].
+#[derive(Resource, Clone, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct Palette {
+    name: String,
+    colors: Vec<Color>,
+    /// Image the colors are sampled from whenever it is loaded or changes on disk.
+    source: Option<Handle<Image>>,
+}
+
+const VIRIDIS: [&str; 10] = [
+    "440154", "482878", "3e4989", "31688e", "26828e", "1f9e89", "35b779", "6ece58", "b5de2b",
+    "fde725",
+];
+
+const INFERNO: [&str; 10] = [
+    "000004", "1b0c41", "4a0c6b", "781c6d", "a52c60", "cf4446", "ed6925", "fb9b06", "f7d13d",
+    "fcffa4",
+];
+
+impl Palette {
+    /// Number of entries in the lookup table.
+    pub const SIZE: usize = 256;
+
+    /// Spreads the colors evenly over the table, blending linearly between them.
+    pub fn from_colors(name: impl Into<String>, stops: &[Color]) -> Self {
+        assert!(!stops.is_empty(), "a palette needs at least one color");
+        let stops: Vec<Vec4> = stops
+            .iter()
+            .map(|color| color.as_linear_rgba_f32().into())
+            .collect();
+        let colors = (0..Self::SIZE)
+            .map(|i| {
+                let position = i as f32 / (Self::SIZE - 1) as f32 * (stops.len() - 1) as f32;
+                let low = (position.floor() as usize).min(stops.len() - 1);
+                let high = (low + 1).min(stops.len() - 1);
+                let [r, g, b, a] = stops[low].lerp(stops[high], position.fract()).to_array();
+                Color::rgba_linear(r, g, b, a)
+            })
+            .collect();
+        Self {
+            name: name.into(),
+            colors,
+            source: None,
+        }
+    }
+
+    /// Samples evenly spaced pixels along the middle row of an 8-bit RGBA image strip, once it has
+    /// loaded and again every time it is reloaded. Grayscale until then.
+    pub fn from_image(image: Handle<Image>) -> Self {
+        Self {
+            source: Some(image),
+            ..Self::grayscale()
+        }
+    }
+
+    /// Reads a palette with one hex color per line, like the `.hex` files shared by pixel artists.
+    /// Blank lines are ignored and invalid ones skipped with a warning.
+    pub fn from_hex_file(path: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        let stops: Vec<Color> = source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| match Color::hex(line.trim()) {
+                Ok(color) => Some(color),
+                Err(err) => {
+                    warn!(
+                        "{}:{}: skipping invalid color: {err:?}",
+                        path.display(),
+                        index + 1
+                    );
+                    None
+                }
+            })
+            .collect();
+        if stops.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} contains no valid colors", path.display()),
+            ));
+        }
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        Ok(Self::from_colors(name, &stops))
+    }
+
+    pub fn viridis() -> Self {
+        Self::from_hex("viridis", &VIRIDIS)
+    }
+
+    pub fn inferno() -> Self {
+        Self::from_hex("inferno", &INFERNO)
+    }
+
+    pub fn grayscale() -> Self {
+        Self::from_colors("grayscale", &[Color::BLACK, Color::WHITE])
+    }
+
+    /// The built-in palettes, in the order the controls cycle through them.
+    pub fn builtins() -> [Palette; 3] {
+        [Self::viridis(), Self::inferno(), Self::grayscale()]
+    }
+
+    fn from_hex(name: &str, stops: &[&str]) -> Self {
+        let stops: Vec<Color> = stops
+            .iter()
+            .map(|hex| Color::hex(hex).expect("built-in palettes are valid hex"))
+            .collect();
+        Self::from_colors(name, &stops)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The [`Palette::SIZE`] entries of the table.
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::grayscale()
+    }
+}
+
+/// The colors along the middle row of an image, or `None` for formats other than 8-bit RGBA.
+fn image_stops(image: &Image) -> Option<Vec<Color>> {
+    let srgb = match image.texture_descriptor.format {
+        TextureFormat::Rgba8UnormSrgb => true,
+        TextureFormat::Rgba8Unorm => false,
+        _ => return None,
+    };
+    let size = image.texture_descriptor.size;
+    let (width, row) = (size.width as usize, size.height as usize / 2);
+    let count = width.min(Palette::SIZE);
+    let stops = (0..count)
+        .map(|i| {
+            let x = ((i as f32 + 0.5) * width as f32 / count as f32) as usize;
+            let offset = 4 * (row * width + x.min(width - 1));
+            let [r, g, b, a] = [0, 1, 2, 3].map(|c| image.data[offset + c] as f32 / 255.0);
+            if srgb {
+                Color::rgba(r, g, b, a)
+            } else {
+                Color::rgba_linear(r, g, b, a)
+            }
+        })
+        .collect();
+    Some(stops)
+}
+
+/// Resamples a [`Palette::from_image`] whenever its image is loaded or modified.
+pub(crate) fn update_palette_image(
+    mut events: EventReader<AssetEvent<Image>>,
+    palette: Option<ResMut<Palette>>,
+    images: Res<Assets<Image>>,
+) {
+    let Some(mut palette) = palette else {
+        events.clear();
+        return;
+    };
+    let Some(source) = palette.source.clone() else {
+        return;
+    };
+    let reloaded = events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == source,
+        AssetEvent::Removed { .. } => false,
+    });
+    // The image may have finished loading before the palette was inserted.
+    if !reloaded && !palette.is_added() {
+        return;
+    }
+    let Some(image) = images.get(&source) else {
+        return;
+    };
+
+    match image_stops(image) {
+        Some(stops) if !stops.is_empty() => {
+            let name = std::mem::take(&mut palette.name);
+            *palette = Palette {
+                source: Some(source),
+                ..Palette::from_colors(name, &stops)
+            };
+        }
+        Some(_) => warn!("palette images must not be empty"),
+        None => warn!(
+            "palette images must be 8-bit RGBA, got {:?}",
+            image.texture_descriptor.format
+        ),
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct PaletteTexture {
+    texture: Option<(Texture, TextureView)>,
+}
+
+impl PaletteTexture {
+    pub(crate) fn view(&self) -> Option<&TextureView> {
+        self.texture.as_ref().map(|(_, view)| view)
+    }
+}
+
+/// Mirrors the main world resource, cloning it only when it changed.
+pub(crate) fn extract_palette(mut commands: Commands, palette: Extract<Option<Res<Palette>>>) {
+    match &*palette {
+        Some(palette) if palette.is_changed() => commands.insert_resource(Palette::clone(palette)),
+        Some(_) => {}
+        None => commands.remove_resource::<Palette>(),
+    }
+}
+
+pub(crate) fn prepare_palette(
+    palette: Option<Res<Palette>>,
+    mut texture: ResMut<PaletteTexture>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(palette) = palette else {
+        texture.texture = None;
+        return;
+    };
+    if texture.texture.is_some() && !palette.is_changed() {
+        return;
+    }
+
+    let size = Extent3d {
+        width: Palette::SIZE as u32,
+        height: 1,
+        depth_or_array_layers: 1,
+    };
+    let (gpu_texture, _) = texture.texture.get_or_insert_with(|| {
+        let gpu_texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("flow field palette"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba32Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = gpu_texture.create_view(&TextureViewDescriptor::default());
+        (gpu_texture, view)
+    });
+
+    let data: Vec<[f32; 4]> = palette
+        .colors
+        .iter()
+        .map(|color| color.as_linear_rgba_f32())
+        .collect();
+    render_queue.write_texture(
+        ImageCopyTexture {
+            texture: gpu_texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        bytemuck::cast_slice(&data),
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(size.width * std::mem::size_of::<[f32; 4]>() as u32),
+            rows_per_image: None,
+        },
+        size,
+    );
+}
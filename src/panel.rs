@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPlugin};
+
+use crate::{
+    controls, help, BoundaryMode, FlowFieldParams, InputBindings, Palette, PrimaryFlowField,
+    ResetSimulation, ScreenshotRequest, SimulationSpeed, SimulationState,
+};
+
+/// An egui side panel with sliders to tune the parameters of the flow fields while they run, and
+/// buttons to reset, pause or screenshot them. Its header collapses it, `Tab` hides it entirely.
+/// As every export reads the image of a flow field back rather than the window, the panel never
+/// shows up in screenshots, recordings or GIFs.
+pub struct ControlPanelPlugin;
+
+impl Plugin for ControlPanelPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.init_resource::<InputBindings>();
+        app.init_resource::<PanelVisible>();
+        app.add_systems(
+            Update,
+            (
+                toggle_panel.run_if(help::help_closed.and_then(controls::keyboard_free)),
+                show_panel,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct PanelVisible(bool);
+
+impl Default for PanelVisible {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// Copies the parameters the panel edits, so every flow field follows the panel.
+fn copy_panel_params(from: &FlowFieldParams, to: &mut FlowFieldParams) {
+    to.drag = from.drag;
+    to.fade = from.fade;
+    to.deposit = from.deposit;
+    to.noise_scale = from.noise_scale;
+    to.octaves = from.octaves;
+    to.exposure = from.exposure;
+    to.gamma = from.gamma;
+    to.boundary = from.boundary;
+}
+
+fn toggle_panel(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut visible: ResMut<PanelVisible>,
+) {
+    if bindings.toggle_panel.just_pressed(&keys) {
+        visible.0 = !visible.0;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    visible: Res<PanelVisible>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut state: ResMut<SimulationState>,
+    mut resets: EventWriter<ResetSimulation>,
+    screenshot: Res<ScreenshotRequest>,
+    palette: Option<Res<Palette>>,
+    mut fields: Query<(&mut FlowFieldParams, Option<&PrimaryFlowField>)>,
+) {
+    if !visible.0 {
+        return;
+    }
+    let Some(primary) = fields
+        .iter()
+        .find_map(|(params, primary)| primary.map(|_| *params))
+    else {
+        return;
+    };
+    let mut params = primary;
+    let mut new_speed = speed.0;
+    let palette_name = palette.as_deref().map_or("none", Palette::name).to_string();
+    let mut new_palette = None;
+
+    egui::SidePanel::right("flow field controls").show(contexts.ctx_mut(), |ui| {
+        egui::CollapsingHeader::new("controls")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.add(
+                    egui::Slider::new(&mut new_speed, SimulationSpeed::MIN..=SimulationSpeed::MAX)
+                        .logarithmic(true)
+                        .text("speed"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut params.drag, 0.0..=FlowFieldParams::MAX_DRAG)
+                        .text("drag"),
+                );
+                ui.add(egui::Slider::new(&mut params.fade, 0.0..=0.2).text("fade"));
+                ui.add(
+                    egui::Slider::new(&mut params.deposit, 0.01..=20.0)
+                        .logarithmic(true)
+                        .text("deposit"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut params.noise_scale, 10.0..=2000.0)
+                        .logarithmic(true)
+                        .text("noise scale"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut params.octaves, 1..=FlowFieldParams::MAX_OCTAVES)
+                        .text("octaves"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut params.exposure, 0.0..=64.0)
+                        .logarithmic(true)
+                        .text("exposure"),
+                );
+                ui.add(egui::Slider::new(&mut params.gamma, 0.1..=4.0).text("gamma"));
+
+                egui::ComboBox::from_label("palette")
+                    .selected_text(&palette_name)
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(palette.is_none(), "none").clicked() {
+                            new_palette = Some(None);
+                        }
+                        for builtin in Palette::builtins() {
+                            let selected = builtin.name() == palette_name;
+                            if ui.selectable_label(selected, builtin.name()).clicked() {
+                                new_palette = Some(Some(builtin));
+                            }
+                        }
+                    });
+                egui::ComboBox::from_label("boundary")
+                    .selected_text(format!("{:?}", params.boundary))
+                    .show_ui(ui, |ui| {
+                        for boundary in BoundaryMode::ALL {
+                            ui.selectable_value(
+                                &mut params.boundary,
+                                boundary,
+                                format!("{boundary:?}"),
+                            );
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    if ui.button("reset").clicked() {
+                        resets.send(ResetSimulation);
+                    }
+                    let pause = match *state {
+                        SimulationState::Running => "pause",
+                        SimulationState::Paused => "resume",
+                    };
+                    if ui.button(pause).clicked() {
+                        state.toggle();
+                    }
+                    if ui.button("screenshot").clicked() {
+                        screenshot.request();
+                    }
+                });
+            });
+    });
+
+    // Only written back when changed, so the panel doesn't trigger change detection every frame.
+    if new_speed != speed.0 {
+        speed.0 = new_speed;
+    }
+    if params != primary {
+        for (mut field, _) in &mut fields {
+            copy_panel_params(&params, &mut field);
+        }
+    }
+    match new_palette {
+        Some(Some(palette)) => commands.insert_resource(palette),
+        Some(None) => commands.remove_resource::<Palette>(),
+        None => {}
+    }
+}
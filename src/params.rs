@@ -0,0 +1,657 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_component::ExtractComponent,
+        render_asset::RenderAssets,
+        render_resource::{ShaderType, UniformBuffer},
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buffers::NEIGHBOR_CELL_SIZE, field_image, plugin::OwedSteps, FieldImage, FieldPrimitives,
+    FilmEffects, FlowFieldSettings, ObstacleSdf, Obstacles, Palette, PointerForce, SimulationSpeed,
+    SimulationState, StepRequest, Substeps, VectorField,
+};
+
+/// Per-frame values shared by all flow fields, bound next to the per-field [`FlowFieldUniform`].
+#[derive(Clone, Copy, Default, ShaderType)]
+pub(crate) struct SimParams {
+    /// Time step of a single substep.
+    dt: f32,
+    /// Time step of all the steps of the frame.
+    frame_dt: f32,
+    /// Simulated seconds, which advance with [`SimulationSpeed`] rather than wall time.
+    pub(crate) elapsed: f32,
+    speed: f32,
+    /// Number of [`FieldPrimitives`] to sum, the buffer always holds at least one.
+    primitive_count: u32,
+    /// Whether the [`FieldImage`] is bound, rather than the fallback image.
+    has_field_image: u32,
+    /// Whether there is a [`VectorField`] to sample.
+    has_vector_field: u32,
+    /// Number of [`Obstacles`], the buffer always holds at least one.
+    obstacle_count: u32,
+    /// Whether the [`ObstacleSdf`] is bound, rather than the fallback image.
+    has_obstacle_sdf: u32,
+    /// Whether there is a [`Palette`] to map the energy through.
+    has_palette: u32,
+    /// Number of prepared frames, which animates the grain.
+    pub(crate) frame: u32,
+    /// Whether [`FilmEffects`] are shown.
+    film_effects: u32,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct SimParamsBuffer {
+    pub(crate) uniform: UniformBuffer<SimParams>,
+    /// Whether the particles are updated this frame, false while paused unless stepping and when
+    /// a fast display hasn't accumulated a whole [`Timestep`] yet.
+    pub(crate) advance: bool,
+    /// Fixed steps to take, each running every substep.
+    pub(crate) steps: u32,
+}
+
+/// What happens to particles that leave the flow field.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Reflect, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    /// Move to a random position inside the field.
+    #[default]
+    Respawn,
+    /// Reappear at the opposite edge.
+    Wrap,
+    /// Reflect off the edge.
+    Bounce,
+    /// Stop moving and depositing energy.
+    Kill,
+}
+
+impl BoundaryMode {
+    pub const ALL: [BoundaryMode; 4] = [
+        BoundaryMode::Respawn,
+        BoundaryMode::Wrap,
+        BoundaryMode::Bounce,
+        BoundaryMode::Kill,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// How the noise is turned into the direction particles are steered towards.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Reflect, Serialize, Deserialize)]
+pub enum FieldType {
+    /// Use the noise value as the angle of the flow.
+    #[default]
+    Angle,
+    /// Use the curl of the noise as the flow, which is divergence free so particles circulate
+    /// instead of collapsing into lines.
+    Curl,
+}
+
+impl FieldType {
+    pub const ALL: [FieldType; 2] = [FieldType::Angle, FieldType::Curl];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// How the color particles deposit into their trails is chosen.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Reflect, Serialize, Deserialize)]
+pub enum ColorMode {
+    /// The color of the particle and its species.
+    #[default]
+    Particle,
+    /// A hue from the direction the particle is heading, for a rainbow of directions.
+    Heading,
+    /// A gradient from [`FlowFieldParams::color_slow`] to [`FlowFieldParams::color_fast`] with the
+    /// speed of the particle relative to [`FlowFieldParams::max_speed`].
+    Speed,
+    /// The color of the particle, fading along the [`FlowFieldParams::age_curve`] from fully bright
+    /// when it spawns to dark at the end of its lifetime. Needs a
+    /// [`FlowFieldParams::particle_lifetime`] to have any effect.
+    Age,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 4] = [
+        ColorMode::Particle,
+        ColorMode::Heading,
+        ColorMode::Speed,
+        ColorMode::Age,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// How particles dim with their age in [`ColorMode::Age`].
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Reflect, Serialize, Deserialize)]
+pub enum AgeCurve {
+    #[default]
+    Linear,
+    /// Stays bright for most of the lifetime and fades quickly at the end.
+    EaseIn,
+    /// Fades quickly after spawning, leaving a short bright head.
+    EaseOut,
+}
+
+/// How the accumulated trail energy is normalized before it is colored.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Reflect, Serialize, Deserialize)]
+pub enum DisplayMode {
+    /// Energy is proportional to brightness, which saturates in long runs.
+    #[default]
+    Linear,
+    /// Logarithm of the energy relative to the brightest pixel, measured every frame.
+    Log,
+    /// Spreads the brightness of the lit pixels evenly through a histogram of their energy, so
+    /// hour-long accumulations stay balanced. Costs two more passes than [`DisplayMode::Log`].
+    Equalized,
+}
+
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::Linear,
+        DisplayMode::Log,
+        DisplayMode::Equalized,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Curve that compresses the unbounded trail brightness into the displayable range, applied after
+/// the exposure.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Reflect, Serialize, Deserialize)]
+pub enum Tonemap {
+    /// Leaves the brightness as it is, clipping everything above `1.0`.
+    #[default]
+    None,
+    /// `x / (1 + x)`, which never quite reaches white.
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve,
+    /// `x (2.51 x + 0.03) / (x (2.43 x + 0.59) + 0.14)` clamped to `0.0..=1.0`, with more contrast
+    /// than [`Tonemap::Reinhard`].
+    Aces,
+}
+
+impl Tonemap {
+    pub const ALL: [Tonemap; 3] = [Tonemap::None, Tonemap::Reinhard, Tonemap::Aces];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// The curve evaluated on the CPU, the same as the shader applies to every channel.
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            Tonemap::None => x,
+            Tonemap::Reinhard => x / (1.0 + x),
+            Tonemap::Aces => {
+                (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+/// Look of a flow field, uploaded to the shader as a uniform every frame.
+#[derive(Component, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Component)]
+pub struct FlowFieldParams {
+    /// Size in pixels of the features in the noise field, larger is smoother.
+    pub noise_scale: f32,
+    /// How fast the noise field morphs over simulated time, `0.0` keeps it frozen.
+    pub field_evolution_speed: f32,
+    /// Number of noise layers summed into the field, at most [`FlowFieldParams::MAX_OCTAVES`].
+    pub octaves: u32,
+    /// Frequency multiplier from one octave to the next.
+    pub lacunarity: f32,
+    /// Amplitude multiplier from one octave to the next.
+    pub gain: f32,
+    /// Share of the noise in the flow, the rest comes from the [`FieldPrimitives`].
+    pub noise_mix: f32,
+    /// How much the contours of the [`FieldImage`] override the flow, if there is one.
+    pub image_influence: f32,
+    /// How much the [`VectorField`] overrides the flow, if there is one.
+    pub vector_field_influence: f32,
+    /// Distance in pixels from the edges of the [`ObstacleSdf`] within which particles are steered
+    /// along them.
+    pub sdf_threshold: f32,
+    pub field_type: FieldType,
+    /// Frequency multiplier on the noise used as the curl potential.
+    pub curl_frequency: f32,
+    /// Step in noise space of the finite differences taken for the curl.
+    pub curl_epsilon: f32,
+    /// Radians the direction of the noise is rotated by, turning the whole flow.
+    pub field_rotation: f32,
+    /// Shifts the noise by this many of its features, picking another part of the same field.
+    pub noise_offset: Vec2,
+    pub boundary: BoundaryMode,
+    /// Seconds a particle lives before respawning, `0.0` lets particles live forever.
+    pub particle_lifetime: f32,
+    /// Relative spread of the lifetime between particles, in `0.0..=1.0`.
+    pub lifetime_variance: f32,
+    /// How strongly the field steers the particles, lower values give more inertial motion.
+    pub field_strength: f32,
+    /// Fraction of velocity lost every 1/60th of a second, in `0.0..=FlowFieldParams::MAX_DRAG`.
+    pub drag: f32,
+    /// Speed limit in pixels per second.
+    pub max_speed: f32,
+    /// Acceleration applied to every particle regardless of its mass, relative to the speed of the
+    /// noise field per second. `y` points down.
+    pub gravity: Vec2,
+    /// Constant force pushing the particles, which accelerates light particles the most.
+    pub wind: Vec2,
+    /// Push nearby particles apart, so dense regions don't collapse into thin lines. Binning the
+    /// particles for this and the other neighbor forces costs roughly an extra millisecond per
+    /// substep at 32k particles on a mid-range GPU, and nothing while they are all disabled.
+    pub repulsion: bool,
+    /// Distance in pixels within which particles repel, at most
+    /// [`FlowFieldParams::MAX_NEIGHBOR_RADIUS`].
+    pub repulsion_radius: f32,
+    /// Acceleration between two touching particles, relative to the speed of the noise field per
+    /// second.
+    pub repulsion_strength: f32,
+    /// How quickly particles match the average velocity of their neighbors, per second, which
+    /// bundles them into ribbons. `0.0` disables it.
+    pub alignment_strength: f32,
+    /// Acceleration towards the center of the neighbors, relative to the speed of the noise field
+    /// per second. `0.0` disables it.
+    pub cohesion_strength: f32,
+    /// Distance in pixels within which particles count as neighbors for alignment and cohesion, at
+    /// most [`FlowFieldParams::MAX_NEIGHBOR_RADIUS`].
+    pub neighbor_radius: f32,
+    /// Fraction of the accumulated trail energy lost every 1/60th of a second, `0.0` keeps the
+    /// trails forever.
+    pub fade: f32,
+    /// Energy each particle deposits per update.
+    pub deposit: f32,
+    /// Spreads each deposit over a Gaussian this many pixels wide, positioned with sub-pixel
+    /// precision, to smooth out aliased trails. `0.0` deposits into a single pixel, at most
+    /// [`FlowFieldParams::MAX_SPLAT_RADIUS`].
+    pub splat_radius: f32,
+    /// Deposits along the line a particle moved since the last update rather than only at its new
+    /// position, so fast particles leave unbroken trails.
+    pub trail_lines: bool,
+    pub color_mode: ColorMode,
+    /// Saturation of the hues of [`ColorMode::Heading`], in `0.0..=1.0`.
+    pub color_saturation: f32,
+    /// Brightness of the hues of [`ColorMode::Heading`], in `0.0..=1.0`.
+    pub color_value: f32,
+    /// Color of resting particles in [`ColorMode::Speed`].
+    pub color_slow: Color,
+    /// Color of particles at the maximum speed in [`ColorMode::Speed`].
+    pub color_fast: Color,
+    /// Exponent on the relative speed before it picks the color. Values below `1.0` spread out the
+    /// slow end of the gradient, which helps when high drag keeps most particles slow.
+    pub speed_gamma: f32,
+    pub age_curve: AgeCurve,
+    pub display_mode: DisplayMode,
+    /// Maps the trail energy through `1 - exp(-energy * exposure)` so dense areas roll off instead
+    /// of blowing out. `0.0` keeps the energy linear.
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    /// Applied to the trail energy after the exposure, values above `1.0` bring out sparse areas.
+    pub gamma: f32,
+    /// Inverts the final image, for dark trails on a light background. Only changes how the
+    /// trails are shown, so toggling it keeps them.
+    pub invert: bool,
+    /// Turns of the color wheel the hues of the image are rotated by, before the inversion.
+    pub hue_shift: f32,
+    /// How much of a blurred copy of the trails is added on top of them, for a soft glow. `0.0`
+    /// skips the blur.
+    pub glow_strength: f32,
+    /// Radius in pixels of the glow blur, at most [`FlowFieldParams::MAX_GLOW_RADIUS`].
+    pub glow_radius: f32,
+    /// Lets the brightest parts of the image bleed into their surroundings, through blurred copies
+    /// at half and quarter resolution.
+    pub bloom_enabled: bool,
+    /// Brightness above which the image blooms, where `1.0` is a fully lit pixel before the
+    /// exposure.
+    pub bloom_threshold: f32,
+    /// How much of the bloom is added back on top of the image.
+    pub bloom_intensity: f32,
+    /// How much the corners of the image are darkened, in `0.0..=1.0`.
+    pub vignette_strength: f32,
+    /// Distance from the center, relative to the corners, at which the vignette starts.
+    pub vignette_radius: f32,
+    /// Amount of random brightness added to every pixel, changing every frame like film grain.
+    pub grain_amount: f32,
+    pub background_color: Color,
+    pub trail_color: Color,
+}
+
+impl Default for FlowFieldParams {
+    fn default() -> Self {
+        Self {
+            noise_scale: 280.0,
+            field_evolution_speed: 0.0,
+            octaves: 1,
+            lacunarity: 2.0,
+            gain: 0.5,
+            noise_mix: 1.0,
+            image_influence: 1.0,
+            vector_field_influence: 1.0,
+            sdf_threshold: 24.0,
+            field_type: FieldType::Angle,
+            curl_frequency: 1.0,
+            curl_epsilon: 0.01,
+            field_rotation: 0.0,
+            noise_offset: Vec2::ZERO,
+            boundary: BoundaryMode::Respawn,
+            particle_lifetime: 0.0,
+            lifetime_variance: 0.5,
+            field_strength: 1.0,
+            drag: 0.0,
+            max_speed: 100.0,
+            gravity: Vec2::ZERO,
+            wind: Vec2::ZERO,
+            repulsion: false,
+            repulsion_radius: 4.0,
+            repulsion_strength: 2.0,
+            alignment_strength: 0.0,
+            cohesion_strength: 0.0,
+            neighbor_radius: 6.0,
+            fade: 0.0,
+            deposit: 1.0,
+            splat_radius: 0.0,
+            trail_lines: true,
+            color_mode: ColorMode::Particle,
+            color_saturation: 1.0,
+            color_value: 1.0,
+            color_slow: Color::rgb_linear(0.05, 0.1, 0.6),
+            color_fast: Color::rgb_linear(1.0, 0.6, 0.1),
+            speed_gamma: 1.0,
+            age_curve: AgeCurve::Linear,
+            display_mode: DisplayMode::Linear,
+            exposure: 0.0,
+            tonemap: Tonemap::None,
+            gamma: 1.0,
+            invert: false,
+            hue_shift: 0.0,
+            glow_strength: 0.0,
+            glow_radius: 4.0,
+            bloom_enabled: false,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.5,
+            vignette_strength: 0.0,
+            vignette_radius: 0.0,
+            grain_amount: 0.0,
+            background_color: Color::rgb_linear(0.0, 0.0, 0.01),
+            trail_color: Color::WHITE,
+        }
+    }
+}
+
+impl FlowFieldParams {
+    pub const MAX_OCTAVES: u32 = 8;
+    pub const MAX_DRAG: f32 = 0.2;
+    /// Splats cover up to `(2 * radius + 1)^2` accumulated pixels, so they are kept small. The cap
+    /// applies after supersampling, which makes the widest splats smaller in the image.
+    pub const MAX_SPLAT_RADIUS: f32 = 4.0;
+    /// The size of the cells particles are binned into for the neighbor forces, which only look at
+    /// the adjacent cells.
+    pub const MAX_NEIGHBOR_RADIUS: f32 = NEIGHBOR_CELL_SIZE as f32;
+    /// Every pixel of the glow sums `2 * radius + 1` pixels in each blur pass.
+    pub const MAX_GLOW_RADIUS: f32 = 8.0;
+
+    /// A one line summary of the parameters that shape the look the most.
+    pub fn describe(&self) -> String {
+        format!(
+            "noise scale {:.0}, {} octaves, fade {:.3}, drag {:.3}",
+            self.noise_scale, self.octaves, self.fade, self.drag
+        )
+    }
+}
+
+#[derive(Component, Clone, Copy, ShaderType)]
+pub struct FlowFieldUniform {
+    size: UVec2,
+    supersample: u32,
+    noise_scale: f32,
+    field_evolution_speed: f32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+    noise_mix: f32,
+    image_influence: f32,
+    vector_field_influence: f32,
+    sdf_threshold: f32,
+    field_type: u32,
+    curl_frequency: f32,
+    curl_epsilon: f32,
+    field_rotation: f32,
+    noise_offset: Vec2,
+    boundary: u32,
+    particle_lifetime: f32,
+    lifetime_variance: f32,
+    initial_velocity: u32,
+    initial_speed_min: f32,
+    initial_speed_max: f32,
+    field_strength: f32,
+    drag: f32,
+    max_speed: f32,
+    gravity: Vec2,
+    wind: Vec2,
+    pub(crate) neighbors_enabled: u32,
+    repulsion_enabled: u32,
+    repulsion_radius: f32,
+    repulsion_strength: f32,
+    alignment_strength: f32,
+    cohesion_strength: f32,
+    neighbor_radius: f32,
+    fade: f32,
+    deposit: f32,
+    splat_radius: f32,
+    trail_lines: u32,
+    color_mode: u32,
+    color_saturation: f32,
+    color_value: f32,
+    color_slow: Vec4,
+    color_fast: Vec4,
+    speed_gamma: f32,
+    age_curve: u32,
+    pub(crate) display_mode: u32,
+    exposure: f32,
+    tonemap: u32,
+    gamma: f32,
+    invert: u32,
+    hue_shift: f32,
+    pub(crate) glow_strength: f32,
+    glow_radius: f32,
+    pub(crate) bloom_enabled: u32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+    grain_amount: f32,
+    /// Position, signed strength and radius of the active [`Pointer`]s.
+    pointers: [Vec4; PointerForce::MAX_POINTERS],
+    pointer_count: u32,
+    background_color: Vec4,
+    trail_color: Vec4,
+}
+
+impl ExtractComponent for FlowFieldParams {
+    type Query = (
+        &'static FlowFieldParams,
+        &'static FlowFieldSettings,
+        Option<&'static PointerForce>,
+    );
+    type Filter = ();
+    type Out = FlowFieldUniform;
+
+    fn extract_component(
+        (params, settings, pointer): (&FlowFieldParams, &FlowFieldSettings, Option<&PointerForce>),
+    ) -> Option<Self::Out> {
+        let (initial_speed_min, initial_speed_max) = settings.velocity.range();
+        let (pointers, pointer_count) = pointer.map_or(
+            ([Vec4::ZERO; PointerForce::MAX_POINTERS], 0),
+            PointerForce::uniform,
+        );
+        Some(FlowFieldUniform {
+            size: UVec2::new(settings.width, settings.height),
+            supersample: settings.supersample,
+            noise_scale: params.noise_scale.max(f32::EPSILON),
+            field_evolution_speed: params.field_evolution_speed,
+            octaves: params.octaves.clamp(1, FlowFieldParams::MAX_OCTAVES),
+            lacunarity: params.lacunarity,
+            gain: params.gain,
+            noise_mix: params.noise_mix.clamp(0.0, 1.0),
+            image_influence: params.image_influence.clamp(0.0, 1.0),
+            vector_field_influence: params.vector_field_influence.clamp(0.0, 1.0),
+            sdf_threshold: params.sdf_threshold.max(0.0),
+            field_type: params.field_type as u32,
+            curl_frequency: params.curl_frequency,
+            curl_epsilon: params.curl_epsilon.max(1e-5),
+            field_rotation: params.field_rotation,
+            noise_offset: params.noise_offset,
+            boundary: params.boundary as u32,
+            particle_lifetime: params.particle_lifetime,
+            lifetime_variance: params.lifetime_variance,
+            initial_velocity: settings.velocity.mode(),
+            initial_speed_min,
+            initial_speed_max,
+            field_strength: params.field_strength,
+            drag: params.drag.clamp(0.0, FlowFieldParams::MAX_DRAG),
+            max_speed: params.max_speed,
+            gravity: params.gravity,
+            wind: params.wind,
+            neighbors_enabled: (params.repulsion
+                || params.alignment_strength != 0.0
+                || params.cohesion_strength != 0.0) as u32,
+            repulsion_enabled: params.repulsion as u32,
+            repulsion_radius: params
+                .repulsion_radius
+                .clamp(0.0, FlowFieldParams::MAX_NEIGHBOR_RADIUS),
+            repulsion_strength: params.repulsion_strength,
+            alignment_strength: params.alignment_strength.max(0.0),
+            cohesion_strength: params.cohesion_strength,
+            neighbor_radius: params
+                .neighbor_radius
+                .clamp(0.0, FlowFieldParams::MAX_NEIGHBOR_RADIUS),
+            fade: params.fade.clamp(0.0, 1.0),
+            deposit: params.deposit,
+            splat_radius: params
+                .splat_radius
+                .clamp(0.0, FlowFieldParams::MAX_SPLAT_RADIUS),
+            trail_lines: params.trail_lines as u32,
+            color_mode: params.color_mode as u32,
+            color_saturation: params.color_saturation.clamp(0.0, 1.0),
+            color_value: params.color_value.clamp(0.0, 1.0),
+            color_slow: params.color_slow.as_linear_rgba_f32().into(),
+            color_fast: params.color_fast.as_linear_rgba_f32().into(),
+            speed_gamma: params.speed_gamma.max(0.01),
+            age_curve: params.age_curve as u32,
+            display_mode: params.display_mode as u32,
+            exposure: params.exposure.max(0.0),
+            tonemap: params.tonemap as u32,
+            gamma: params.gamma.max(0.01),
+            invert: params.invert as u32,
+            hue_shift: params.hue_shift.rem_euclid(1.0),
+            glow_strength: params.glow_strength.max(0.0),
+            glow_radius: params
+                .glow_radius
+                .clamp(0.0, FlowFieldParams::MAX_GLOW_RADIUS),
+            bloom_enabled: params.bloom_enabled as u32,
+            bloom_threshold: params.bloom_threshold.max(0.0),
+            bloom_intensity: params.bloom_intensity.max(0.0),
+            vignette_strength: params.vignette_strength.clamp(0.0, 1.0),
+            vignette_radius: params.vignette_radius.clamp(0.0, 1.0),
+            grain_amount: params.grain_amount.max(0.0),
+            pointers,
+            pointer_count,
+            background_color: params.background_color.as_linear_rgba_f32().into(),
+            trail_color: params.trail_color.as_linear_rgba_f32().into(),
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn prepare_sim_params(
+    mut buffer: ResMut<SimParamsBuffer>,
+    owed: Res<OwedSteps>,
+    speed: Res<SimulationSpeed>,
+    state: Res<SimulationState>,
+    step: Res<StepRequest>,
+    substeps: Res<Substeps>,
+    primitives: Res<FieldPrimitives>,
+    obstacles: Res<Obstacles>,
+    field_image: Option<Res<FieldImage>>,
+    vector_field: Option<Res<VectorField>>,
+    obstacle_sdf: Option<Res<ObstacleSdf>>,
+    palette: Option<Res<Palette>>,
+    film_effects: Res<FilmEffects>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    // Taken unconditionally so a request made while running doesn't linger until the next pause.
+    let step = step.take();
+    buffer.steps = match *state {
+        SimulationState::Running => owed.steps,
+        SimulationState::Paused => step as u32,
+    };
+    buffer.advance = buffer.steps > 0;
+
+    let dt = buffer.steps as f32 * owed.timestep;
+    let elapsed = buffer.uniform.get().elapsed + dt * speed.0;
+    let frame = buffer.uniform.get().frame.wrapping_add(1);
+    let field_image = field_image.as_ref().map(|image| &image.0);
+    let obstacle_sdf = obstacle_sdf.as_ref().map(|sdf| &sdf.0);
+    buffer.uniform.set(SimParams {
+        dt: owed.timestep / substeps.0.clamp(1, Substeps::MAX) as f32,
+        frame_dt: dt,
+        elapsed,
+        speed: speed.0,
+        primitive_count: primitives.0.len() as u32,
+        has_field_image: field_image::loaded(field_image, &gpu_images).is_some() as u32,
+        has_vector_field: vector_field.is_some() as u32,
+        obstacle_count: obstacles.0.len() as u32,
+        has_obstacle_sdf: field_image::loaded(obstacle_sdf, &gpu_images).is_some() as u32,
+        has_palette: palette.is_some() as u32,
+        frame,
+        film_effects: film_effects.0 as u32,
+    });
+    buffer.uniform.set_label(Some("flow field sim params"));
+    buffer.uniform.write_buffer(&render_device, &render_queue);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUTS: [f32; 5] = [0.0, 0.18, 1.0, 4.0, 16.0];
+
+    #[test]
+    fn tonemap_golden_values() {
+        let expected = [
+            (Tonemap::None, [0.0, 0.18, 1.0, 4.0, 16.0]),
+            (Tonemap::Reinhard, [0.0, 0.152542, 0.5, 0.8, 0.941176]),
+            (Tonemap::Aces, [0.0, 0.266899, 0.803797, 0.973417, 1.0]),
+        ];
+        assert_eq!(expected.len(), Tonemap::ALL.len());
+        for (tonemap, expected) in expected {
+            for (x, expected) in INPUTS.into_iter().zip(expected) {
+                let actual = tonemap.apply(x);
+                assert!(
+                    (actual - expected).abs() < 1e-5,
+                    "{tonemap:?} of {x} should be {expected}, got {actual}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tonemap_matches_shader() {
+        let shader = include_str!("../assets/shaders/flow_field.wgsl");
+        assert!(shader.contains("return light / (1.0 + light);"));
+        assert!(shader
+            .contains("light * (2.51 * light + 0.03) / (light * (2.43 * light + 0.59) + 0.14)"));
+    }
+}
@@ -0,0 +1,313 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{encase, ShaderSize, ShaderType},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{FlowFieldParams, FlowFieldSettings, FlowFieldSpecies};
+
+/// How particle masses are spread. Heavier particles are steered less by the field and carve longer,
+/// straighter strokes.
+#[derive(Clone, Copy, PartialEq, Debug, Reflect, Serialize, Deserialize)]
+pub enum MassDistribution {
+    Uniform {
+        min: f32,
+        max: f32,
+    },
+    /// Log-normal, centered on the geometric mean of `min` and `max` and clamped to that range.
+    LogNormal {
+        min: f32,
+        max: f32,
+    },
+}
+
+impl MassDistribution {
+    pub(crate) fn range(&self) -> (f32, f32) {
+        match *self {
+            MassDistribution::Uniform { min, max } | MassDistribution::LogNormal { min, max } => {
+                (min, max)
+            }
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> f32 {
+        let (min, max) = self.range();
+        match self {
+            MassDistribution::Uniform { .. } => min + (max - min) * rng.gen::<f32>(),
+            MassDistribution::LogNormal { .. } => {
+                // Box-Muller, with the range spanning six standard deviations.
+                let normal = (-2.0 * (1.0 - rng.gen::<f32>()).ln()).sqrt()
+                    * (std::f32::consts::TAU * rng.gen::<f32>()).cos();
+                let (log_min, log_max) = (min.ln(), max.ln());
+                let log_mass = (log_min + log_max) / 2.0 + normal * (log_max - log_min) / 6.0;
+                log_mass.exp().clamp(min, max)
+            }
+        }
+    }
+}
+
+impl Default for MassDistribution {
+    fn default() -> Self {
+        MassDistribution::Uniform { min: 1.0, max: 1.0 }
+    }
+}
+
+/// How fast and in which direction particles start out, and start over when they respawn. The
+/// field takes over within a few frames, but a bias here shows as a drift right after a reset.
+#[derive(Clone, Copy, PartialEq, Debug, Reflect, Serialize, Deserialize)]
+pub enum InitialVelocity {
+    Zero,
+    /// A uniformly random direction, at a speed in pixels per second between `min` and `max`.
+    Random {
+        min: f32,
+        max: f32,
+    },
+    /// Away from the center of the field, at a speed between `min` and `max`.
+    Radial {
+        min: f32,
+        max: f32,
+    },
+}
+
+impl InitialVelocity {
+    pub(crate) fn range(&self) -> (f32, f32) {
+        match *self {
+            InitialVelocity::Zero => (0.0, 0.0),
+            InitialVelocity::Random { min, max } | InitialVelocity::Radial { min, max } => {
+                (min, max)
+            }
+        }
+    }
+
+    /// The `VELOCITY_*` constant of the shader, which samples respawned particles the same way.
+    pub(crate) fn mode(&self) -> u32 {
+        match self {
+            InitialVelocity::Zero => 0,
+            InitialVelocity::Random { .. } => 1,
+            InitialVelocity::Radial { .. } => 2,
+        }
+    }
+
+    fn sample(&self, position: Vec2, center: Vec2, rng: &mut impl Rng) -> Vec2 {
+        let (min, max) = self.range();
+        let speed = min + (max - min) * rng.gen::<f32>();
+        match self {
+            InitialVelocity::Zero => Vec2::ZERO,
+            InitialVelocity::Random { .. } => {
+                Vec2::from_angle(std::f32::consts::TAU * rng.gen::<f32>()) * speed
+            }
+            InitialVelocity::Radial { .. } => (position - center).normalize_or_zero() * speed,
+        }
+    }
+}
+
+impl Default for InitialVelocity {
+    fn default() -> Self {
+        InitialVelocity::Random { min: 0.0, max: 1.0 }
+    }
+}
+
+/// How initial particle colors are assigned. Particles keep their color when they respawn, so
+/// colors that start out apart gradually mix.
+#[derive(Clone, Copy, PartialEq, Debug, Reflect, Serialize, Deserialize)]
+pub enum ParticleColoring {
+    Solid(Color),
+    /// Particles on the left half start out `left`, the others `right`.
+    HorizontalSplit {
+        left: Color,
+        right: Color,
+    },
+    /// Blends from `left` to `right` with the starting position.
+    HorizontalGradient {
+        left: Color,
+        right: Color,
+    },
+    /// A random blend of `a` and `b` per particle.
+    Random {
+        a: Color,
+        b: Color,
+    },
+}
+
+impl ParticleColoring {
+    fn color(&self, x: f32, rng: &mut impl Rng) -> Vec4 {
+        let mix = |a: Color, b: Color, t: f32| {
+            Vec4::from(a.as_linear_rgba_f32()).lerp(Vec4::from(b.as_linear_rgba_f32()), t)
+        };
+        match *self {
+            ParticleColoring::Solid(color) => color.as_linear_rgba_f32().into(),
+            ParticleColoring::HorizontalSplit { left, right } => {
+                mix(left, right, if x < 0.5 { 0.0 } else { 1.0 })
+            }
+            ParticleColoring::HorizontalGradient { left, right } => mix(left, right, x),
+            ParticleColoring::Random { a, b } => mix(a, b, rng.gen()),
+        }
+    }
+}
+
+impl Default for ParticleColoring {
+    fn default() -> Self {
+        ParticleColoring::Solid(Color::WHITE)
+    }
+}
+
+/// One particle as the shaders see it. Encoded with the storage layout of encase, which matches
+/// WGSL: `position` at byte 0, `velocity` at 8, the four scalars from 16 to 28, `color` aligned to
+/// 32 and `species` at 48, padded to 64 bytes by the 16 byte alignment of `color`. The shaders
+/// index the particle buffer by this size, so new fields have to go into the `Particle` struct of
+/// `flow_field.wgsl` in the same order.
+#[derive(Clone, Copy, ShaderType)]
+pub struct Particle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub seed: u32,
+    /// Seconds since the particle (re)spawned.
+    pub age: f32,
+    /// Age at which the particle respawns, `0.0` for particles that live forever.
+    pub max_age: f32,
+    pub mass: f32,
+    /// Linear RGBA color the particle deposits, multiplied with the field's trail color.
+    pub color: Vec4,
+    /// Index into the field's [`FlowFieldSpecies`].
+    pub species: u32,
+}
+
+// The WGSL `Particle` struct has to match this layout.
+const _: () = assert!(<Particle as ShaderSize>::SHADER_SIZE.get() == 64);
+
+/// The encoded contents of a freshly initialized particle buffer.
+pub(crate) fn initial_particles(
+    settings: &FlowFieldSettings,
+    params: &FlowFieldParams,
+    species: &FlowFieldSpecies,
+    seed: u64,
+) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let center = Vec2::new(settings.width as f32, settings.height as f32) / 2.0;
+
+    let mut particles = vec![
+        Particle {
+            position: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+            seed: 0,
+            age: 0.0,
+            max_age: 0.0,
+            mass: 1.0,
+            color: Vec4::ONE,
+            species: 0,
+        };
+        settings.nr_particles as usize
+    ];
+
+    for (i, p) in &mut particles.iter_mut().enumerate() {
+        p.position = Vec2::new(
+            rng.gen::<f32>() * settings.width as f32,
+            rng.gen::<f32>() * settings.height as f32,
+        );
+        p.velocity = settings.velocity.sample(p.position, center, &mut rng);
+
+        p.seed = particle_seed(seed, i as u32);
+
+        // Start at a random point in their life so the particles don't all respawn at once.
+        p.max_age = params.particle_lifetime
+            * (1.0 + params.lifetime_variance * (rng.gen::<f32>() * 2.0 - 1.0)).max(0.0);
+        p.age = rng.gen::<f32>() * p.max_age;
+        p.mass = settings.mass.sample(&mut rng);
+        p.color = settings
+            .coloring
+            .color(p.position.x / settings.width as f32, &mut rng);
+        p.species = species.pick(&mut rng);
+    }
+
+    let mut particle_byte_buffer: Vec<u8> = Vec::new();
+    let mut particle_buffer = encase::StorageBuffer::new(&mut particle_byte_buffer);
+    particle_buffer.write(&particles).unwrap();
+    particle_byte_buffer
+}
+
+/// Starting state of the hash the shader uses for respawning particles.
+fn particle_seed(seed: u64, index: u32) -> u32 {
+    let mixed = (seed ^ index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    (mixed >> 32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 10_000;
+
+    fn mean_velocity(velocity: InitialVelocity) -> Vec2 {
+        let mut rng = StdRng::seed_from_u64(7);
+        let center = Vec2::new(640.0, 360.0);
+        let sum: Vec2 = (0..SAMPLES)
+            .map(|_| {
+                let position = Vec2::new(rng.gen::<f32>(), rng.gen::<f32>()) * center * 2.0;
+                velocity.sample(position, center, &mut rng)
+            })
+            .sum();
+        sum / SAMPLES as f32
+    }
+
+    #[test]
+    fn initial_velocity_has_no_drift() {
+        for velocity in [
+            InitialVelocity::Random { min: 0.0, max: 1.0 },
+            InitialVelocity::Random { min: 2.0, max: 3.0 },
+            InitialVelocity::Radial { min: 1.0, max: 1.0 },
+        ] {
+            let mean = mean_velocity(velocity);
+            let (_, max) = velocity.range();
+            assert!(mean.length() < 0.05 * max, "{velocity:?} drifts by {mean}");
+        }
+        assert_eq!(mean_velocity(InitialVelocity::Zero), Vec2::ZERO);
+    }
+
+    #[test]
+    fn velocity_modes_match_shader() {
+        let shader = include_str!("../assets/shaders/flow_field.wgsl");
+        for (name, velocity) in [
+            ("ZERO", InitialVelocity::Zero),
+            ("RANDOM", InitialVelocity::Random { min: 0.0, max: 1.0 }),
+            ("RADIAL", InitialVelocity::Radial { min: 0.0, max: 1.0 }),
+        ] {
+            let constant = format!("const VELOCITY_{name}: u32 = {}u;", velocity.mode());
+            assert!(shader.contains(&constant), "the shader lacks `{constant}`");
+        }
+    }
+
+    #[test]
+    fn particle_layout() {
+        let particle = Particle {
+            position: Vec2::new(1.0, 2.0),
+            velocity: Vec2::new(3.0, 4.0),
+            seed: 5,
+            age: 6.0,
+            max_age: 7.0,
+            mass: 8.0,
+            color: Vec4::new(0.1, 0.2, 0.3, 0.4),
+            species: 9,
+        };
+        for n in [1, 3, 64] {
+            let mut bytes = Vec::new();
+            encase::StorageBuffer::new(&mut bytes)
+                .write(&vec![particle; n])
+                .unwrap();
+            // The runtime sized array has no header, the shaders count the particles by this length.
+            assert_eq!(bytes.len() as u64, Particle::min_size().get() * n as u64);
+        }
+
+        let mut bytes = Vec::new();
+        encase::StorageBuffer::new(&mut bytes)
+            .write(&particle)
+            .unwrap();
+        let word =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        assert_eq!(f32::from_bits(word(8)), 3.0);
+        assert_eq!(word(16), 5);
+        assert_eq!(f32::from_bits(word(28)), 8.0);
+        assert_eq!(f32::from_bits(word(32)), 0.1);
+        assert_eq!(word(48), 9);
+    }
+}
@@ -0,0 +1,639 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_component::ComponentUniforms,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+            BufferBinding, BufferBindingType, BufferId, CachedComputePipelineId,
+            ComputePipelineDescriptor, PipelineCache, ShaderDefVal, ShaderStages, ShaderType,
+            StorageTextureAccess, TextureSampleType, TextureViewDimension, TextureViewId,
+        },
+        renderer::RenderDevice,
+        texture::FallbackImage,
+    },
+};
+
+use crate::{
+    field_image, plugin::MAX_WORKGROUPS, ComputeInput, CustomField, FieldImage,
+    FieldPrimitivesBuffer, FlowFieldSettings, FlowFieldUniform, ObstacleSdf, ObstaclesBuffer,
+    OutputFormat, PaletteTexture, ParticleBuffer, SimParams, SimParamsBuffer, VectorFieldTexture,
+    WorkgroupSize,
+};
+
+/// The embedded compute shader every flow field is simulated with, unless
+/// [`ComputePlugin::with_shader_path`](crate::ComputePlugin::with_shader_path) loads another.
+pub const SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5f1c_7a3e_9b24_d801);
+
+/// The shader the [`ComputePipeline`] is built from, the [`SHADER_HANDLE`] or the one loaded from
+/// disk.
+#[derive(Resource)]
+pub(crate) struct ComputeShader(pub(crate) Handle<Shader>);
+
+#[derive(Resource)]
+pub struct ComputePipeline {
+    bind_group_layout: BindGroupLayout,
+    shader: Handle<Shader>,
+    pub(crate) workgroup_size: WorkgroupSize,
+    output_format: OutputFormat,
+    /// The [`CustomField`] the programs were queued with.
+    custom_field: CustomField,
+    /// One pipeline per [`Program`], in the order of [`ENTRY_POINTS`].
+    programs: [CachedComputePipelineId; ENTRY_POINTS.len()],
+}
+
+impl ComputePipeline {
+    pub(crate) fn programs(&self) -> [CachedComputePipelineId; ENTRY_POINTS.len()] {
+        self.programs
+    }
+}
+
+#[derive(Component)]
+pub struct ComputeBindGroup(pub(crate) BindGroup);
+
+/// The views and buffers bound by a [`ComputeBindGroup`], which only change when one of them is
+/// recreated: on a resize, a reload of an image or when a buffer outgrows its contents.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct BindGroupKey {
+    views: [TextureViewId; 5],
+    buffers: [BufferId; 12],
+}
+
+/// The bind group of every flow field with the resources it was created from, reused while they
+/// stay the same. Kept in a resource as render world entities are cleared every frame.
+#[derive(Resource, Default)]
+pub(crate) struct BindGroupCache(HashMap<Entity, (BindGroupKey, BindGroup)>);
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn prepare_bind_group(
+    mut commands: Commands,
+    pipeline: Res<ComputePipeline>,
+    gpu_images: Res<RenderAssets<Image>>,
+    uniforms: Res<ComponentUniforms<FlowFieldUniform>>,
+    sim_params: Res<SimParamsBuffer>,
+    primitives: Res<FieldPrimitivesBuffer>,
+    obstacles: Res<ObstaclesBuffer>,
+    field_image: Option<Res<FieldImage>>,
+    obstacle_sdf: Option<Res<ObstacleSdf>>,
+    vector_field: Res<VectorFieldTexture>,
+    palette: Res<PaletteTexture>,
+    fallback_image: Res<FallbackImage>,
+    fields: Query<(Entity, &ComputeInput, &ParticleBuffer)>,
+    mut cache: ResMut<BindGroupCache>,
+    render_device: Res<RenderDevice>,
+) {
+    let (Some(params), Some(params_buffer)) = (uniforms.binding(), uniforms.uniforms().buffer())
+    else {
+        return;
+    };
+    let (Some(sim_params), Some(sim_params_buffer)) =
+        (sim_params.uniform.binding(), sim_params.uniform.buffer())
+    else {
+        return;
+    };
+    let (Some(primitives), Some(primitives_buffer)) =
+        (primitives.buffer.binding(), primitives.buffer.buffer())
+    else {
+        return;
+    };
+    let (Some(obstacles), Some(obstacles_buffer)) =
+        (obstacles.buffer.binding(), obstacles.buffer.buffer())
+    else {
+        return;
+    };
+    let field_image = field_image::view(
+        field_image.as_ref().map(|image| &image.0),
+        &gpu_images,
+        &fallback_image,
+    );
+    let obstacle_sdf = field_image::view(
+        obstacle_sdf.as_ref().map(|sdf| &sdf.0),
+        &gpu_images,
+        &fallback_image,
+    );
+    let vector_field = vector_field
+        .view()
+        .unwrap_or(&fallback_image.d2.texture_view);
+    let palette = palette.view().unwrap_or(&fallback_image.d2.texture_view);
+
+    cache.0.retain(|entity, _| fields.contains(*entity));
+    for (entity, input, particles) in &fields {
+        // The image of a resized field may not have been prepared yet, it's skipped for a frame.
+        let Some(view) = gpu_images.get(&input.dst_image) else {
+            continue;
+        };
+        let key = BindGroupKey {
+            views: [
+                view.texture_view.id(),
+                field_image.texture_view.id(),
+                vector_field.id(),
+                obstacle_sdf.texture_view.id(),
+                palette.id(),
+            ],
+            buffers: [
+                particles.particles.id(),
+                particles.energies.id(),
+                params_buffer.id(),
+                sim_params_buffer.id(),
+                particles.species.id(),
+                primitives_buffer.id(),
+                obstacles_buffer.id(),
+                particles.grid_cells.id(),
+                particles.sorted_particles.id(),
+                particles.display_stats.id(),
+                particles.glow.id(),
+                particles.bloom.id(),
+            ],
+        };
+        if let Some((cached, bind_group)) = cache.0.get(&entity) {
+            if *cached == key {
+                commands
+                    .entity(entity)
+                    .insert(ComputeBindGroup(bind_group.clone()));
+                continue;
+            }
+        }
+
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("flow field bind group"),
+            layout: &pipeline.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.particles,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.energies,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: params.clone(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: sim_params.clone(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.species,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: primitives.clone(),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(&field_image.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: BindingResource::TextureView(vector_field),
+                },
+                BindGroupEntry {
+                    binding: 9,
+                    resource: obstacles.clone(),
+                },
+                BindGroupEntry {
+                    binding: 10,
+                    resource: BindingResource::TextureView(&obstacle_sdf.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 11,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.grid_cells,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 12,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.sorted_particles,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 13,
+                    resource: BindingResource::TextureView(palette),
+                },
+                BindGroupEntry {
+                    binding: 14,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.display_stats,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 15,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.glow,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 16,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &particles.bloom,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+            ],
+        });
+        cache.0.insert(entity, (key, bind_group.clone()));
+        commands.entity(entity).insert(ComputeBindGroup(bind_group));
+    }
+}
+
+impl FromWorld for ComputePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let output_format = *world.resource::<OutputFormat>();
+        let bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("flow field bind group layout"),
+                    entries: &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::StorageTexture {
+                                access: StorageTextureAccess::WriteOnly,
+                                format: output_format.texture_format(),
+                                view_dimension: TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: true,
+                                min_binding_size: Some(FlowFieldUniform::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(SimParams::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 5,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 6,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 7,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                // Only loaded from, so float images of any kind can be bound.
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 8,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                // Rg32Float isn't filterable on most devices.
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 9,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 10,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 11,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 12,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 13,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 14,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 15,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 16,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let workgroup_size = *world.resource::<WorkgroupSize>();
+        let shader = world.resource::<ComputeShader>().0.clone();
+        let custom_field = world.resource::<CustomField>().clone();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let programs = queue_programs(
+            pipeline_cache,
+            &bind_group_layout,
+            &shader,
+            workgroup_size,
+            output_format,
+            &custom_field,
+        );
+
+        ComputePipeline {
+            bind_group_layout,
+            shader,
+            workgroup_size,
+            output_format,
+            custom_field,
+            programs,
+        }
+    }
+}
+
+/// The programs of the compute shader, indexing [`ENTRY_POINTS`] and the pipelines of the
+/// [`ComputePipeline`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Program {
+    Update,
+    Draw,
+    Fade,
+    /// Build the neighbor grid: count the particles per cell, turn the counts into offsets and
+    /// sort the particles into their cells.
+    BinParticles,
+    PrefixSum,
+    ScatterParticles,
+    /// Measure the energy for the [`DisplayMode`](crate::DisplayMode)s that normalize it: the
+    /// peak, a histogram and its running sum.
+    EnergyMax,
+    EnergyHistogram,
+    EnergyCdf,
+    /// Blur the energies into [`ParticleBuffer::glow`], one axis at a time.
+    GlowHorizontal,
+    GlowVertical,
+    /// Build the bloom: extract the bright parts at half resolution, shrink them to a quarter and
+    /// blur both levels, which `draw` scales back up.
+    BloomThreshold,
+    BloomDownsample,
+    BloomHorizontal,
+    BloomVertical,
+}
+
+/// The entry point of every [`Program`], in the order of its variants.
+pub(crate) const ENTRY_POINTS: [&str; 15] = [
+    "update",
+    "draw",
+    "fade",
+    "bin_particles",
+    "prefix_sum",
+    "scatter_particles",
+    "energy_max",
+    "energy_histogram",
+    "energy_cdf",
+    "glow_horizontal",
+    "glow_vertical",
+    "bloom_threshold",
+    "bloom_downsample",
+    "bloom_horizontal",
+    "bloom_vertical",
+];
+
+fn queue_programs(
+    pipeline_cache: &PipelineCache,
+    bind_group_layout: &BindGroupLayout,
+    shader: &Handle<Shader>,
+    workgroup_size: WorkgroupSize,
+    output_format: OutputFormat,
+    custom_field: &CustomField,
+) -> [CachedComputePipelineId; ENTRY_POINTS.len()] {
+    let mut shader_defs = vec![
+        ShaderDefVal::UInt("WORKGROUP_SIZE".to_string(), workgroup_size.0),
+        output_format.shader_def(),
+    ];
+    if custom_field.0.is_some() {
+        shader_defs.push("CUSTOM_FIELD".into());
+    }
+    ENTRY_POINTS.map(|entry_point| {
+        pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some(format!("flow field {entry_point}").into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from(entry_point),
+        })
+    })
+}
+
+fn max_workgroup_size(render_device: &RenderDevice) -> u32 {
+    let limits = render_device.limits();
+    limits
+        .max_compute_workgroup_size_x
+        .min(limits.max_compute_invocations_per_workgroup)
+}
+
+/// The [`WorkgroupSize`] if the device can run it, otherwise the largest power of two it can, or
+/// one in place of zero.
+pub(crate) fn supported_workgroup_size(
+    workgroup_size: WorkgroupSize,
+    render_device: &RenderDevice,
+) -> WorkgroupSize {
+    let max = max_workgroup_size(render_device);
+    match workgroup_size.0 {
+        0 => WorkgroupSize(1),
+        size if size > max => WorkgroupSize(1 << max.ilog2()),
+        _ => workgroup_size,
+    }
+}
+
+/// [`supported_workgroup_size`], with a warning if that isn't the requested one.
+pub(crate) fn fit_workgroup_size(
+    workgroup_size: WorkgroupSize,
+    render_device: &RenderDevice,
+) -> WorkgroupSize {
+    let fitted = supported_workgroup_size(workgroup_size, render_device);
+    if fitted != workgroup_size {
+        warn!(
+            "flow field workgroup size {} must be between 1 and the device limit of {}, using {}",
+            workgroup_size.0,
+            max_workgroup_size(render_device),
+            fitted.0
+        );
+    }
+    fitted
+}
+
+/// Recompiles the programs when [`WorkgroupSize`] or the [`CustomField`] changes. The old
+/// pipelines stay in the cache but nothing is dispatched until the new ones are ready. A workgroup
+/// size too small to dispatch the particles of every flow field is refused.
+pub(crate) fn requeue_pipelines(
+    mut requested: Local<Option<WorkgroupSize>>,
+    mut pipeline: ResMut<ComputePipeline>,
+    workgroup_size: Res<WorkgroupSize>,
+    custom_field: Res<CustomField>,
+    fields: Query<&FlowFieldSettings>,
+    pipeline_cache: Res<PipelineCache>,
+    render_device: Res<RenderDevice>,
+) {
+    // Fitted once per requested size, so one the device can't run warns a single time.
+    let fitted = match *requested == Some(*workgroup_size) {
+        true => pipeline.workgroup_size,
+        false => {
+            let fitted = fit_workgroup_size(*workgroup_size, &render_device);
+            let max_particles = MAX_WORKGROUPS as u64 * fitted.0 as u64;
+            match fields.iter().map(|settings| settings.nr_particles).max() {
+                Some(most) if most as u64 > max_particles => {
+                    warn!(
+                        "flow field workgroup size {} can't dispatch {most} particles in \
+                         {MAX_WORKGROUPS} workgroups, keeping {}",
+                        fitted.0, pipeline.workgroup_size.0
+                    );
+                    pipeline.workgroup_size
+                }
+                _ => fitted,
+            }
+        }
+    };
+    *requested = Some(*workgroup_size);
+    if fitted == pipeline.workgroup_size && *custom_field == pipeline.custom_field {
+        return;
+    }
+
+    let pipeline = &mut *pipeline;
+    pipeline.programs = queue_programs(
+        &pipeline_cache,
+        &pipeline.bind_group_layout,
+        &pipeline.shader,
+        fitted,
+        pipeline.output_format,
+        &custom_field,
+    );
+    pipeline.workgroup_size = fitted;
+    pipeline.custom_field = custom_field.clone();
+}
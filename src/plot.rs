@@ -0,0 +1,334 @@
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ShaderSize,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+use crate::{
+    exr, poll, ComputeInput, FlowFieldOutput, FlowFieldSettings, ParticleBuffer, SimulatedFrames,
+};
+
+/// Bytes of every [`Particle`](crate::Particle) that are read back, up to and including its color.
+const SAMPLE_BYTES: u64 = 48;
+
+/// Points closer than this to the previous one of their polyline are left out, in pixels.
+const MIN_SEGMENT: f32 = 0.5;
+
+/// How [`Trajectories::write_svg`] draws the polylines.
+#[derive(Clone, Debug)]
+pub struct PlotStyle {
+    pub stroke_width: f32,
+    /// Pen color of every polyline, `None` uses the color of its particle.
+    pub color: Option<Color>,
+    /// Fills the page before drawing, `None` leaves it transparent.
+    pub background: Option<Color>,
+}
+
+impl Default for PlotStyle {
+    fn default() -> Self {
+        Self {
+            stroke_width: 0.5,
+            color: None,
+            background: None,
+        }
+    }
+}
+
+/// Records the paths of up to `max_particles` particles of the [`FlowFieldOutput`]'s field, every
+/// `nr_particles / max_particles`th one, for exporting them as vector art for pen plotters. The
+/// positions are read back from the GPU every simulated frame while recording. Paths are broken
+/// where particles respawn and clipped at the edges where they wrap around. Shared with the
+/// render world like [`StepRequest`](crate::StepRequest).
+#[derive(Resource, Clone)]
+pub struct Trajectories(Arc<Mutex<Paths>>);
+
+impl Default for Trajectories {
+    fn default() -> Self {
+        Self::new(2000)
+    }
+}
+
+impl Trajectories {
+    pub fn new(max_particles: u32) -> Self {
+        Self(Arc::new(Mutex::new(Paths {
+            max_particles: max_particles.max(1),
+            ..default()
+        })))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.paths().recording
+    }
+
+    /// Starts or resumes recording, continuing the existing paths.
+    pub fn start(&self) {
+        self.paths().recording = true;
+    }
+
+    pub fn stop(&self) {
+        self.paths().recording = false;
+    }
+
+    /// Forgets all recorded paths.
+    pub fn clear(&self) {
+        let mut paths = self.paths();
+        paths.polylines.clear();
+        paths.open.clear();
+    }
+
+    /// Number of polylines with at least one segment.
+    pub fn len(&self) -> usize {
+        self.paths().drawn().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the recorded paths as an SVG sized like the field, one pixel per unit.
+    pub fn write_svg(&self, path: &Path, style: &PlotStyle) -> io::Result<()> {
+        let paths = self.paths();
+        let to_hex = |color: Color| {
+            let [r, g, b, _] = color.as_rgba_u8();
+            format!("#{r:02x}{g:02x}{b:02x}")
+        };
+        let (width, height) = (paths.size.x, paths.size.y);
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}""#
+        );
+        let _ = writeln!(svg, r#"     viewBox="0 0 {width} {height}">"#);
+        if let Some(background) = style.background {
+            let fill = to_hex(background);
+            let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{fill}"/>"#);
+        }
+        let _ = writeln!(
+            svg,
+            r#"<g fill="none" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round">"#,
+            style.stroke_width
+        );
+        for polyline in paths.drawn() {
+            let stroke = to_hex(style.color.unwrap_or(polyline.color));
+            let _ = write!(svg, r#"<polyline stroke="{stroke}" points=""#);
+            for (i, point) in polyline.points.iter().enumerate() {
+                let separator = if i == 0 { "" } else { " " };
+                let _ = write!(svg, "{separator}{:.2},{:.2}", point.x, point.y);
+            }
+            svg.push_str("\"/>\n");
+        }
+        svg.push_str("</g>\n</svg>\n");
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, svg)
+    }
+
+    fn paths(&self) -> std::sync::MutexGuard<'_, Paths> {
+        self.0.lock().unwrap()
+    }
+}
+
+#[derive(Default)]
+struct Paths {
+    max_particles: u32,
+    recording: bool,
+    /// Size of the field the paths were recorded in.
+    size: UVec2,
+    polylines: Vec<Polyline>,
+    /// Per sampled particle its polyline and age at the last sample.
+    open: Vec<Option<(usize, f32)>>,
+}
+
+struct Polyline {
+    color: Color,
+    points: Vec<Vec2>,
+}
+
+impl Paths {
+    fn drawn(&self) -> impl Iterator<Item = &Polyline> {
+        self.polylines.iter().filter(|line| line.points.len() > 1)
+    }
+
+    fn begin(&mut self, particle: usize, position: Vec2, age: f32, color: Color) {
+        self.polylines.push(Polyline {
+            color,
+            points: vec![position],
+        });
+        self.open[particle] = Some((self.polylines.len() - 1, age));
+    }
+
+    /// Appends one frame of samples, as read back from the particle buffer.
+    fn append(&mut self, size: UVec2, samples: &[u8]) {
+        let count = samples.len() / SAMPLE_BYTES as usize;
+        if self.size != size || self.open.len() != count {
+            // The field was resized, the old paths can't continue.
+            self.open = vec![None; count];
+            self.size = size;
+        }
+        let size = size.as_vec2();
+
+        for (particle, sample) in samples.chunks_exact(SAMPLE_BYTES as usize).enumerate() {
+            let floats: &[f32] = bytemuck::cast_slice(sample);
+            let position = Vec2::new(floats[0], floats[1]);
+            let age = floats[5];
+            let [r, g, b, a] = [floats[8], floats[9], floats[10], floats[11]];
+            let color = Color::rgba_linear(r, g, b, a);
+
+            let Some((line, last_age)) = self.open[particle] else {
+                self.begin(particle, position, age, color);
+                continue;
+            };
+            let last = *self.polylines[line].points.last().unwrap();
+            if age < last_age {
+                // Respawned somewhere else.
+                self.begin(particle, position, age, color);
+                continue;
+            }
+
+            // A jump of more than half the field is a wrap around, in which case the path is
+            // continued to the edge and picked up again at the opposite one.
+            let delta = position - last;
+            let wrap = Vec2::select(
+                delta.abs().cmpgt(size / 2.0),
+                -delta.signum() * size,
+                Vec2::ZERO,
+            );
+            if wrap != Vec2::ZERO {
+                let edge = clip_to_bounds(last, position + wrap, size);
+                self.polylines[line].points.push(edge);
+                self.begin(particle, edge - wrap, age, color);
+                self.polylines.last_mut().unwrap().points.push(position);
+                continue;
+            }
+
+            if delta.length() >= MIN_SEGMENT {
+                self.polylines[line].points.push(position);
+            }
+            self.open[particle] = Some((line, age));
+        }
+    }
+}
+
+/// Where the segment from `inside` to `outside` leaves the field.
+fn clip_to_bounds(inside: Vec2, outside: Vec2, size: Vec2) -> Vec2 {
+    let delta = outside - inside;
+    let t = [0, 1]
+        .into_iter()
+        .filter(|&axis| delta[axis] != 0.0)
+        .map(|axis| {
+            let edge = if delta[axis] > 0.0 { size[axis] } else { 0.0 };
+            (edge - inside[axis]) / delta[axis]
+        })
+        .fold(1.0f32, f32::min)
+        .clamp(0.0, 1.0);
+    inside + delta * t
+}
+
+/// Render world side of the [`Trajectories`]: while one frame's samples are copied, the previous
+/// one's are appended, so the GPU only has to catch up with the previous frame.
+#[derive(Resource, Default)]
+pub(crate) struct Plotter {
+    pending: Option<PendingSamples>,
+    spare: Option<Buffer>,
+    /// [`SimulatedFrames`] when the last samples were taken.
+    sampled: u64,
+}
+
+struct PendingSamples {
+    staging: Buffer,
+    /// Resources have to be `Sync`, the receiver is only ever used through `&mut self`.
+    mapped: Mutex<mpsc::Receiver<Result<(), String>>>,
+    size: UVec2,
+}
+
+impl Plotter {
+    /// Waits for the pending samples and appends them.
+    pub(crate) fn flush(&mut self, trajectories: &Trajectories, render_queue: &RenderQueue) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let mapped = poll::wait_for_map(render_queue, &pending.mapped.into_inner().unwrap());
+        match mapped.expect("the trajectory buffer was dropped") {
+            Ok(()) => {
+                let data = pending.staging.slice(..).get_mapped_range();
+                trajectories.paths().append(pending.size, &data);
+            }
+            Err(err) => error!("could not read the particle paths back: {err}"),
+        }
+        pending.staging.unmap();
+        self.spare = Some(pending.staging);
+    }
+}
+
+pub(crate) fn record_trajectories(
+    mut plotter: ResMut<Plotter>,
+    trajectories: Res<Trajectories>,
+    frames: Res<SimulatedFrames>,
+    output: Option<Res<FlowFieldOutput>>,
+    fields: Query<(&FlowFieldSettings, &ParticleBuffer, &ComputeInput)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let advanced = frames.0 != plotter.sampled;
+    plotter.sampled = frames.0;
+    if !trajectories.is_recording() {
+        plotter.flush(&trajectories, &render_queue);
+        return;
+    }
+    if !advanced {
+        return;
+    }
+    let Some((settings, buffers)) = exr::output_field(output.as_deref(), &fields) else {
+        return;
+    };
+
+    let max_particles = trajectories.paths().max_particles;
+    let stride = settings.nr_particles.div_ceil(max_particles).max(1);
+    let count = settings.nr_particles.div_ceil(stride);
+    let size = count as u64 * SAMPLE_BYTES;
+    let staging = match plotter.spare.take() {
+        Some(spare) if spare.size() == size => spare,
+        _ => render_device.create_buffer(&BufferDescriptor {
+            label: Some("flow field trajectory readback"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }),
+    };
+
+    let particle_size = crate::Particle::SHADER_SIZE.get();
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+    for i in 0..count as u64 {
+        let source = i * stride as u64 * particle_size;
+        encoder.copy_buffer_to_buffer(
+            &buffers.particles,
+            source,
+            &staging,
+            i * SAMPLE_BYTES,
+            SAMPLE_BYTES,
+        );
+    }
+    render_queue.submit([encoder.finish()]);
+    let mapped = poll::map_read(&render_device, &staging);
+
+    plotter.flush(&trajectories, &render_queue);
+    plotter.pending = Some(PendingSamples {
+        staging,
+        mapped: Mutex::new(mapped),
+        size: UVec2::new(settings.width, settings.height),
+    });
+}
@@ -0,0 +1,979 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_graph::RenderGraph,
+        render_resource::{ShaderDefVal, ShaderType, TextureFormat, TextureUsages},
+        renderer::{RenderAdapter, RenderDevice},
+        texture::TextureFormatPixelInfo,
+        Render, RenderApp, RenderSet,
+    },
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "export-exr")]
+use crate::exr;
+use crate::{
+    buffers::{
+        init_flow_fields, reset_flow_fields, resize_flow_fields, resize_particles,
+        resize_post_buffers, update_buffer_info,
+    },
+    custom_field::{update_custom_field, CUSTOM_FIELD_SHADER_HANDLE},
+    field_image, interpolation, obstacles, palette,
+    params::prepare_sim_params,
+    pipeline::{
+        fit_workgroup_size, prepare_bind_group, requeue_pipelines, supported_workgroup_size,
+        BindGroupCache, ComputeShader,
+    },
+    plot, presets, primitives, sdf, shutdown, snapshot, species,
+    sprite::{fit_sprite, fit_to_window, follow_background, follow_window, spawn_sprite},
+    target::follow_targets,
+    vector_field, ComputeInput, ComputeNode, ComputePipeline, CustomField, EnergyExportRequest,
+    FieldPrimitive, FieldPrimitives, FieldPrimitivesBuffer, FlowFieldParams, FlowFieldTarget,
+    FlowFieldUniform, GifRequest, GifSettings, InitialVelocity, MassDistribution, Obstacles,
+    ObstaclesBuffer, Palette, PaletteTexture, Particle, ParticleBuffer, ParticleBufferInfo,
+    ParticleColoring, PendingExports, Presets, Recording, ScreenshotRequest, SimParamsBuffer,
+    Snapshots, Trajectories, VectorFieldTexture, NODE_NAME, SHADER_HANDLE,
+};
+#[cfg(feature = "export-png")]
+use crate::{gif, recording, screenshot, GIF_SHADER_HANDLE};
+
+const DEFAULT_WORKGROUP_SIZE: u32 = 256;
+/// Workgroups every device can dispatch along one dimension, which bounds the particle count.
+pub(crate) const MAX_WORKGROUPS: u32 = 65535;
+
+/// Send to restart every flow field from freshly initialized particles and an empty image.
+#[derive(Event, Clone, Copy, Default)]
+pub struct ResetSimulation;
+
+/// Global seed from which every flow field without its own seed derives its initial state, so runs
+/// with the same seed and parameters start out bit-identical.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Seed(pub u64);
+
+/// The variation of the [`Seed`] picked with
+/// [`InputBindings::variations`](crate::InputBindings::variations). Variation `n` starts the
+/// particles from the seed the app started with plus `n`, and shifts the noise by an offset derived
+/// from that seed.
+#[derive(Resource, Clone, Copy, Debug, ExtractResource)]
+pub struct SeedVariation {
+    pub base: u64,
+    pub index: Option<u32>,
+}
+
+impl SeedVariation {
+    /// Switches to variation `index`, returning its seed.
+    pub fn select(&mut self, index: u32) -> u64 {
+        self.index = Some(index);
+        self.base.wrapping_add(index as u64)
+    }
+
+    /// The [`FlowFieldParams::noise_offset`] of the variation with `seed`.
+    pub fn noise_offset(seed: u64) -> Vec2 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Vec2::new(rng.gen(), rng.gen()) * 1000.0
+    }
+}
+
+impl Seed {
+    pub(crate) fn for_field(&self, entity: Entity, settings: &FlowFieldSettings) -> u64 {
+        settings
+            .seed
+            .unwrap_or_else(|| self.0.wrapping_add(entity.index() as u64))
+    }
+}
+
+/// Describes a single flow field. Spawning an entity with this component (see [`FlowFieldBundle`])
+/// creates its output image and GPU buffers on the next update.
+#[derive(Component, Clone, Copy, Reflect, ExtractComponent)]
+#[reflect(Component)]
+pub struct FlowFieldSettings {
+    pub width: u32,
+    pub height: u32,
+    pub nr_particles: u32,
+    /// Seed for the initial particle state, `None` derives one from the global [`Seed`].
+    pub seed: Option<u64>,
+    pub mass: MassDistribution,
+    pub velocity: InitialVelocity,
+    pub coloring: ParticleColoring,
+    /// Accumulates the trails at 1, 2 or 4 times the resolution in each direction and box filters
+    /// them down for display, which smooths thin trails. The particles keep moving in image pixels,
+    /// so the look of the other settings doesn't change. The energies take 16 bytes per
+    /// accumulated pixel: 4x at 1920x1080 needs about 530 MB, see [`MemoryBudget`].
+    pub supersample: u32,
+}
+
+impl FlowFieldSettings {
+    pub const SUPERSAMPLE_FACTORS: [u32; 3] = [1, 2, 4];
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            nr_particles: DEFAULT_WORKGROUP_SIZE * 128,
+            seed: None,
+            mass: MassDistribution::default(),
+            velocity: InitialVelocity::default(),
+            coloring: ParticleColoring::default(),
+            supersample: 1,
+        }
+    }
+
+    /// A one line summary of the size of the field.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}x{} at {}x supersampling, {} particles",
+            self.width, self.height, self.supersample, self.nr_particles
+        )
+    }
+
+    /// Approximate GPU memory of the field's buffers and output image, leaving out the scratch
+    /// space of the post effects.
+    pub(crate) fn estimated_memory(&self, output_format: OutputFormat) -> u64 {
+        let pixels = self.width as u64 * self.height as u64;
+        let energies = 16 * pixels * self.supersample.pow(2) as u64;
+        let image = output_format.texture_format().pixel_size() as u64 * pixels;
+        let particles =
+            (Particle::min_size().get() + Vec4::min_size().get()) * self.nr_particles as u64;
+        energies + image + particles
+    }
+
+    /// A copy shrunk until the largest buffer and the output image fit the limits of the device,
+    /// or `None` if these settings already fit. Lowers the supersampling first, then halves the
+    /// resolution, and halves the particle count on its own, also until the particles fit in
+    /// [`MAX_WORKGROUPS`] of the `workgroup_size` the device runs. Sized as if the glow is on,
+    /// which can be turned on later without reallocating anything else.
+    pub(crate) fn fit_device(
+        &self,
+        render_device: &RenderDevice,
+        workgroup_size: WorkgroupSize,
+    ) -> Option<Self> {
+        let limits = render_device.limits();
+        let max_buffer =
+            (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let max_dimension = limits.max_texture_dimension_2d;
+        let largest_image_buffer = |settings: &Self| {
+            let pixels = settings.width as u64 * settings.height as u64;
+            (16 * pixels * settings.supersample.pow(2) as u64).max(32 * pixels)
+        };
+
+        let mut fitted = *self;
+        while fitted.width > max_dimension
+            || fitted.height > max_dimension
+            || largest_image_buffer(&fitted) > max_buffer
+        {
+            if fitted.supersample > 1 {
+                fitted.supersample /= 2;
+            } else if fitted.width > 1 || fitted.height > 1 {
+                fitted.width = fitted.width.div_ceil(2);
+                fitted.height = fitted.height.div_ceil(2);
+            } else {
+                panic!("the GPU can't hold a flow field of a single pixel in {max_buffer} bytes");
+            }
+        }
+        let workgroup_size = supported_workgroup_size(workgroup_size, render_device);
+        let max_particles = MAX_WORKGROUPS as u64 * workgroup_size.0 as u64;
+        while Particle::min_size().get() * fitted.nr_particles as u64 > max_buffer
+            || fitted.nr_particles as u64 > max_particles
+        {
+            fitted.nr_particles /= 2;
+        }
+
+        let fits = fitted.width == self.width
+            && fitted.height == self.height
+            && fitted.supersample == self.supersample
+            && fitted.nr_particles == self.nr_particles;
+        (!fits).then_some(fitted)
+    }
+
+    /// Describes the first setting out of range.
+    pub fn check(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!(
+                "flow field size must be non-zero, got {}x{}",
+                self.width, self.height
+            ));
+        }
+        if self.nr_particles == 0 {
+            return Err("flow field needs at least one particle".to_string());
+        }
+        let (min, max) = self.mass.range();
+        if !(min > 0.0 && min <= max) {
+            return Err(format!(
+                "particle mass range must be positive and ordered, got {min}..{max}"
+            ));
+        }
+        let (min, max) = self.velocity.range();
+        if !(min >= 0.0 && min <= max) {
+            return Err(format!(
+                "initial speed range must be non-negative and ordered, got {min}..{max}"
+            ));
+        }
+        if !Self::SUPERSAMPLE_FACTORS.contains(&self.supersample) {
+            return Err(format!(
+                "flow field supersampling must be one of {:?}, got {}",
+                Self::SUPERSAMPLE_FACTORS,
+                self.supersample
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn validate(&self) {
+        if let Err(err) = self.check() {
+            panic!("{err}");
+        }
+    }
+}
+
+impl Default for FlowFieldSettings {
+    fn default() -> Self {
+        Self::new(1280, 720)
+    }
+}
+
+/// Workgroup size of the particle update, shared by all flow fields. Changing it at runtime
+/// recompiles the compute pipelines.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, ExtractResource)]
+pub struct WorkgroupSize(pub u32);
+
+impl Default for WorkgroupSize {
+    fn default() -> Self {
+        Self(DEFAULT_WORKGROUP_SIZE)
+    }
+}
+
+/// Format of the images all flow fields render into. Only read at startup.
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum OutputFormat {
+    /// Full precision at 16 bytes per pixel.
+    Rgba32Float,
+    /// Keeps the brightness range of [`OutputFormat::Rgba32Float`] at half the memory and
+    /// bandwidth.
+    #[default]
+    Rgba16Float,
+    /// Clamps the image to `0.0..=1.0` at 4 bytes per pixel.
+    Rgba8Unorm,
+}
+
+impl OutputFormat {
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            OutputFormat::Rgba32Float => TextureFormat::Rgba32Float,
+            OutputFormat::Rgba16Float => TextureFormat::Rgba16Float,
+            OutputFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+        }
+    }
+
+    /// Selects the matching declaration of the output image in the shader.
+    pub(crate) fn shader_def(self) -> ShaderDefVal {
+        let name = match self {
+            OutputFormat::Rgba32Float => "OUTPUT_RGBA32FLOAT",
+            OutputFormat::Rgba16Float => "OUTPUT_RGBA16FLOAT",
+            OutputFormat::Rgba8Unorm => "OUTPUT_RGBA8UNORM",
+        };
+        ShaderDefVal::Bool(name.to_string(), true)
+    }
+
+    /// This format if the adapter can write it from a compute shader, otherwise the first of
+    /// the float formats and then [`OutputFormat::Rgba8Unorm`] that it can.
+    fn supported(self, adapter: &RenderAdapter) -> Self {
+        let storage = |format: OutputFormat| {
+            adapter
+                .get_texture_format_features(format.texture_format())
+                .allowed_usages
+                .contains(TextureUsages::STORAGE_BINDING)
+        };
+        if storage(self) {
+            return self;
+        }
+        let fallbacks = [
+            OutputFormat::Rgba16Float,
+            OutputFormat::Rgba32Float,
+            OutputFormat::Rgba8Unorm,
+        ];
+        let Some(fallback) = fallbacks.into_iter().find(|&format| storage(format)) else {
+            panic!("the adapter can't write any of the flow field output formats from a shader");
+        };
+        warn!("the adapter can't use {self:?} as a storage texture, falling back to {fallback:?}");
+        fallback
+    }
+}
+
+/// Flow fields whose buffers would take more bytes of GPU memory than this log a warning when they
+/// are created.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct MemoryBudget(pub u64);
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self(1 << 30)
+    }
+}
+
+/// How the [`PrimaryFlowField`] is sized from the physical resolution of the primary window when
+/// it is shown as a sprite, see [`ComputePlugin::with_sprite`].
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct WindowSizing {
+    /// Simulated pixels per physical pixel of the window, below 1 to simulate at a lower
+    /// resolution on weak GPUs.
+    pub scale: f32,
+    /// Longest side of the simulation in pixels, keeping the aspect ratio of the window.
+    pub max_dimension: Option<u32>,
+}
+
+impl WindowSizing {
+    pub(crate) fn field_size(&self, window: &Window) -> UVec2 {
+        let physical = UVec2::new(window.physical_width(), window.physical_height());
+        let mut size = physical.as_vec2() * self.scale;
+        if let Some(max_dimension) = self.max_dimension {
+            size *= (max_dimension as f32 / size.max_element()).min(1.0);
+        }
+        size.round().max(Vec2::ONE).as_uvec2()
+    }
+}
+
+impl Default for WindowSizing {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            max_dimension: None,
+        }
+    }
+}
+
+/// Multiplier on the simulation speed of all flow fields.
+#[derive(Resource, Clone, Copy, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct SimulationSpeed(pub f32);
+
+impl SimulationSpeed {
+    pub const MIN: f32 = 0.01;
+    pub const MAX: f32 = 20.0;
+}
+
+impl Default for SimulationSpeed {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Number of particle updates per [`Timestep`], each integrating a fraction of it. More substeps
+/// give fast particles continuous rather than dotted trails.
+#[derive(Resource, Clone, Copy, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct Substeps(pub u32);
+
+impl Substeps {
+    pub const MAX: u32 = 8;
+}
+
+impl Default for Substeps {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+/// Simulated seconds covered by one fixed step of the flow fields. Every frame they advance by as
+/// many steps as the elapsed time owes them, up to [`Timestep::MAX_STEPS`], so the trajectories
+/// don't depend on the refresh rate of the display.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Timestep(pub f32);
+
+impl Timestep {
+    /// Steps taken in a single frame at most. Time owed beyond that is dropped, so a slow frame
+    /// doesn't make the following ones slower still.
+    pub const MAX_STEPS: u32 = 8;
+}
+
+impl Default for Timestep {
+    fn default() -> Self {
+        Self(1.0 / 120.0)
+    }
+}
+
+/// Fixed steps to take this frame, counted in the main world and extracted for the compute node.
+#[derive(Resource, Clone, Copy, Default, ExtractResource)]
+pub(crate) struct OwedSteps {
+    pub(crate) steps: u32,
+    /// The [`Timestep`] they were counted in.
+    pub(crate) timestep: f32,
+}
+
+/// Whether the vignette and grain of every flow field are drawn, to compare the image with and
+/// without them.
+#[derive(Resource, Clone, Copy, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct FilmEffects(pub bool);
+
+impl Default for FilmEffects {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// Whether the simulation advances. While paused the output images keep their last frame.
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq, Debug, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub enum SimulationState {
+    #[default]
+    Running,
+    Paused,
+}
+
+impl SimulationState {
+    pub fn toggle(&mut self) {
+        *self = match self {
+            SimulationState::Running => SimulationState::Paused,
+            SimulationState::Paused => SimulationState::Running,
+        };
+    }
+}
+
+/// Asks a paused simulation to advance by a single [`Timestep`]. The flag is shared with the render world,
+/// which clears it once the step has been taken, so repeated requests before that collapse into one.
+#[derive(Resource, Clone, Default)]
+pub struct StepRequest(Arc<AtomicBool>);
+
+impl StepRequest {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Number of frames the flow fields have been simulated for. Only counts once the compute
+/// pipelines are ready and there is something to dispatch.
+#[derive(Resource, Default)]
+pub(crate) struct SimulatedFrames(pub(crate) u64);
+
+/// Whether the compute pipelines have compiled, as the render world saw it on the last frame. The
+/// simulation only starts once they are ready, and pauses again while a reloaded shader compiles.
+#[derive(Resource, Clone, Default, PartialEq, Eq, Debug)]
+pub enum FlowFieldStatus {
+    #[default]
+    Compiling,
+    Ready,
+    /// A program failed to compile, with the errors of the shader. Fixing a hot reloaded shader
+    /// compiles it again.
+    Error(String),
+}
+
+impl FlowFieldStatus {
+    pub fn is_ready(&self) -> bool {
+        *self == FlowFieldStatus::Ready
+    }
+}
+
+/// Sent once, the first time the [`FlowFieldStatus`] is ready.
+#[derive(Event, Clone, Copy, Default, Debug)]
+pub struct FlowFieldReadyEvent;
+
+/// The [`FlowFieldStatus`], shared with the render world which sets it.
+#[derive(Resource, Clone, Default)]
+pub(crate) struct SharedStatus(pub(crate) Arc<Mutex<FlowFieldStatus>>);
+
+fn sync_status(
+    mut sent: Local<bool>,
+    shared: Res<SharedStatus>,
+    mut status: ResMut<FlowFieldStatus>,
+    mut ready: EventWriter<FlowFieldReadyEvent>,
+) {
+    let Ok(shared) = shared.0.lock() else {
+        return;
+    };
+    status.set_if_neq(shared.clone());
+    if !*sent && status.is_ready() {
+        *sent = true;
+        ready.send(FlowFieldReadyEvent);
+    }
+}
+
+/// The render world systems of the [`ComputePlugin`], to order your own systems against.
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FlowFieldSet {
+    /// Uploads the uniforms of the frame, the primitives, obstacles, vector field and palette, and
+    /// requeues the pipelines after a [`WorkgroupSize`] change. In [`RenderSet::Prepare`].
+    PrepareBuffers,
+    /// Creates the bind group of every flow field from the prepared buffers. In
+    /// [`RenderSet::PrepareBindGroups`].
+    PrepareBindGroup,
+}
+
+/// Marks the flow field spawned by [`ComputePlugin`] itself.
+#[derive(Component)]
+pub struct PrimaryFlowField;
+
+/// The storage image the [`PrimaryFlowField`] renders into, available from `PostStartup` on. It can
+/// be used as the texture of a sprite, UI node or material.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct FlowFieldOutput(pub Handle<Image>);
+
+#[derive(Bundle, Clone, Copy, Default)]
+pub struct FlowFieldBundle {
+    pub settings: FlowFieldSettings,
+    pub params: FlowFieldParams,
+}
+
+/// Simulates flow fields on the GPU. The plugin spawns one [`PrimaryFlowField`] configured through
+/// the builder methods; more can be added by spawning a [`FlowFieldBundle`].
+///
+/// In the render world its systems run in the [`FlowFieldSet`]s, and the [`ComputeNode`] is added
+/// to the main render graph as [`NODE_NAME`], before the camera driver.
+#[derive(Default)]
+pub struct ComputePlugin {
+    settings: FlowFieldSettings,
+    params: FlowFieldParams,
+    workgroup_size: WorkgroupSize,
+    output_format: OutputFormat,
+    memory_budget: MemoryBudget,
+    seed: Option<u64>,
+    primitives: FieldPrimitives,
+    spawn_sprite: bool,
+    window_sizing: WindowSizing,
+    timestep: Timestep,
+    shader_path: Option<String>,
+    custom_field_shader: Option<Handle<Shader>>,
+    target: Option<Handle<Image>>,
+}
+
+impl ComputePlugin {
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.settings.width = width;
+        self.settings.height = height;
+        self
+    }
+
+    pub fn with_particles(mut self, nr_particles: u32) -> Self {
+        self.settings.nr_particles = nr_particles;
+        self
+    }
+
+    pub fn with_mass(mut self, mass: MassDistribution) -> Self {
+        self.settings.mass = mass;
+        self
+    }
+
+    pub fn with_velocity(mut self, velocity: InitialVelocity) -> Self {
+        self.settings.velocity = velocity;
+        self
+    }
+
+    pub fn with_coloring(mut self, coloring: ParticleColoring) -> Self {
+        self.settings.coloring = coloring;
+        self
+    }
+
+    /// See [`FlowFieldSettings::supersample`].
+    pub fn with_supersample(mut self, supersample: u32) -> Self {
+        self.settings.supersample = supersample;
+        self
+    }
+
+    pub fn with_workgroup_size(mut self, workgroup_size: u32) -> Self {
+        self.workgroup_size = WorkgroupSize(workgroup_size);
+        self
+    }
+
+    pub fn with_output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Sets the [`MemoryBudget`] in bytes.
+    pub fn with_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = MemoryBudget(bytes);
+        self
+    }
+
+    /// Sets the global [`Seed`]. Without one a random seed is picked and logged at startup.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_params(mut self, params: FlowFieldParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Sets the initial [`FieldPrimitives`].
+    pub fn with_primitives(mut self, primitives: Vec<FieldPrimitive>) -> Self {
+        self.primitives = FieldPrimitives(primitives);
+        self
+    }
+
+    /// Also spawn a sprite filling the primary window with the [`FlowFieldOutput`]. The primary
+    /// field is sized from the resolution of the window instead, see [`WindowSizing`], and
+    /// restarts when the window is resized.
+    pub fn with_sprite(mut self) -> Self {
+        self.spawn_sprite = true;
+        self
+    }
+
+    /// Sets the [`Timestep`] in seconds.
+    pub fn with_timestep(mut self, seconds: f32) -> Self {
+        self.timestep = Timestep(seconds);
+        self
+    }
+
+    pub fn with_window_sizing(mut self, window_sizing: WindowSizing) -> Self {
+        self.window_sizing = window_sizing;
+        self
+    }
+
+    /// Loads the compute shader from this path, relative to the asset folder, instead of using
+    /// the embedded one. It hot reloads when the asset server watches for changes, see
+    /// [`SHADER_ASSET_PATH`](crate::SHADER_ASSET_PATH).
+    pub fn with_shader_path(mut self, path: impl Into<String>) -> Self {
+        self.shader_path = Some(path.into());
+        self
+    }
+
+    /// Sets the initial [`CustomField`], whose `field` function replaces the noise of every flow
+    /// field.
+    pub fn with_custom_field(mut self, shader: Handle<Shader>) -> Self {
+        self.custom_field_shader = Some(shader);
+        self
+    }
+
+    /// Renders the primary flow field into this image at its size instead of creating the output,
+    /// see [`FlowFieldTarget`]. It doesn't follow the window and gets no sprite.
+    pub fn with_target(mut self, image: Handle<Image>) -> Self {
+        self.target = Some(image);
+        self
+    }
+
+    /// Describes the first setting out of range, which would make [`Plugin::build`] panic.
+    pub fn check(&self) -> Result<(), String> {
+        self.settings.check()?;
+        if self.workgroup_size.0 == 0 {
+            return Err("flow field workgroup size must be non-zero".to_string());
+        }
+        let max_particles = MAX_WORKGROUPS as u64 * self.workgroup_size.0 as u64;
+        if self.settings.nr_particles as u64 > max_particles {
+            return Err(format!(
+                "flow field of {} particles needs more than {MAX_WORKGROUPS} workgroups of {}, \
+                 use at most {max_particles} particles or a larger workgroup size",
+                self.settings.nr_particles, self.workgroup_size.0
+            ));
+        }
+        if self.window_sizing.scale <= 0.0 {
+            return Err(format!(
+                "flow field window scale must be positive, got {}",
+                self.window_sizing.scale
+            ));
+        }
+        if self.timestep.0 <= 0.0 {
+            return Err(format!(
+                "flow field timestep must be positive, got {}",
+                self.timestep.0
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Counts the [`Timestep`]s owed to the flow fields since the last frame.
+fn accumulate_steps(
+    mut owed_time: Local<Duration>,
+    time: Res<Time>,
+    state: Res<SimulationState>,
+    timestep: Res<Timestep>,
+    mut owed: ResMut<OwedSteps>,
+) {
+    owed.timestep = timestep.0;
+    if *state != SimulationState::Running {
+        *owed_time = Duration::ZERO;
+        owed.steps = 0;
+        return;
+    }
+    // In nanoseconds, so a frame time that is a multiple of the timestep gives exact step counts.
+    let step = Duration::from_secs_f32(timestep.0).as_nanos().max(1);
+    *owed_time += time.delta();
+    let steps = owed_time.as_nanos() / step;
+    *owed_time -= Duration::from_nanos((steps * step) as u64);
+    owed.steps = steps.min(Timestep::MAX_STEPS as u128) as u32;
+}
+
+impl Plugin for ComputePlugin {
+    fn build(&self, app: &mut App) {
+        if let Err(err) = self.check() {
+            panic!("{err}");
+        }
+        load_internal_asset!(
+            app,
+            SHADER_HANDLE,
+            "../assets/shaders/flow_field.wgsl",
+            Shader::from_wgsl
+        );
+        #[cfg(feature = "export-png")]
+        load_internal_asset!(
+            app,
+            GIF_SHADER_HANDLE,
+            "../assets/shaders/gif_downsample.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            CUSTOM_FIELD_SHADER_HANDLE,
+            "../assets/shaders/custom_field.wgsl",
+            Shader::from_wgsl
+        );
+        let custom_field = CustomField(self.custom_field_shader.clone());
+        let shader = match &self.shader_path {
+            Some(path) => {
+                info!("loading the flow field shader from {path}");
+                app.world.resource::<AssetServer>().load(path.as_str())
+            }
+            None => SHADER_HANDLE.typed(),
+        };
+        let mut primary = app.world.spawn((
+            FlowFieldBundle {
+                settings: self.settings,
+                params: self.params,
+            },
+            PrimaryFlowField,
+        ));
+        if let Some(target) = &self.target {
+            primary.insert(FlowFieldTarget(target.clone()));
+        }
+        let seed = Seed(self.seed.unwrap_or_else(rand::random));
+        info!("flow field seed: {}", seed.0);
+        app.insert_resource(seed);
+        app.insert_resource(SeedVariation {
+            base: seed.0,
+            index: None,
+        });
+        app.insert_resource(self.workgroup_size);
+        app.insert_resource(self.output_format);
+        app.insert_resource(self.memory_budget);
+        app.insert_resource(self.window_sizing);
+        app.insert_resource(self.timestep);
+        app.insert_resource(self.primitives.clone());
+        app.insert_resource(custom_field.clone());
+        app.init_resource::<Obstacles>();
+        app.init_resource::<SimulationSpeed>();
+        app.init_resource::<SimulationState>();
+        app.init_resource::<StepRequest>();
+        app.init_resource::<ScreenshotRequest>();
+        app.init_resource::<EnergyExportRequest>();
+        app.init_resource::<GifRequest>();
+        app.init_resource::<GifSettings>();
+        app.init_resource::<Recording>();
+        app.init_resource::<Trajectories>();
+        app.init_resource::<Snapshots>();
+        app.init_resource::<FlowFieldStatus>();
+        app.init_resource::<SharedStatus>();
+        app.init_resource::<PendingExports>();
+        app.init_resource::<snapshot::SnapshotTransfer>();
+        app.init_resource::<Substeps>();
+        app.init_resource::<OwedSteps>();
+        app.init_resource::<FilmEffects>();
+        app.init_resource::<Presets>();
+        app.register_type::<FlowFieldSettings>()
+            .register_type::<FlowFieldParams>()
+            .register_type::<ParticleBufferInfo>()
+            .register_type::<SimulationSpeed>()
+            .register_type::<SimulationState>()
+            .register_type::<Substeps>()
+            .register_type::<FilmEffects>()
+            .register_type::<Palette>();
+        app.add_systems(Startup, init_flow_fields);
+        app.add_systems(First, vector_field::reset_dirty_region);
+        app.add_systems(
+            PreUpdate,
+            (sdf::build_obstacle_sdf, palette::update_palette_image),
+        );
+        app.add_event::<ResetSimulation>();
+        app.add_event::<FlowFieldReadyEvent>();
+        app.add_systems(First, sync_status);
+        app.add_systems(
+            Update,
+            (
+                presets::apply_preset,
+                interpolation::interpolate_params,
+                follow_targets,
+                resize_flow_fields,
+                resize_particles,
+                resize_post_buffers,
+                species::update_species,
+                reset_flow_fields,
+            )
+                .chain(),
+        );
+        app.add_systems(
+            PostUpdate,
+            (
+                init_flow_fields,
+                snapshot::transfer_snapshots,
+                update_buffer_info,
+            )
+                .chain(),
+        );
+        app.add_systems(PostUpdate, (accumulate_steps, update_custom_field));
+        if self.spawn_sprite && self.target.is_some() {
+            warn!("the primary flow field renders into a target, not spawning its sprite");
+        } else if self.spawn_sprite {
+            app.add_systems(Startup, fit_to_window.before(init_flow_fields));
+            app.add_systems(PostStartup, spawn_sprite);
+            app.add_systems(Update, follow_window.before(resize_flow_fields));
+            app.add_systems(PostUpdate, (follow_background, fit_sprite));
+        }
+        #[cfg(feature = "debug-validate")]
+        app.init_resource::<crate::ValidationInterval>()
+            .add_systems(Last, crate::validate::validate_particles);
+        app.add_plugins((
+            ExtractComponentPlugin::<FlowFieldSettings>::default(),
+            ExtractComponentPlugin::<FlowFieldParams>::default(),
+            ExtractComponentPlugin::<ParticleBuffer>::default(),
+            ExtractComponentPlugin::<ComputeInput>::default(),
+            UniformComponentPlugin::<FlowFieldUniform>::default(),
+            ExtractResourcePlugin::<WorkgroupSize>::default(),
+            ExtractResourcePlugin::<SimulationSpeed>::default(),
+            ExtractResourcePlugin::<SimulationState>::default(),
+            ExtractResourcePlugin::<Substeps>::default(),
+            ExtractResourcePlugin::<FilmEffects>::default(),
+            ExtractResourcePlugin::<FieldPrimitives>::default(),
+            ExtractResourcePlugin::<Obstacles>::default(),
+            ExtractResourcePlugin::<FlowFieldOutput>::default(),
+            ExtractResourcePlugin::<Recording>::default(),
+        ));
+        app.add_plugins((
+            ExtractResourcePlugin::<OwedSteps>::default(),
+            ExtractResourcePlugin::<SeedVariation>::default(),
+            ExtractResourcePlugin::<CustomField>::default(),
+        ));
+
+        let step = app.world.resource::<StepRequest>().clone();
+        let screenshot = app.world.resource::<ScreenshotRequest>().clone();
+        let energy_export = app.world.resource::<EnergyExportRequest>().clone();
+        let gif = app.world.resource::<GifRequest>().clone();
+        let trajectories = app.world.resource::<Trajectories>().clone();
+        let status = app.world.resource::<SharedStatus>().clone();
+        let exports = app.world.resource::<PendingExports>().clone();
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.insert_resource(step);
+        render_app.insert_resource(screenshot);
+        render_app.insert_resource(energy_export);
+        render_app.insert_resource(gif);
+        render_app.insert_resource(trajectories);
+        render_app.insert_resource(status);
+        render_app.insert_resource(exports);
+        render_app.insert_resource(ComputeShader(shader));
+        render_app.insert_resource(custom_field);
+        render_app.init_resource::<SimParamsBuffer>();
+        render_app.init_resource::<BindGroupCache>();
+        render_app.init_resource::<SimulatedFrames>();
+        render_app.init_resource::<FieldPrimitivesBuffer>();
+        render_app.init_resource::<ObstaclesBuffer>();
+        render_app.init_resource::<VectorFieldTexture>();
+        render_app.init_resource::<PaletteTexture>();
+        render_app.init_resource::<shutdown::Exiting>();
+        render_app.init_resource::<plot::Plotter>();
+        #[cfg(feature = "export-png")]
+        render_app.init_resource::<gif::GifCapture>();
+        render_app.init_resource::<snapshot::PendingSnapshots>();
+        render_app.add_systems(
+            ExtractSchedule,
+            (
+                field_image::extract_field_image,
+                vector_field::extract_vector_field,
+                sdf::extract_obstacle_sdf,
+                palette::extract_palette,
+                shutdown::extract_exit,
+                snapshot::extract_snapshots,
+            ),
+        );
+        render_app.configure_sets(
+            Render,
+            (
+                FlowFieldSet::PrepareBuffers.in_set(RenderSet::Prepare),
+                FlowFieldSet::PrepareBindGroup.in_set(RenderSet::PrepareBindGroups),
+            ),
+        );
+        render_app.add_systems(
+            Render,
+            (
+                (
+                    requeue_pipelines,
+                    prepare_sim_params,
+                    primitives::prepare_field_primitives,
+                    obstacles::prepare_obstacles,
+                    vector_field::prepare_vector_field,
+                    palette::prepare_palette,
+                    snapshot::restore_snapshot.before(prepare_sim_params),
+                )
+                    .in_set(FlowFieldSet::PrepareBuffers),
+                prepare_bind_group.in_set(FlowFieldSet::PrepareBindGroup),
+                // After the frame has been submitted.
+                (plot::record_trajectories, snapshot::save_snapshot)
+                    // The fields are only readable until bevy clears the render entities.
+                    .before(World::clear_entities)
+                    .before(shutdown::wait_for_exports)
+                    .in_set(RenderSet::Cleanup),
+                shutdown::wait_for_exports.in_set(RenderSet::Cleanup),
+            ),
+        );
+        #[cfg(feature = "export-exr")]
+        render_app.add_systems(
+            Render,
+            exr::capture_energies
+                // The fields are only readable until bevy clears the render entities.
+                .before(World::clear_entities)
+                .before(shutdown::wait_for_exports)
+                .in_set(RenderSet::Cleanup),
+        );
+        #[cfg(feature = "export-png")]
+        render_app.add_systems(
+            Render,
+            (
+                screenshot::capture_screenshot,
+                gif::capture_gif,
+                recording::record_frame,
+            )
+                .before(shutdown::wait_for_exports)
+                .in_set(RenderSet::Cleanup),
+        );
+
+        let node = ComputeNode::from_world(&mut render_app.world);
+        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
+        render_graph.add_node(NODE_NAME, node);
+        render_graph.add_node_edge(NODE_NAME, bevy::render::main_graph::node::CAMERA_DRIVER);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let workgroup_size = *app.world.resource::<WorkgroupSize>();
+        let output_format = *app.world.resource::<OutputFormat>();
+        let render_app = app.sub_app_mut(RenderApp);
+        let output_format = output_format.supported(render_app.world.resource::<RenderAdapter>());
+
+        let workgroup_size =
+            fit_workgroup_size(workgroup_size, render_app.world.resource::<RenderDevice>());
+
+        render_app.insert_resource(workgroup_size);
+        render_app.insert_resource(output_format);
+        render_app.init_resource::<ComputePipeline>();
+        #[cfg(feature = "export-png")]
+        render_app.init_resource::<recording::Recorder>();
+        #[cfg(feature = "export-png")]
+        render_app.init_resource::<gif::GifPipeline>();
+        // The images are created in the main world from `Startup` on.
+        app.insert_resource(output_format);
+        app.insert_resource(workgroup_size);
+    }
+}
@@ -0,0 +1,280 @@
+use bevy::{ecs::query::ReadOnlyWorldQuery, prelude::*, window::PrimaryWindow};
+
+use crate::{brush, controls::UiFocus, ComputeInput, FlowFieldSettings};
+
+/// Radial forces around the cursor and every finger on a touchscreen, pulling particles in or
+/// pushing them away. Driven by the mouse and touches through the
+/// [`FlowFieldControlsPlugin`](crate::FlowFieldControlsPlugin).
+#[derive(Component, Clone, Debug)]
+pub struct PointerForce {
+    /// Acceleration at the center, relative to the speed of the noise field per second.
+    pub strength: f32,
+    /// Distance in pixels at which the force has smoothly dropped to zero.
+    pub radius: f32,
+    /// The active pointers, of which only the first [`PointerForce::MAX_POINTERS`] are used.
+    pub pointers: Vec<Pointer>,
+}
+
+/// A single mouse cursor or touch pushing the particles around.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pointer {
+    /// Position in pixels of the flow field.
+    pub position: Vec2,
+    /// Attracts particles when `true` and repels them otherwise.
+    pub attract: bool,
+}
+
+impl Default for PointerForce {
+    fn default() -> Self {
+        Self {
+            strength: 4.0,
+            radius: 150.0,
+            pointers: Vec::new(),
+        }
+    }
+}
+
+impl PointerForce {
+    pub const MAX_POINTERS: usize = 8;
+
+    /// The pointers packed as position, signed strength and radius, followed by their count.
+    pub(crate) fn uniform(&self) -> ([Vec4; Self::MAX_POINTERS], u32) {
+        let mut packed = [Vec4::ZERO; Self::MAX_POINTERS];
+        let count = self.pointers.len().min(Self::MAX_POINTERS);
+        for (packed, pointer) in packed.iter_mut().zip(&self.pointers) {
+            let strength = if pointer.attract {
+                self.strength.abs()
+            } else {
+                -self.strength.abs()
+            };
+            *packed = pointer
+                .position
+                .extend(strength)
+                .extend(self.radius.max(1.0));
+        }
+        (packed, count as u32)
+    }
+}
+
+/// The cursor in world space, `None` while it is outside the primary window.
+pub(crate) fn cursor_world_position<F: ReadOnlyWorldQuery>(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform), F>,
+) -> Option<Vec2> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    screen_to_world(cursor, cameras)
+}
+
+/// Converts logical window coordinates, like those of the cursor and touches, to world space
+/// through the active camera drawn first, leaving out insets like the
+/// [`Magnifier`](crate::Magnifier).
+fn screen_to_world<F: ReadOnlyWorldQuery>(
+    position: Vec2,
+    cameras: &Query<(&Camera, &GlobalTransform), F>,
+) -> Option<Vec2> {
+    let (camera, transform) = cameras
+        .iter()
+        .filter(|(camera, _)| camera.is_active)
+        .min_by_key(|(camera, _)| camera.order)?;
+    camera.viewport_to_world_2d(transform, position)
+}
+
+/// Converts a world position to pixels of the flow field shown by the sprite, which may lie
+/// outside the field.
+pub fn world_to_field(
+    world: Vec2,
+    settings: &FlowFieldSettings,
+    sprite: &Sprite,
+    transform: &GlobalTransform,
+) -> Vec2 {
+    let field_size = Vec2::new(settings.width as f32, settings.height as f32);
+    let size = sprite.custom_size.unwrap_or(field_size);
+    let local = transform
+        .affine()
+        .inverse()
+        .transform_point3(world.extend(0.0))
+        .truncate();
+    let centered = local / size + sprite.anchor.as_vec();
+    let mut uv = Vec2::new(centered.x + 0.5, 0.5 - centered.y);
+    if sprite.flip_x {
+        uv.x = 1.0 - uv.x;
+    }
+    if sprite.flip_y {
+        uv.y = 1.0 - uv.y;
+    }
+    uv * field_size
+}
+
+/// Converts pixels of the flow field shown by the sprite to world space, the inverse of
+/// [`world_to_field`].
+pub fn field_to_world(
+    pixel: Vec2,
+    settings: &FlowFieldSettings,
+    sprite: &Sprite,
+    transform: &GlobalTransform,
+) -> Vec2 {
+    let field_size = Vec2::new(settings.width as f32, settings.height as f32);
+    let size = sprite.custom_size.unwrap_or(field_size);
+    let mut uv = pixel / field_size;
+    if sprite.flip_x {
+        uv.x = 1.0 - uv.x;
+    }
+    if sprite.flip_y {
+        uv.y = 1.0 - uv.y;
+    }
+    let centered = Vec2::new(uv.x - 0.5, 0.5 - uv.y);
+    let local = (centered - sprite.anchor.as_vec()) * size;
+    transform.transform_point(local.extend(0.0)).truncate()
+}
+
+/// Sprites that may show a flow field, matched to it by their texture.
+pub(crate) type FieldSprites<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Handle<Image>,
+        &'static Sprite,
+        &'static GlobalTransform,
+    ),
+>;
+
+pub(crate) fn field_sprite<'a>(
+    sprites: &'a FieldSprites,
+    input: &ComputeInput,
+) -> Option<(&'a Sprite, &'a GlobalTransform)> {
+    sprites
+        .iter()
+        .find(|(texture, _, _)| **texture == input.dst_image)
+        .map(|(_, sprite, transform)| (sprite, transform))
+}
+
+/// The mouse attracts while the left button is held and repels while the right one is, unless the
+/// left button is painting with the [`Brush`](crate::Brush). Every touch attracts, and stops as
+/// soon as the finger is lifted or the touch is cancelled.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_pointer_force(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    focus: Res<UiFocus>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    sprites: FieldSprites,
+    mut fields: Query<(
+        Entity,
+        &FlowFieldSettings,
+        &ComputeInput,
+        Option<&mut PointerForce>,
+    )>,
+) {
+    let mouse_attract = if mouse.pressed(MouseButton::Left) && !brush::painting(&keys) {
+        Some(true)
+    } else if mouse.pressed(MouseButton::Right) {
+        Some(false)
+    } else {
+        None
+    };
+    // Released and cancelled touches are no longer pressed by the time this runs.
+    let mut pressed: Vec<(Vec2, bool)> = mouse_attract
+        .and_then(|attract| Some((cursor_world_position(&windows, &cameras)?, attract)))
+        .into_iter()
+        .chain(
+            touches
+                .iter()
+                .filter_map(|touch| Some((screen_to_world(touch.position(), &cameras)?, true))),
+        )
+        .take(PointerForce::MAX_POINTERS)
+        .collect();
+    // Pointers over egui stop pushing the particles rather than freezing where they were.
+    if focus.pointer {
+        pressed.clear();
+    }
+
+    for (entity, settings, input, force) in &mut fields {
+        let Some(mut force) = force else {
+            commands.entity(entity).insert(PointerForce::default());
+            continue;
+        };
+        let pointers: Vec<Pointer> = match field_sprite(&sprites, input) {
+            Some((sprite, transform)) => pressed
+                .iter()
+                .map(|&(world, attract)| Pointer {
+                    position: world_to_field(world, settings, sprite, transform),
+                    attract,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        // Only write changes, so idle fields aren't marked as changed every frame.
+        if force.pointers != pointers {
+            force.pointers = pointers;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::sprite::Anchor;
+
+    use super::*;
+
+    fn assert_close(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-3),
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    /// A 200x100 field stretched over a 400x200 sprite, scaled by 1.5 and moved off the origin.
+    fn scaled_sprite() -> (FlowFieldSettings, Sprite, GlobalTransform) {
+        let sprite = Sprite {
+            custom_size: Some(Vec2::new(400.0, 200.0)),
+            ..default()
+        };
+        let transform = Transform::from_xyz(50.0, -30.0, 0.0).with_scale(Vec3::new(1.5, 1.5, 1.0));
+        (FlowFieldSettings::new(200, 100), sprite, transform.into())
+    }
+
+    #[test]
+    fn corners() {
+        let (settings, sprite, transform) = scaled_sprite();
+        let corners = [
+            (Vec2::new(0.0, 0.0), Vec2::new(-250.0, 120.0)),
+            (Vec2::new(200.0, 0.0), Vec2::new(350.0, 120.0)),
+            (Vec2::new(0.0, 100.0), Vec2::new(-250.0, -180.0)),
+            (Vec2::new(200.0, 100.0), Vec2::new(350.0, -180.0)),
+        ];
+        for (pixel, world) in corners {
+            assert_close(field_to_world(pixel, &settings, &sprite, &transform), world);
+            assert_close(world_to_field(world, &settings, &sprite, &transform), pixel);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let (settings, sprite, transform) = scaled_sprite();
+        let flipped = Sprite {
+            flip_x: true,
+            flip_y: true,
+            anchor: Anchor::BottomLeft,
+            ..sprite.clone()
+        };
+        let rotated: GlobalTransform = Transform::from_xyz(-12.0, 7.0, 0.0)
+            .with_rotation(Quat::from_rotation_z(0.3))
+            .with_scale(Vec3::new(0.5, 2.0, 1.0))
+            .into();
+        let pixels = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(200.0, 100.0),
+            Vec2::new(37.5, 81.25),
+            Vec2::new(-20.0, 140.0),
+        ];
+        for (sprite, transform) in [(&sprite, &transform), (&flipped, &rotated)] {
+            for pixel in pixels {
+                let world = field_to_world(pixel, &settings, sprite, transform);
+                assert_close(world_to_field(world, &settings, sprite, transform), pixel);
+            }
+        }
+    }
+}
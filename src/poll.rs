@@ -0,0 +1,47 @@
+use std::{
+    sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError},
+    time::Duration,
+};
+
+use bevy::render::{
+    render_resource::{Buffer, MapMode},
+    renderer::{RenderDevice, RenderQueue},
+};
+
+/// How often [`wait_for_map`] polls the device.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Maps `buffer` for reading once the copies into it are done. The result arrives on the returned
+/// channel when the device is next polled, by a frame or by [`wait_for_map`].
+pub(crate) fn map_read(
+    render_device: &RenderDevice,
+    buffer: &Buffer,
+) -> Receiver<Result<(), String>> {
+    let (sender, mapped) = mpsc::channel();
+    render_device.map_buffer(&buffer.slice(..), MapMode::Read, move |result| {
+        let _ = sender.send(result.map_err(|err| err.to_string()));
+    });
+    mapped
+}
+
+/// Runs the callbacks of the buffer maps whose copies have finished without waiting for the GPU.
+/// wgpu polls the device on every submission, so this submits nothing rather than taking the
+/// `wgpu::Maintain` of [`RenderDevice::poll`], which bevy doesn't re-export.
+pub(crate) fn poll(render_queue: &RenderQueue) {
+    render_queue.submit([]);
+}
+
+/// Blocks until the buffer map of [`map_read`] completes, polling the device in between.
+pub(crate) fn wait_for_map<T>(
+    render_queue: &RenderQueue,
+    mapped: &Receiver<T>,
+) -> Result<T, RecvError> {
+    loop {
+        poll(render_queue);
+        match mapped.recv_timeout(POLL_INTERVAL) {
+            Ok(result) => return Ok(result),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(RecvError),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+
+use crate::{
+    AgeCurve, BoundaryMode, ColorMode, FieldType, FlowFieldParams, FlowFieldSettings,
+    ParamInterpolation,
+};
+
+/// A named look, bundling the shader parameters with the number of particles to simulate.
+#[derive(Clone)]
+pub struct Preset {
+    pub name: String,
+    pub params: FlowFieldParams,
+    pub nr_particles: u32,
+}
+
+/// The list of presets to choose from. Changing `active` applies that preset to every flow field on
+/// the next update, transitioning smoothly for fields with a [`ParamInterpolation`]. Fields keep
+/// their noise offset, so the [`SeedVariation`](crate::SeedVariation) carries over to the new preset.
+#[derive(Resource)]
+pub struct Presets {
+    pub presets: Vec<Preset>,
+    pub active: usize,
+}
+
+impl Presets {
+    pub fn select(&mut self, index: usize) {
+        if index < self.presets.len() {
+            self.active = index;
+        }
+    }
+
+    pub fn active(&self) -> Option<&Preset> {
+        self.presets.get(self.active)
+    }
+}
+
+impl Default for Presets {
+    fn default() -> Self {
+        let params = FlowFieldParams::default();
+        Self {
+            presets: vec![
+                Preset {
+                    name: "default".to_string(),
+                    params,
+                    nr_particles: FlowFieldSettings::default().nr_particles,
+                },
+                Preset {
+                    name: "dense slow trails".to_string(),
+                    params: FlowFieldParams {
+                        noise_scale: 420.0,
+                        field_evolution_speed: 0.02,
+                        particle_lifetime: 12.0,
+                        trail_color: Color::rgb(1.0, 0.85, 0.6),
+                        ..params
+                    },
+                    nr_particles: 256 * 512,
+                },
+                Preset {
+                    name: "sparse fast streaks".to_string(),
+                    params: FlowFieldParams {
+                        noise_scale: 140.0,
+                        fade: 0.02,
+                        trail_color: Color::rgb(0.5, 0.8, 1.0),
+                        ..params
+                    },
+                    nr_particles: 256 * 32,
+                },
+                Preset {
+                    name: "high-contrast monochrome".to_string(),
+                    params: FlowFieldParams {
+                        noise_scale: 280.0,
+                        background_color: Color::BLACK,
+                        trail_color: Color::rgb(4.0, 4.0, 4.0),
+                        ..params
+                    },
+                    nr_particles: 256 * 128,
+                },
+                Preset {
+                    name: "turbulent detail".to_string(),
+                    params: FlowFieldParams {
+                        noise_scale: 560.0,
+                        octaves: 6,
+                        trail_color: Color::rgb(0.7, 1.0, 0.8),
+                        ..params
+                    },
+                    nr_particles: 256 * 256,
+                },
+                Preset {
+                    name: "curl circulation".to_string(),
+                    params: FlowFieldParams {
+                        field_type: FieldType::Curl,
+                        fade: 0.01,
+                        trail_color: Color::rgb(1.0, 0.6, 0.9),
+                        ..params
+                    },
+                    nr_particles: 256 * 128,
+                },
+                Preset {
+                    name: "rain".to_string(),
+                    params: FlowFieldParams {
+                        gravity: Vec2::new(0.0, 1.0),
+                        boundary: BoundaryMode::Wrap,
+                        drag: 0.02,
+                        fade: 0.03,
+                        trail_color: Color::rgb(0.6, 0.7, 1.0),
+                        ..params
+                    },
+                    nr_particles: 256 * 128,
+                },
+                Preset {
+                    name: "comets".to_string(),
+                    params: FlowFieldParams {
+                        color_mode: ColorMode::Age,
+                        age_curve: AgeCurve::EaseOut,
+                        particle_lifetime: 4.0,
+                        fade: 0.05,
+                        deposit: 3.0,
+                        ..params
+                    },
+                    nr_particles: 256 * 64,
+                },
+            ],
+            active: 0,
+        }
+    }
+}
+
+pub(crate) fn apply_preset(
+    presets: Res<Presets>,
+    mut fields: Query<(
+        &mut FlowFieldParams,
+        Option<&mut ParamInterpolation>,
+        &mut FlowFieldSettings,
+    )>,
+) {
+    if !presets.is_changed() || presets.is_added() {
+        return;
+    }
+    let Some(preset) = presets.active() else {
+        return;
+    };
+
+    info!(
+        "switching to preset {}: {}",
+        presets.active + 1,
+        preset.name
+    );
+    for (mut params, interpolation, mut settings) in &mut fields {
+        let target = FlowFieldParams {
+            noise_offset: params.noise_offset,
+            ..preset.params
+        };
+        match interpolation {
+            Some(mut interpolation) => interpolation.set_target(target),
+            None => *params = target,
+        }
+        if settings.nr_particles != preset.nr_particles {
+            settings.nr_particles = preset.nr_particles;
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::{ShaderType, StorageBuffer},
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// The shape of the flow around a [`FieldPrimitive`].
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PrimitiveKind {
+    /// Circles around the position, counter-clockwise for a positive strength.
+    #[default]
+    Vortex,
+    /// Pushes away from the position.
+    Source,
+    /// Pulls towards the position.
+    Sink,
+    /// Blows along the direction.
+    Wind,
+}
+
+/// An analytic building block of the flow, in pixel coordinates of the flow field.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FieldPrimitive {
+    pub kind: PrimitiveKind,
+    pub position: Vec2,
+    /// Direction of a [`PrimitiveKind::Wind`], ignored by the other kinds.
+    pub direction: Vec2,
+    /// Speed of the flow relative to the noise field, negative values reverse it.
+    pub strength: f32,
+    /// Distance in pixels at which the influence has dropped to about a third, `0.0` reaches the
+    /// whole field.
+    pub radius: f32,
+}
+
+impl Default for FieldPrimitive {
+    fn default() -> Self {
+        Self {
+            kind: PrimitiveKind::Vortex,
+            position: Vec2::ZERO,
+            direction: Vec2::X,
+            strength: 1.0,
+            radius: 100.0,
+        }
+    }
+}
+
+/// The primitives summed into every flow field, blended with the noise according to
+/// [`FlowFieldParams::noise_mix`](crate::FlowFieldParams::noise_mix). Uploaded again whenever the
+/// resource changes.
+#[derive(Resource, ExtractResource, Clone, Default, Debug)]
+pub struct FieldPrimitives(pub Vec<FieldPrimitive>);
+
+#[derive(Clone, Copy, Default, ShaderType)]
+pub(crate) struct GpuFieldPrimitive {
+    position: Vec2,
+    direction: Vec2,
+    kind: u32,
+    strength: f32,
+    radius: f32,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct FieldPrimitivesBuffer {
+    pub(crate) buffer: StorageBuffer<Vec<GpuFieldPrimitive>>,
+}
+
+pub(crate) fn prepare_field_primitives(
+    primitives: Res<FieldPrimitives>,
+    mut buffer: ResMut<FieldPrimitivesBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !primitives.is_changed() {
+        return;
+    }
+
+    let mut gpu_primitives: Vec<GpuFieldPrimitive> = primitives
+        .0
+        .iter()
+        .map(|primitive| GpuFieldPrimitive {
+            position: primitive.position,
+            direction: primitive.direction.normalize_or_zero(),
+            kind: primitive.kind as u32,
+            strength: primitive.strength,
+            radius: primitive.radius.max(0.0),
+        })
+        .collect();
+    // Bindings can't be empty, the shader only looks at the first `primitive_count` entries.
+    if gpu_primitives.is_empty() {
+        gpu_primitives.push(GpuFieldPrimitive::default());
+    }
+    buffer.buffer.set(gpu_primitives);
+    buffer.buffer.set_label(Some("flow field primitives"));
+    buffer.buffer.write_buffer(&render_device, &render_queue);
+}
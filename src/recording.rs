@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+#[cfg(feature = "export-png")]
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+#[cfg(feature = "export-png")]
+use bevy::render::{
+    render_asset::RenderAssets,
+    render_resource::Buffer,
+    renderer::{RenderDevice, RenderQueue},
+    texture::GpuImage,
+};
+use bevy::{prelude::*, render::extract_resource::ExtractResource};
+
+#[cfg(feature = "export-png")]
+use crate::{
+    poll,
+    screenshot::Readback,
+    shutdown::{Exiting, PendingExports},
+    FlowFieldOutput, SimParamsBuffer,
+};
+
+/// Number of staging buffers frames are read back into. While one is being written to disk the
+/// next frame is copied into the other, so the GPU never waits for a buffer to be mapped.
+#[cfg(feature = "export-png")]
+const STAGING_BUFFERS: usize = 2;
+
+/// Writes every `every`th simulated frame of the [`FlowFieldOutput`](crate::FlowFieldOutput) to `directory` while active,
+/// as `frame_00000.png`, `frame_00001.png` and so on. The numbers keep counting up when recording
+/// is stopped and started again, so a session never overwrites its own frames. Needs the
+/// `export-png` feature.
+#[derive(Resource, ExtractResource, Clone, Debug)]
+pub struct Recording {
+    pub active: bool,
+    pub every: u32,
+    pub directory: PathBuf,
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self {
+            active: false,
+            every: 1,
+            directory: PathBuf::from("frames"),
+        }
+    }
+}
+
+/// Render world state of a [`Recording`]: the frame counters and the staging buffers, which the
+/// writer threads hand back once their frame is on disk.
+#[cfg(feature = "export-png")]
+#[derive(Resource)]
+pub(crate) struct Recorder {
+    /// Simulated frames since recording was last started.
+    frame: u64,
+    /// Number of the next file.
+    next_index: u32,
+    was_active: bool,
+    readback: Option<Readback>,
+    free: Vec<Buffer>,
+    /// Buffers that exist, free or in flight.
+    allocated: usize,
+    /// Resources have to be `Sync`, the receiver is only ever used through `&mut self`.
+    returned: (Sender<Buffer>, Mutex<Receiver<Buffer>>),
+    writers: Vec<JoinHandle<()>>,
+    render_device: RenderDevice,
+}
+
+#[cfg(feature = "export-png")]
+impl FromWorld for Recorder {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            frame: 0,
+            next_index: 0,
+            was_active: false,
+            readback: None,
+            free: Vec::new(),
+            allocated: 0,
+            returned: {
+                let (sender, receiver) = mpsc::channel();
+                (sender, Mutex::new(receiver))
+            },
+            writers: Vec::new(),
+            render_device: world.resource::<RenderDevice>().clone(),
+        }
+    }
+}
+
+#[cfg(feature = "export-png")]
+impl Recorder {
+    fn receiver(&mut self) -> &Receiver<Buffer> {
+        self.returned.1.get_mut().unwrap()
+    }
+
+    /// Takes back the buffers of finished frames, keeping only the ones matching the layout.
+    fn reclaim(&mut self, buffer: Buffer) {
+        match self.readback {
+            Some(readback) if buffer.size() == readback.buffer_size() => self.free.push(buffer),
+            _ => self.allocated -= 1,
+        }
+    }
+
+    /// A free staging buffer, waiting for a writer to finish if both are in flight.
+    fn staging(&mut self, readback: Readback) -> Buffer {
+        if self.readback != Some(readback) {
+            self.allocated -= self.free.len();
+            self.free.clear();
+            self.readback = Some(readback);
+        }
+        while let Ok(buffer) = self.receiver().try_recv() {
+            self.reclaim(buffer);
+        }
+        while self.free.is_empty() {
+            if self.allocated < STAGING_BUFFERS {
+                self.allocated += 1;
+                return readback
+                    .create_buffer(&self.render_device, "flow field recording readback");
+            }
+            let buffer = self.receiver().recv().expect("the recorder owns a sender");
+            self.reclaim(buffer);
+        }
+        self.free.pop().unwrap()
+    }
+
+    /// Waits until every frame read back so far has been written.
+    fn flush(&mut self) {
+        for writer in self.writers.drain(..) {
+            if writer.join().is_err() {
+                error!("a recording thread panicked");
+            }
+        }
+    }
+
+    /// Lets the writers finish on their own, counted in the [`PendingExports`].
+    fn shutdown(&mut self) {
+        self.writers.clear();
+    }
+}
+
+#[cfg(feature = "export-png")]
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Copies the output image of every recorded frame into a staging buffer after the frame has been
+/// submitted and writes it to disk on a background thread.
+#[cfg(feature = "export-png")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_frame(
+    recording: Option<Res<Recording>>,
+    mut recorder: ResMut<Recorder>,
+    exiting: Res<Exiting>,
+    pending: Res<PendingExports>,
+    sim_params: Res<SimParamsBuffer>,
+    output: Option<Res<FlowFieldOutput>>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if exiting.0 {
+        recorder.shutdown();
+        return;
+    }
+    let active = recording.as_ref().is_some_and(|recording| recording.active);
+    if active && !recorder.was_active {
+        recorder.frame = 0;
+    }
+    recorder.was_active = active;
+    recorder.writers.retain(|writer| !writer.is_finished());
+
+    let image = output.and_then(|output| gpu_images.get(&output.0));
+    if let (Some(recording), Some(image)) = (recording.filter(|_| active), image) {
+        if sim_params.advance {
+            if recorder.frame.is_multiple_of(recording.every.max(1) as u64) {
+                let path = recording
+                    .directory
+                    .join(format!("frame_{:05}.png", recorder.next_index));
+                recorder.next_index += 1;
+                let writer = write_frame(
+                    &mut recorder,
+                    image,
+                    path,
+                    &pending,
+                    &render_device,
+                    &render_queue,
+                );
+                recorder.writers.push(writer);
+            }
+            recorder.frame += 1;
+        }
+    }
+}
+
+#[cfg(feature = "export-png")]
+fn write_frame(
+    recorder: &mut Recorder,
+    image: &GpuImage,
+    path: PathBuf,
+    pending: &PendingExports,
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+) -> JoinHandle<()> {
+    let readback = Readback::new(image);
+    let staging = recorder.staging(readback);
+    readback.copy(image, &staging, render_device, render_queue);
+
+    let mapped = poll::map_read(render_device, &staging);
+    let returned = recorder.returned.0.clone();
+    let render_queue = render_queue.clone();
+    let pending = pending.start();
+    thread::spawn(move || {
+        let _pending = pending;
+        // Blocks this thread rather than the renderer until the copy is done and mapped.
+        match poll::wait_for_map(&render_queue, &mapped).expect("the recording buffer was dropped")
+        {
+            Ok(()) => {
+                if let Err(err) = readback.save_png(&staging, &path) {
+                    error!("could not save frame {}: {err}", path.display());
+                }
+            }
+            Err(err) => error!("could not read frame {} back: {err}", path.display()),
+        }
+        // The recorder is gone when the app exits, then the buffer is dropped instead.
+        let _ = returned.send(staging);
+    })
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FieldPrimitive, FieldPrimitives, FlowFieldParams, FlowFieldStatus, Obstacle, Obstacles,
+    PrimaryFlowField, ResetSimulation, SimulationSpeed, SimulationState, Substeps,
+};
+
+/// The changes made to a simulation while it ran, each with the frame it was made in, as
+/// written by the [`SessionRecorderPlugin`] and played back by the [`SessionReplayPlugin`].
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    /// Seconds every frame advances the simulation by.
+    pub dt: f32,
+    pub changes: Vec<SessionChange>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionChange {
+    /// Simulated frames before the change, frames while paused don't count.
+    pub frame: u64,
+    pub change: Change,
+}
+
+/// A mutation of the simulation, holding the new value.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Change {
+    /// The [`FlowFieldParams`] of the [`PrimaryFlowField`].
+    Params(Box<FlowFieldParams>),
+    Speed(f32),
+    Substeps(u32),
+    Primitives(Vec<FieldPrimitive>),
+    Obstacles(Vec<Obstacle>),
+    Reset,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        ron::from_str(&source).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, source)
+    }
+}
+
+/// Logs every change to the [`FlowFieldParams`] of the [`PrimaryFlowField`], the
+/// [`SimulationSpeed`], [`Substeps`], [`FieldPrimitives`] and [`Obstacles`] and every
+/// [`ResetSimulation`] to `path`, for the [`SessionReplayPlugin`] to reproduce the render. While
+/// recording the simulation advances by a fixed `dt` every frame, and it is held until the compute
+/// pipelines have compiled so frames count the same way as in the replay. The file is rewritten
+/// after every change. Pointer forces, painting and single steps aren't recorded.
+pub struct SessionRecorderPlugin {
+    pub path: PathBuf,
+    pub dt: f32,
+}
+
+/// Plays a recorded [`Session`] back, applying every change at the frame it was made in with the
+/// same fixed time step. Started from the same config and seed, this reproduces the recorded
+/// render up to the order the GPU sums floats in.
+pub struct SessionReplayPlugin {
+    pub session: Session,
+}
+
+impl Plugin for SessionRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SessionLog {
+            path: self.path.clone(),
+            session: Session {
+                dt: self.dt,
+                changes: Vec::new(),
+            },
+            recorded: Recorded::default(),
+        });
+        add_clock(app, self.dt);
+        app.add_systems(PostUpdate, record_changes);
+    }
+}
+
+impl Plugin for SessionReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Playback {
+            changes: self.session.changes.clone(),
+            next: 0,
+        });
+        add_clock(app, self.session.dt);
+        // Resets are picked up in `Update`, the other changes are applied after it, where they
+        // were recorded.
+        app.add_systems(PreUpdate, replay_resets);
+        app.add_systems(PostUpdate, replay_changes);
+    }
+}
+
+fn add_clock(app: &mut App, dt: f32) {
+    assert!(dt > 0.0, "the session time step must be positive");
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        dt,
+    )));
+    app.init_resource::<SessionClock>();
+    app.add_systems(First, start_session);
+    app.add_systems(Last, advance_clock);
+}
+
+/// Simulated frames since the session started.
+#[derive(Resource, Default)]
+struct SessionClock {
+    started: bool,
+    frame: u64,
+}
+
+/// Holds the simulation until the pipelines are ready, so the first running frame is also the
+/// first simulated one.
+fn start_session(
+    mut clock: ResMut<SessionClock>,
+    status: Res<FlowFieldStatus>,
+    mut state: ResMut<SimulationState>,
+) {
+    if clock.started {
+        return;
+    }
+    if status.is_ready() {
+        clock.started = true;
+        *state = SimulationState::Running;
+    } else if *state != SimulationState::Paused {
+        *state = SimulationState::Paused;
+    }
+}
+
+fn advance_clock(mut clock: ResMut<SessionClock>, state: Res<SimulationState>) {
+    if clock.started && *state == SimulationState::Running {
+        clock.frame += 1;
+    }
+}
+
+#[derive(Resource)]
+struct SessionLog {
+    path: PathBuf,
+    session: Session,
+    recorded: Recorded,
+}
+
+/// The recorded values, `None` before the first frame.
+#[derive(Default)]
+struct Recorded {
+    params: Option<FlowFieldParams>,
+    speed: Option<f32>,
+    substeps: Option<u32>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_changes(
+    mut log: ResMut<SessionLog>,
+    clock: Res<SessionClock>,
+    field: Query<&FlowFieldParams, With<PrimaryFlowField>>,
+    speed: Res<SimulationSpeed>,
+    substeps: Res<Substeps>,
+    primitives: Res<FieldPrimitives>,
+    obstacles: Res<Obstacles>,
+    mut resets: EventReader<ResetSimulation>,
+) {
+    let reset = resets.iter().count() > 0;
+    if !clock.started {
+        return;
+    }
+    let first = log.session.changes.is_empty();
+    let log = &mut *log;
+    let mut changes = Vec::new();
+
+    if let Ok(params) = field.get_single() {
+        if log.recorded.params.as_ref() != Some(params) {
+            log.recorded.params = Some(*params);
+            changes.push(Change::Params(Box::new(*params)));
+        }
+    }
+    if log.recorded.speed != Some(speed.0) {
+        log.recorded.speed = Some(speed.0);
+        changes.push(Change::Speed(speed.0));
+    }
+    if log.recorded.substeps != Some(substeps.0) {
+        log.recorded.substeps = Some(substeps.0);
+        changes.push(Change::Substeps(substeps.0));
+    }
+    if first || primitives.is_changed() {
+        changes.push(Change::Primitives(primitives.0.clone()));
+    }
+    if first || obstacles.is_changed() {
+        changes.push(Change::Obstacles(obstacles.0.clone()));
+    }
+    if reset {
+        changes.push(Change::Reset);
+    }
+    if changes.is_empty() {
+        return;
+    }
+
+    let frame = clock.frame;
+    log.session.changes.extend(
+        changes
+            .into_iter()
+            .map(|change| SessionChange { frame, change }),
+    );
+    if let Err(err) = log.session.save(&log.path) {
+        error!(
+            "could not save the session to {}: {err}",
+            log.path.display()
+        );
+    }
+}
+
+#[derive(Resource)]
+struct Playback {
+    changes: Vec<SessionChange>,
+    /// Index of the first change that hasn't been applied.
+    next: usize,
+}
+
+impl Playback {
+    /// The changes due this frame.
+    fn due(&mut self, frame: u64) -> &[SessionChange] {
+        let start = self.next;
+        let count = self.changes[start..]
+            .iter()
+            .take_while(|change| change.frame <= frame)
+            .count();
+        self.next += count;
+        &self.changes[start..self.next]
+    }
+}
+
+fn replay_resets(
+    playback: Res<Playback>,
+    clock: Res<SessionClock>,
+    mut resets: EventWriter<ResetSimulation>,
+) {
+    if !clock.started {
+        return;
+    }
+    let mut pending = playback.changes[playback.next..]
+        .iter()
+        .take_while(|change| change.frame <= clock.frame);
+    if pending.any(|change| matches!(change.change, Change::Reset)) {
+        resets.send(ResetSimulation);
+    }
+}
+
+fn replay_changes(
+    mut playback: ResMut<Playback>,
+    clock: Res<SessionClock>,
+    mut field: Query<&mut FlowFieldParams, With<PrimaryFlowField>>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut substeps: ResMut<Substeps>,
+    mut primitives: ResMut<FieldPrimitives>,
+    mut obstacles: ResMut<Obstacles>,
+) {
+    if !clock.started {
+        return;
+    }
+    for change in playback.due(clock.frame) {
+        match &change.change {
+            Change::Params(params) => {
+                if let Ok(mut field) = field.get_single_mut() {
+                    *field = **params;
+                }
+            }
+            Change::Speed(value) => speed.0 = *value,
+            Change::Substeps(value) => substeps.0 = *value,
+            Change::Primitives(value) => primitives.0 = value.clone(),
+            Change::Obstacles(value) => obstacles.0 = value.clone(),
+            Change::Reset => {}
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+#[cfg(feature = "export-png")]
+use std::{path::PathBuf, sync::mpsc, thread};
+
+#[cfg(feature = "export-png")]
+use bevy::render::{render_asset::RenderAssets, render_resource::MapMode};
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d,
+            ImageCopyBuffer, ImageDataLayout, TextureDimension, TextureFormat,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::{GpuImage, TextureFormatPixelInfo},
+    },
+};
+
+use crate::SeedVariation;
+#[cfg(feature = "export-png")]
+use crate::{
+    shutdown::{Exiting, PendingExports},
+    FlowFieldOutput,
+};
+
+/// Folder screenshots are saved to, relative to the working directory.
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Asks for the [`FlowFieldOutput`](crate::FlowFieldOutput) to be saved as a PNG in [`SCREENSHOT_DIR`]. Like the
+/// [`StepRequest`](crate::StepRequest), the flag is shared with the render world, which reads the
+/// image back after the frame has been rendered and writes the file on a background thread.
+/// Without the `export-png` feature requests are logged and dropped.
+#[derive(Resource, Clone, Default)]
+pub struct ScreenshotRequest(Arc<AtomicBool>);
+
+impl ScreenshotRequest {
+    pub fn request(&self) {
+        if cfg!(not(feature = "export-png")) {
+            warn!("built without export-png, no screenshot is taken");
+            return;
+        }
+        self.0.store(true, Ordering::Release);
+    }
+
+    #[cfg(feature = "export-png")]
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Copies the output image into a buffer once the frame has been submitted. The buffer is mapped
+/// when the next frame is submitted, the background thread waits for that.
+#[cfg(feature = "export-png")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn capture_screenshot(
+    request: Res<ScreenshotRequest>,
+    exiting: Res<Exiting>,
+    pending: Res<PendingExports>,
+    output: Option<Res<FlowFieldOutput>>,
+    variation: Option<Res<SeedVariation>>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    // No new exports are started once the app exits.
+    if exiting.0 || !request.take() {
+        return;
+    }
+    let Some(image) = output.and_then(|output| gpu_images.get(&output.0)) else {
+        warn!("the flow field output isn't ready yet, no screenshot taken");
+        return;
+    };
+
+    let readback = Readback::new(image);
+    let staging = readback.create_buffer(&render_device, "flow field screenshot readback");
+    readback.copy(image, &staging, &render_device, &render_queue);
+
+    let (sender, receiver) = mpsc::channel();
+    render_device.map_buffer(&staging.slice(..), MapMode::Read, move |result| {
+        // The thread is gone if it panicked, in which case there is nothing left to do.
+        let _ = sender.send(result);
+    });
+    let path = PathBuf::from(SCREENSHOT_DIR).join(file_name("flow", variation.as_deref(), "png"));
+    let pending = pending.start();
+    thread::spawn(move || {
+        let _pending = pending;
+        if let Err(err) = receiver.recv().expect("the screenshot buffer was dropped") {
+            error!("could not read the screenshot back: {err}");
+            return;
+        }
+        match readback.save_png(&staging, &path) {
+            Ok(()) => info!("saved screenshot to {}", path.display()),
+            Err(err) => error!("could not save screenshot to {}: {err}", path.display()),
+        }
+    });
+}
+
+/// How an output image is laid out once it has been copied into a buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Readback {
+    pub(crate) size: UVec2,
+    format: TextureFormat,
+    /// Rows of a texture copy have to start at multiples of 256 bytes.
+    padded_row_bytes: usize,
+}
+
+impl Readback {
+    pub(crate) fn new(image: &GpuImage) -> Self {
+        let size = image.size.as_uvec2();
+        let format = image.texture_format;
+        let row_bytes = size.x as usize * format.pixel_size();
+        Self {
+            size,
+            format,
+            padded_row_bytes: RenderDevice::align_copy_bytes_per_row(row_bytes),
+        }
+    }
+
+    pub(crate) fn buffer_size(&self) -> u64 {
+        (self.padded_row_bytes * self.size.y as usize) as u64
+    }
+
+    pub(crate) fn create_buffer(&self, render_device: &RenderDevice, label: &str) -> Buffer {
+        render_device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: self.buffer_size(),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Submits a copy of the image into `staging`, which has to be unmapped.
+    pub(crate) fn copy(
+        &self,
+        image: &GpuImage,
+        staging: &Buffer,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+    ) {
+        let mut encoder =
+            render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            image.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: staging,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_row_bytes as u32),
+                    rows_per_image: None,
+                },
+            },
+            self.extent(),
+        );
+        render_queue.submit([encoder.finish()]);
+    }
+
+    /// The tightly packed 8-bit sRGB pixels of the mapped `staging` buffer, which is unmapped once
+    /// they have been read.
+    pub(crate) fn srgb_pixels(&self, staging: &Buffer) -> Result<Vec<u8>, String> {
+        let pixels = to_srgb(staging, *self);
+        staging.unmap();
+        pixels.ok_or_else(|| format!("{:?} images aren't supported", self.format))
+    }
+
+    /// Encodes the mapped `staging` buffer as a PNG, unmapping it once the pixels have been read.
+    pub(crate) fn save_png(&self, staging: &Buffer, path: &Path) -> Result<(), String> {
+        let pixels = self.srgb_pixels(staging)?;
+        let image = Image::new(
+            self.extent(),
+            TextureDimension::D2,
+            pixels,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+        }
+        image
+            .try_into_dynamic()
+            .map_err(|err| err.to_string())?
+            .save(path)
+            .map_err(|err| err.to_string())
+    }
+
+    fn extent(&self) -> Extent3d {
+        Extent3d {
+            width: self.size.x,
+            height: self.size.y,
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+/// The mapped rows without their padding, encoded as 8-bit sRGB the way the window shows them. The
+/// image already holds the final linear colors, so everything above `1.0` is clipped.
+fn to_srgb(staging: &Buffer, readback: Readback) -> Option<Vec<u8>> {
+    let Readback {
+        size,
+        format,
+        padded_row_bytes,
+    } = readback;
+    let channel: fn(&[u8]) -> f32 = match format {
+        TextureFormat::Rgba32Float => |bytes| f32::from_le_bytes(bytes[..4].try_into().unwrap()),
+        TextureFormat::Rgba16Float => |bytes| f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+        TextureFormat::Rgba8Unorm => |bytes| bytes[0] as f32 / 255.0,
+        _ => return None,
+    };
+    let pixel_bytes = format.pixel_size();
+    let channel_bytes = pixel_bytes / 4;
+
+    let data = staging.slice(..).get_mapped_range();
+    let mut pixels = Vec::with_capacity(4 * size.x as usize * size.y as usize);
+    for row in data.chunks_exact(padded_row_bytes) {
+        for pixel in row[..size.x as usize * pixel_bytes].chunks_exact(pixel_bytes) {
+            let [r, g, b] = [0, 1, 2].map(|c| channel(&pixel[c * channel_bytes..]));
+            let [r, g, b] = [r, g, b].map(|c| c.clamp(0.0, 1.0));
+            pixels.extend(Color::rgb_linear(r, g, b).as_rgba_u8());
+        }
+    }
+    Some(pixels)
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as f32 / 1024.0;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-14),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// Name of a file saved now, like `flow_20240101_120000.png`, with the index of the
+/// [`SeedVariation`] it comes from appended, if any.
+pub(crate) fn file_name(
+    prefix: &str,
+    variation: Option<&SeedVariation>,
+    extension: &str,
+) -> String {
+    match variation.and_then(|variation| variation.index) {
+        Some(index) => format!("{prefix}_{}_v{index}.{extension}", timestamp()),
+        None => format!("{prefix}_{}.{extension}", timestamp()),
+    }
+}
+
+/// The current UTC time as `YYYYMMDD_HHMMSS`.
+pub(crate) fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{year:04}{month:02}{day:02}_{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
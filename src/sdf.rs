@@ -0,0 +1,247 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        Extract,
+    },
+};
+
+/// A black-and-white image whose dark, opaque pixels are turned into an [`ObstacleSdf`] once it has
+/// loaded.
+#[derive(Resource, Clone, Debug)]
+pub struct ObstacleMask(pub Handle<Image>);
+
+/// An `R32Float` signed distance field stretched over every flow field, negative inside the
+/// obstacles and in texels of the image. Particles within
+/// [`FlowFieldParams::sdf_threshold`](crate::FlowFieldParams::sdf_threshold) pixels of an edge are
+/// steered along it.
+#[derive(Resource, Clone, Debug)]
+pub struct ObstacleSdf(pub Handle<Image>);
+
+/// Luminance below which an opaque mask pixel counts as inside an obstacle.
+const MASK_THRESHOLD: f32 = 0.5;
+
+/// Squared distances are seeded with this rather than infinity so the parabola intersections stay
+/// finite.
+const FAR: f32 = 1e20;
+
+/// The signed distance in cells from the center of every cell to the nearest edge of the mask,
+/// negative inside. The mask is laid out row by row.
+pub fn signed_distance_field(mask: &[bool], width: usize, height: usize) -> Vec<f32> {
+    assert_eq!(mask.len(), width * height, "mask doesn't match its size");
+    let outside = distance_transform(mask, width, height, true);
+    let inside = distance_transform(mask, width, height, false);
+    // Half a cell either way puts the edge between the cells instead of on them.
+    mask.iter()
+        .zip(outside.iter().zip(&inside))
+        .map(|(&is_inside, (outside, inside))| {
+            if is_inside {
+                0.5 - inside
+            } else {
+                outside - 0.5
+            }
+        })
+        .collect()
+}
+
+/// Exact euclidean distance from every cell to the nearest cell where `mask == target`, after
+/// Felzenszwalb and Huttenlocher: a 1D transform down the columns, then along the rows.
+fn distance_transform(mask: &[bool], width: usize, height: usize, target: bool) -> Vec<f32> {
+    let mut grid: Vec<f32> = mask
+        .iter()
+        .map(|&cell| if cell == target { 0.0 } else { FAR })
+        .collect();
+
+    let len = width.max(height);
+    let mut line = vec![0.0; len];
+    let mut out = vec![0.0; len];
+    let mut parabolas = vec![0; len];
+    let mut bounds = vec![0.0; len + 1];
+
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&line[..height], &mut out, &mut parabolas, &mut bounds);
+        for y in 0..height {
+            grid[y * width + x] = out[y];
+        }
+    }
+    for row in grid.chunks_mut(width) {
+        line[..width].copy_from_slice(row);
+        distance_transform_1d(&line[..width], &mut out, &mut parabolas, &mut bounds);
+        row.copy_from_slice(&out[..width]);
+    }
+
+    grid.iter().map(|distance| distance.sqrt()).collect()
+}
+
+/// Squared distance transform of a sampled function, as the lower envelope of the parabolas rooted
+/// at each sample.
+fn distance_transform_1d(f: &[f32], out: &mut [f32], parabolas: &mut [usize], bounds: &mut [f32]) {
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * (q as f32 - p as f32))
+    };
+
+    let mut k = 0;
+    parabolas[0] = 0;
+    bounds[0] = f32::NEG_INFINITY;
+    bounds[1] = f32::INFINITY;
+    for q in 1..f.len() {
+        let mut s = intersection(q, parabolas[k]);
+        while s <= bounds[k] {
+            k -= 1;
+            s = intersection(q, parabolas[k]);
+        }
+        k += 1;
+        parabolas[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, out) in out.iter_mut().enumerate().take(f.len()) {
+        while bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - parabolas[k] as f32;
+        *out = offset * offset + f[parabolas[k]];
+    }
+}
+
+/// The obstacle cells of a mask image, or `None` for formats other than 8-bit RGBA.
+pub fn mask_from_image(image: &Image) -> Option<Vec<bool>> {
+    if !matches!(
+        image.texture_descriptor.format,
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+    ) {
+        return None;
+    }
+    let mask = image
+        .data
+        .chunks_exact(4)
+        .map(|pixel| {
+            let [r, g, b, a] = [pixel[0], pixel[1], pixel[2], pixel[3]].map(|c| c as f32 / 255.0);
+            let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            a > 0.5 && luminance < MASK_THRESHOLD
+        })
+        .collect();
+    Some(mask)
+}
+
+/// Wraps signed distances in an `R32Float` image that can be used as an [`ObstacleSdf`].
+pub fn sdf_image(distances: &[f32], width: u32, height: u32) -> Image {
+    Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        bytemuck::cast_slice(distances).to_vec(),
+        TextureFormat::R32Float,
+    )
+}
+
+/// Turns the [`ObstacleMask`] into the [`ObstacleSdf`] once it has loaded.
+pub(crate) fn build_obstacle_sdf(
+    mut commands: Commands,
+    mut built: Local<Option<Handle<Image>>>,
+    mask: Option<Res<ObstacleMask>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(mask) = mask else {
+        return;
+    };
+    if built.as_ref() == Some(&mask.0) {
+        return;
+    }
+    let Some(image) = images.get(&mask.0) else {
+        return;
+    };
+
+    *built = Some(mask.0.clone());
+    let size = image.texture_descriptor.size;
+    let Some(cells) = mask_from_image(image) else {
+        warn!(
+            "obstacle masks must be 8-bit RGBA, got {:?}",
+            image.texture_descriptor.format
+        );
+        return;
+    };
+    let distances = signed_distance_field(&cells, size.width as usize, size.height as usize);
+    let sdf = images.add(sdf_image(&distances, size.width, size.height));
+    commands.insert_resource(ObstacleSdf(sdf));
+}
+
+/// Mirrors the main world resource, including its removal.
+pub(crate) fn extract_obstacle_sdf(mut commands: Commands, sdf: Extract<Option<Res<ObstacleSdf>>>) {
+    match &*sdf {
+        Some(sdf) => commands.insert_resource(ObstacleSdf::clone(sdf)),
+        None => commands.remove_resource::<ObstacleSdf>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn single_point() {
+        let (width, height) = (7, 7);
+        let mut mask = vec![false; width * height];
+        mask[3 * width + 3] = true;
+        let sdf = signed_distance_field(&mask, width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (dx, dy) = (x as f32 - 3.0, y as f32 - 3.0);
+                let expected = match (x, y) {
+                    (3, 3) => -0.5,
+                    _ => (dx * dx + dy * dy).sqrt() - 0.5,
+                };
+                assert_close(sdf[y * width + x], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn line() {
+        let (width, height) = (6, 4);
+        let mask: Vec<bool> = (0..width * height).map(|i| i % width == 2).collect();
+        let sdf = signed_distance_field(&mask, width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let expected = match x {
+                    2 => -0.5,
+                    _ => (x as f32 - 2.0).abs() - 0.5,
+                };
+                assert_close(sdf[y * width + x], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_inside() {
+        let (width, height) = (7, 7);
+        // A 5x5 square with a one cell border around it.
+        let mask: Vec<bool> = (0..width * height)
+            .map(|i| (1..6).contains(&(i % width)) && (1..6).contains(&(i / width)))
+            .collect();
+        let sdf = signed_distance_field(&mask, width, height);
+
+        assert_close(sdf[3 * width + 3], -2.5);
+        assert_close(sdf[3 * width + 2], -1.5);
+        assert_close(sdf[width + 1], -0.5);
+        assert_close(sdf[3 * width], 0.5);
+        assert_close(sdf[0], 2f32.sqrt() - 0.5);
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::{FieldType, FlowFieldParams, InputBindings, Palette, Seed};
+
+/// Parameter sets kept to step back to.
+const HISTORY: usize = 16;
+
+/// The parameters of every flow field and the palette, as they were before a shuffle.
+struct ParamSet {
+    params: Vec<(Entity, FlowFieldParams)>,
+    palette: Option<Palette>,
+}
+
+/// The parameters before every shuffle, newest last, and the generator picking the next ones.
+#[derive(Resource, Default)]
+pub(crate) struct ParamShuffle {
+    rng: Option<StdRng>,
+    history: VecDeque<ParamSet>,
+}
+
+/// The shuffled parameters, named like the fields of a [`FlowFieldConfig`](crate::FlowFieldConfig)
+/// so the logged RON can be pasted into one.
+#[derive(Serialize)]
+struct Shuffled {
+    noise_scale: f32,
+    octaves: u32,
+    max_speed: f32,
+    fade: f32,
+    field_type: FieldType,
+}
+
+/// Uniform in log space, so every order of magnitude is as likely.
+fn log_uniform(rng: &mut StdRng, min: f32, max: f32) -> f32 {
+    (min.ln() + (max.ln() - min.ln()) * rng.gen::<f32>()).exp()
+}
+
+/// Randomizes the noise, speed, fade, field type and palette of every flow field, within ranges
+/// that keep the trails visible.
+pub(crate) fn shuffle_params(
+    mut commands: Commands,
+    mut shuffle: ResMut<ParamShuffle>,
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    seed: Res<Seed>,
+    palette: Option<Res<Palette>>,
+    mut fields: Query<(Entity, &mut FlowFieldParams)>,
+) {
+    if !bindings.shuffle_params.just_pressed(&keys) {
+        return;
+    }
+
+    if shuffle.history.len() == HISTORY {
+        shuffle.history.pop_front();
+    }
+    shuffle.history.push_back(ParamSet {
+        params: fields
+            .iter()
+            .map(|(entity, params)| (entity, *params))
+            .collect(),
+        palette: palette.map(|palette| palette.clone()),
+    });
+
+    let rng = shuffle
+        .rng
+        .get_or_insert_with(|| StdRng::seed_from_u64(seed.0));
+    let shuffled = Shuffled {
+        noise_scale: log_uniform(rng, 80.0, 800.0),
+        octaves: rng.gen_range(1..=4),
+        max_speed: log_uniform(rng, 60.0, 300.0),
+        fade: log_uniform(rng, 0.002, 0.05),
+        field_type: FieldType::ALL[rng.gen_range(0..FieldType::ALL.len())],
+    };
+    // Index zero is no palette at all.
+    let builtins = Palette::builtins();
+    let palette = rng
+        .gen_range(0..=builtins.len())
+        .checked_sub(1)
+        .map(|index| builtins[index].clone());
+
+    for (_, mut params) in &mut fields {
+        params.noise_scale = shuffled.noise_scale;
+        params.octaves = shuffled.octaves;
+        params.max_speed = shuffled.max_speed;
+        params.fade = shuffled.fade;
+        params.field_type = shuffled.field_type;
+    }
+    let palette_name = palette.as_ref().map_or("none", Palette::name).to_string();
+    match palette {
+        Some(palette) => commands.insert_resource(palette),
+        None => commands.remove_resource::<Palette>(),
+    }
+    let ron = ron::ser::to_string_pretty(&shuffled, ron::ser::PrettyConfig::default())
+        .unwrap_or_default();
+    info!("shuffled parameters, palette {palette_name}:\n{ron}");
+}
+
+/// Restores the parameters from before the last shuffle.
+pub(crate) fn restore_params(
+    mut commands: Commands,
+    mut shuffle: ResMut<ParamShuffle>,
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut fields: Query<&mut FlowFieldParams>,
+) {
+    if !bindings.previous_params.just_pressed(&keys) {
+        return;
+    }
+    let Some(previous) = shuffle.history.pop_back() else {
+        info!("no earlier parameters to go back to");
+        return;
+    };
+
+    for (entity, previous) in previous.params {
+        if let Ok(mut params) = fields.get_mut(entity) {
+            *params = previous;
+        }
+    }
+    match previous.palette {
+        Some(palette) => commands.insert_resource(palette),
+        None => commands.remove_resource::<Palette>(),
+    }
+    info!(
+        "restored the previous parameters, {} more to go back to",
+        shuffle.history.len()
+    );
+}
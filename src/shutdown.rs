@@ -0,0 +1,93 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::{renderer::RenderQueue, Extract},
+};
+
+use crate::poll;
+
+/// How long the app waits on exit for the [`PendingExports`] before leaving them unfinished.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the device is polled while waiting for the exports.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Number of exports still being read back from the GPU or written on a background thread:
+/// screenshots, recorded frames, GIFs, videos, energy exports and snapshots. Shared with the render
+/// world like the [`StepRequest`](crate::StepRequest).
+///
+/// On the frame the app exits the exports stop taking requests, captures in progress are ended
+/// and ffmpeg's input is closed so it finishes the video. The renderer then waits for the count to
+/// drop to zero, for at most [`SHUTDOWN_TIMEOUT`].
+#[derive(Resource, Clone, Default)]
+pub struct PendingExports(Arc<(Mutex<usize>, Condvar)>);
+
+impl PendingExports {
+    pub fn count(&self) -> usize {
+        *self.0 .0.lock().unwrap()
+    }
+
+    /// Counts one export until the returned guard is dropped, which its thread takes along.
+    pub(crate) fn start(&self) -> PendingExport {
+        *self.0 .0.lock().unwrap() += 1;
+        PendingExport(self.clone())
+    }
+
+    /// Waits until no export is pending or `timeout` has passed.
+    fn wait(&self, timeout: Duration) {
+        let (count, finished) = &*self.0;
+        let _ = finished
+            .wait_timeout_while(count.lock().unwrap(), timeout, |count| *count > 0)
+            .unwrap();
+    }
+}
+
+/// One of the [`PendingExports`], finished when dropped.
+pub(crate) struct PendingExport(PendingExports);
+
+impl Drop for PendingExport {
+    fn drop(&mut self) {
+        let (count, finished) = &*self.0 .0;
+        *count.lock().unwrap() -= 1;
+        finished.notify_all();
+    }
+}
+
+/// Whether the app is about to exit, in which case the exports shut down.
+#[derive(Resource, Default)]
+pub(crate) struct Exiting(pub(crate) bool);
+
+pub(crate) fn extract_exit(mut exiting: ResMut<Exiting>, events: Extract<Res<Events<AppExit>>>) {
+    exiting.0 = !events.is_empty();
+}
+
+/// Blocks the last frame until the [`PendingExports`] are done, once the exports have shut down
+/// in [`RenderSet::Cleanup`](bevy::render::RenderSet::Cleanup).
+pub(crate) fn wait_for_exports(
+    exiting: Res<Exiting>,
+    pending: Res<PendingExports>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !exiting.0 || pending.count() == 0 {
+        return;
+    }
+    info!("waiting for {} exports to finish", pending.count());
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while pending.count() > 0 && Instant::now() < deadline {
+        // Buffer maps only complete while the device is polled, which no later frame does.
+        poll::poll(&render_queue);
+        pending.wait(POLL_INTERVAL);
+    }
+    if pending.count() > 0 {
+        warn!(
+            "exiting with {} exports unfinished after {} s",
+            pending.count(),
+            SHUTDOWN_TIMEOUT.as_secs_f32()
+        );
+    }
+}
@@ -0,0 +1,433 @@
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{BufferDescriptor, BufferUsages, CommandEncoderDescriptor},
+        renderer::{RenderDevice, RenderQueue},
+        Extract,
+    },
+};
+
+use crate::{
+    poll, FlowFieldParams, FlowFieldSettings, ParticleBuffer, PendingExports, PrimaryFlowField,
+    SimParamsBuffer, SimulatedFrames,
+};
+
+/// Where the controls save snapshots and load them from.
+pub const SNAPSHOT_FILE: &str = "flow_field.state";
+
+const MAGIC: &[u8; 8] = b"FLOWSTAT";
+/// Bumped whenever the layout of the file or of the particles changes.
+const VERSION: u32 = 1;
+
+/// Saves the whole simulation of the [`PrimaryFlowField`] to a file and restores it, so a long
+/// accumulation can be stopped and picked up again later. A snapshot holds the particles, the trail
+/// energies, the [`FlowFieldParams`] and the simulated time, so a restored simulation continues
+/// exactly where the saved one was, given the same frame times. It can only be restored into a
+/// field with the same resolution, supersampling and number of particles.
+#[derive(Resource, Default)]
+pub struct Snapshots {
+    save: Option<PathBuf>,
+    load: Option<PathBuf>,
+}
+
+impl Snapshots {
+    /// Saves the state after the current frame to `path`, in the background.
+    pub fn save(&mut self, path: impl Into<PathBuf>) {
+        self.save = Some(path.into());
+    }
+
+    /// Restores the snapshot at `path` before the next frame, or as soon as the field exists.
+    /// Errors are logged.
+    pub fn load(&mut self, path: impl Into<PathBuf>) {
+        self.load = Some(path.into());
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// Not a snapshot or a damaged one.
+    Format(String),
+    Version(u32),
+    /// Saved from a field configured differently: what differs, the saved and the current value.
+    Mismatch {
+        what: &'static str,
+        saved: String,
+        current: String,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "could not read snapshot: {err}"),
+            SnapshotError::Format(reason) => write!(f, "not a flow field snapshot: {reason}"),
+            SnapshotError::Version(version) => write!(
+                f,
+                "snapshot version {version} isn't supported, expected version {VERSION}"
+            ),
+            SnapshotError::Mismatch {
+                what,
+                saved,
+                current,
+            } => write!(
+                f,
+                "snapshot was saved with {what} {saved}, but the flow field has {current}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// Everything in a snapshot besides the buffers.
+#[derive(Clone)]
+struct Header {
+    size: UVec2,
+    supersample: u32,
+    nr_particles: u32,
+    params: FlowFieldParams,
+}
+
+impl Header {
+    fn new(settings: &FlowFieldSettings, params: &FlowFieldParams) -> Self {
+        Self {
+            size: UVec2::new(settings.width, settings.height),
+            supersample: settings.supersample,
+            nr_particles: settings.nr_particles,
+            params: *params,
+        }
+    }
+
+    fn check(&self, settings: &FlowFieldSettings) -> Result<(), SnapshotError> {
+        let current = Header::new(settings, &self.params);
+        let mismatch = |what, saved: &dyn fmt::Display, current: &dyn fmt::Display| {
+            Err(SnapshotError::Mismatch {
+                what,
+                saved: saved.to_string(),
+                current: current.to_string(),
+            })
+        };
+        if self.size != current.size {
+            let size = |size: UVec2| format!("{}x{}", size.x, size.y);
+            return mismatch("resolution", &size(self.size), &size(current.size));
+        }
+        if self.supersample != current.supersample {
+            return mismatch("supersampling", &self.supersample, &current.supersample);
+        }
+        if self.nr_particles != current.nr_particles {
+            return mismatch("particle count", &self.nr_particles, &current.nr_particles);
+        }
+        Ok(())
+    }
+}
+
+/// The simulation clock, see [`SimParams`](crate::SimParams).
+#[derive(Clone, Copy)]
+struct Clock {
+    simulated_frames: u64,
+    frame: u32,
+    elapsed: f32,
+}
+
+struct Snapshot {
+    header: Header,
+    clock: Clock,
+    particles: Vec<u8>,
+    energies: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Reads the snapshot at `path` and checks that it fits the field.
+    fn load(
+        path: &Path,
+        settings: &FlowFieldSettings,
+        buffers: &ParticleBuffer,
+    ) -> Result<Self, SnapshotError> {
+        let data = fs::read(path).map_err(SnapshotError::Io)?;
+        let mut reader = Reader(&data);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::Format("unknown file type".to_string()));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(SnapshotError::Version(version));
+        }
+
+        let size = UVec2::new(reader.u32()?, reader.u32()?);
+        let supersample = reader.u32()?;
+        let nr_particles = reader.u32()?;
+        let clock = Clock {
+            simulated_frames: reader.u64()?,
+            frame: reader.u32()?,
+            elapsed: f32::from_bits(reader.u32()?),
+        };
+        let params = reader.chunk()?;
+        let params = std::str::from_utf8(params)
+            .ok()
+            .and_then(|params| ron::from_str(params).ok())
+            .ok_or_else(|| SnapshotError::Format("the params can't be parsed".to_string()))?;
+        let header = Header {
+            size,
+            supersample,
+            nr_particles,
+            params,
+        };
+        header.check(settings)?;
+
+        let particles = reader.chunk()?.to_vec();
+        let energies = reader.chunk()?.to_vec();
+        for (name, saved, buffer) in [
+            ("particle", &particles, &buffers.particles),
+            ("energy", &energies, &buffers.energies),
+        ] {
+            if saved.len() as u64 != buffer.size() {
+                return Err(SnapshotError::Format(format!(
+                    "the {name} buffer has {} bytes, expected {}",
+                    saved.len(),
+                    buffer.size()
+                )));
+            }
+        }
+        Ok(Self {
+            header,
+            clock,
+            particles,
+            energies,
+        })
+    }
+}
+
+fn write_snapshot(
+    out: &mut impl Write,
+    header: &Header,
+    clock: Clock,
+    buffers: &[u8],
+    particles_size: usize,
+) -> io::Result<()> {
+    let params = ron::to_string(&header.params).map_err(io::Error::other)?;
+    let chunk = |out: &mut dyn Write, data: &[u8]| -> io::Result<()> {
+        out.write_all(&(data.len() as u64).to_le_bytes())?;
+        out.write_all(data)
+    };
+
+    out.write_all(MAGIC)?;
+    for value in [
+        VERSION,
+        header.size.x,
+        header.size.y,
+        header.supersample,
+        header.nr_particles,
+    ] {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    out.write_all(&clock.simulated_frames.to_le_bytes())?;
+    out.write_all(&clock.frame.to_le_bytes())?;
+    out.write_all(&clock.elapsed.to_bits().to_le_bytes())?;
+    chunk(out, params.as_bytes())?;
+    let (particles, energies) = buffers.split_at(particles_size);
+    chunk(out, particles)?;
+    chunk(out, energies)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.0.len() < len {
+            return Err(SnapshotError::Format("the file is truncated".to_string()));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Bytes prefixed with their length.
+    fn chunk(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let len = self.u64()?;
+        self.take(len.try_into().unwrap_or(usize::MAX))
+    }
+}
+
+/// Snapshots on their way to the render world, which does the actual copying. Only set for the
+/// frame they were requested in.
+#[derive(Resource, Default)]
+pub(crate) struct SnapshotTransfer {
+    save: Option<(PathBuf, Entity, Header)>,
+    restore: Option<(Entity, Arc<Snapshot>)>,
+}
+
+/// Picks up the [`Snapshots`] requests once the [`PrimaryFlowField`] has its buffers, restoring
+/// the params right away and handing the rest to the render world.
+pub(crate) fn transfer_snapshots(
+    mut snapshots: ResMut<Snapshots>,
+    mut transfer: ResMut<SnapshotTransfer>,
+    mut field: Query<
+        (
+            Entity,
+            &FlowFieldSettings,
+            &mut FlowFieldParams,
+            &ParticleBuffer,
+        ),
+        With<PrimaryFlowField>,
+    >,
+) {
+    if transfer.save.is_some() || transfer.restore.is_some() {
+        *transfer = SnapshotTransfer::default();
+    }
+    let Ok((entity, settings, mut params, buffers)) = field.get_single_mut() else {
+        return;
+    };
+
+    if let Some(path) = snapshots.save.take() {
+        transfer.save = Some((path, entity, Header::new(settings, &params)));
+    }
+    if let Some(path) = snapshots.load.take() {
+        match Snapshot::load(&path, settings, buffers) {
+            Ok(snapshot) => {
+                *params = snapshot.header.params;
+                transfer.restore = Some((entity, Arc::new(snapshot)));
+                info!("restored simulation state from {}", path.display());
+            }
+            Err(err) => error!("could not restore {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Render world copy of the [`SnapshotTransfer`], taken once handled.
+#[derive(Resource, Default)]
+pub(crate) struct PendingSnapshots {
+    save: Option<(PathBuf, Entity, Header)>,
+    restore: Option<(Entity, Arc<Snapshot>)>,
+}
+
+pub(crate) fn extract_snapshots(
+    mut pending: ResMut<PendingSnapshots>,
+    transfer: Extract<Res<SnapshotTransfer>>,
+) {
+    if !transfer.is_changed() {
+        return;
+    }
+    if let Some(save) = &transfer.save {
+        pending.save = Some(save.clone());
+    }
+    if let Some(restore) = &transfer.restore {
+        pending.restore = Some(restore.clone());
+    }
+}
+
+/// Uploads a restored snapshot before the frame is simulated, and sets the clock the frame
+/// continues from.
+pub(crate) fn restore_snapshot(
+    mut pending: ResMut<PendingSnapshots>,
+    mut sim_params: ResMut<SimParamsBuffer>,
+    mut frames: ResMut<SimulatedFrames>,
+    fields: Query<&ParticleBuffer>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some((entity, snapshot)) = pending.restore.take() else {
+        return;
+    };
+    let Ok(buffers) = fields.get(entity) else {
+        warn!("the flow field is gone, its snapshot isn't restored");
+        return;
+    };
+    render_queue.write_buffer(&buffers.particles, 0, &snapshot.particles);
+    render_queue.write_buffer(&buffers.energies, 0, &snapshot.energies);
+    let uniform = sim_params.uniform.get_mut();
+    uniform.frame = snapshot.clock.frame;
+    uniform.elapsed = snapshot.clock.elapsed;
+    frames.0 = snapshot.clock.simulated_frames;
+}
+
+/// Reads the buffers back after the frame has been submitted and writes the snapshot to disk on a
+/// background thread.
+pub(crate) fn save_snapshot(
+    mut pending: ResMut<PendingSnapshots>,
+    exports: Res<PendingExports>,
+    sim_params: Res<SimParamsBuffer>,
+    frames: Res<SimulatedFrames>,
+    fields: Query<&ParticleBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some((path, entity, header)) = pending.save.take() else {
+        return;
+    };
+    let Ok(buffers) = fields.get(entity) else {
+        warn!("the flow field is gone, no snapshot saved");
+        return;
+    };
+    let clock = Clock {
+        simulated_frames: frames.0,
+        frame: sim_params.uniform.get().frame,
+        elapsed: sim_params.uniform.get().elapsed,
+    };
+
+    let particles_size = buffers.particles.size();
+    let size = particles_size + buffers.energies.size();
+    let staging = render_device.create_buffer(&BufferDescriptor {
+        label: Some("flow field snapshot readback"),
+        size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.copy_buffer_to_buffer(&buffers.particles, 0, &staging, 0, particles_size);
+    encoder.copy_buffer_to_buffer(
+        &buffers.energies,
+        0,
+        &staging,
+        particles_size,
+        buffers.energies.size(),
+    );
+    render_queue.submit([encoder.finish()]);
+
+    let mapped = poll::map_read(&render_device, &staging);
+    let render_queue = render_queue.clone();
+    let export = exports.start();
+    thread::spawn(move || {
+        let _export = export;
+        // Blocks this thread rather than the renderer until the copy is done and mapped.
+        let mapped = poll::wait_for_map(&render_queue, &mapped);
+        let saved = match mapped.expect("the snapshot buffer was dropped") {
+            Ok(()) => {
+                let data = staging.slice(..).get_mapped_range();
+                let write = || -> io::Result<()> {
+                    if let Some(directory) = path.parent() {
+                        fs::create_dir_all(directory)?;
+                    }
+                    let mut file = io::BufWriter::new(fs::File::create(&path)?);
+                    write_snapshot(&mut file, &header, clock, &data, particles_size as usize)?;
+                    file.flush()
+                };
+                write().map_err(|err| err.to_string())
+            }
+            Err(err) => Err(err),
+        };
+        staging.unmap();
+        match saved {
+            Ok(()) => info!("saved simulation state to {}", path.display()),
+            Err(err) => error!(
+                "could not save simulation state to {}: {err}",
+                path.display()
+            ),
+        }
+    });
+}
@@ -0,0 +1,117 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{encase, Buffer, BufferInitDescriptor, BufferUsages, ShaderType},
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+use rand::Rng;
+
+use crate::ParticleBuffer;
+
+/// A group of particles sharing their own take on the field. Particles are assigned a species when
+/// they are created, after which the species can be tweaked at runtime.
+#[derive(Clone, Copy, Debug)]
+pub struct Species {
+    /// Relative share of the particles that belong to this species.
+    pub weight: f32,
+    /// Offset into the noise field in noise space, so species can follow different currents.
+    pub noise_offset: Vec2,
+    /// Multiplier on the simulation speed.
+    pub speed: f32,
+    /// Multiplier on the energy a particle deposits.
+    pub deposit: f32,
+    /// Tint multiplied with the particle color.
+    pub color: Color,
+}
+
+impl Default for Species {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            noise_offset: Vec2::ZERO,
+            speed: 1.0,
+            deposit: 1.0,
+            color: Color::WHITE,
+        }
+    }
+}
+
+/// The species of a flow field, indexed by [`Particle::species`](crate::Particle::species).
+#[derive(Component, Clone, Debug)]
+pub struct FlowFieldSpecies(pub Vec<Species>);
+
+impl Default for FlowFieldSpecies {
+    fn default() -> Self {
+        Self(vec![Species::default()])
+    }
+}
+
+impl FlowFieldSpecies {
+    /// Picks a species index according to the weights.
+    pub(crate) fn pick(&self, rng: &mut impl Rng) -> u32 {
+        let total: f32 = self.0.iter().map(|species| species.weight.max(0.0)).sum();
+        let mut remaining = rng.gen::<f32>() * total;
+        for (index, species) in self.0.iter().enumerate() {
+            remaining -= species.weight.max(0.0);
+            if remaining < 0.0 {
+                return index as u32;
+            }
+        }
+        0
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let species: Vec<GpuSpecies> = self
+            .0
+            .iter()
+            .map(|species| GpuSpecies {
+                noise_offset: species.noise_offset,
+                speed: species.speed,
+                deposit: species.deposit,
+                color: species.color.as_linear_rgba_f32().into(),
+            })
+            .collect();
+
+        let mut bytes = Vec::new();
+        encase::StorageBuffer::new(&mut bytes)
+            .write(&species)
+            .unwrap();
+        bytes
+    }
+
+    pub(crate) fn create_buffer(&self, render_device: &RenderDevice) -> Buffer {
+        render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("flow field species"),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            contents: &self.encode(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, ShaderType)]
+pub(crate) struct GpuSpecies {
+    noise_offset: Vec2,
+    speed: f32,
+    deposit: f32,
+    color: Vec4,
+}
+
+pub(crate) fn update_species(
+    mut fields: Query<(&FlowFieldSpecies, &mut ParticleBuffer), Changed<FlowFieldSpecies>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    for (species, mut buffers) in &mut fields {
+        assert!(
+            !species.0.is_empty(),
+            "a flow field needs at least one species"
+        );
+        let bytes = species.encode();
+        if buffers.species.size() == bytes.len() as u64 {
+            render_queue.write_buffer(&buffers.species, 0, &bytes);
+        } else {
+            buffers.species = species.create_buffer(&render_device);
+        }
+    }
+}
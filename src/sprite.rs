@@ -0,0 +1,132 @@
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowResized, WindowScaleFactorChanged},
+};
+
+use crate::{
+    FlowFieldOutput, FlowFieldParams, FlowFieldSettings, Palette, PrimaryFlowField, WindowSizing,
+};
+
+/// Seconds after the last resize of the window before the sprite's flow field follows it.
+const RESIZE_DEBOUNCE: f32 = 0.25;
+
+/// The color shown where there are no trails, as drawn by the shader.
+fn displayed_background(params: &FlowFieldParams, palette: Option<&Palette>) -> Color {
+    let background = match palette {
+        Some(palette) => palette.colors()[0],
+        None => params.background_color,
+    };
+    let [r, g, b, a] = background.as_linear_rgba_f32();
+    let rgb = rotate_hue(Vec3::new(r, g, b), params.hue_shift).max(Vec3::ZERO);
+    let rgb = match params.invert {
+        true => (1.0 - rgb).max(Vec3::ZERO),
+        false => rgb,
+    };
+    Color::rgba_linear(rgb.x, rgb.y, rgb.z, a)
+}
+
+/// Rotates `color` around the gray axis by `turns` of the color wheel, the same as the shader.
+fn rotate_hue(color: Vec3, turns: f32) -> Vec3 {
+    let (sin, cos) = (turns * std::f32::consts::TAU).sin_cos();
+    let axis = Vec3::splat(1.0 / 3f32.sqrt());
+    color * cos + axis.cross(color) * sin + axis * axis.dot(color) * (1.0 - cos)
+}
+
+/// Clears around the sprite with the background of the [`PrimaryFlowField`], so letterboxing
+/// blends in.
+pub(crate) fn follow_background(
+    mut clear_color: ResMut<ClearColor>,
+    palette: Option<Res<Palette>>,
+    field: Query<&FlowFieldParams, With<PrimaryFlowField>>,
+) {
+    let Ok(params) = field.get_single() else {
+        return;
+    };
+    let background = displayed_background(params, palette.as_deref());
+    if clear_color.0 != background {
+        clear_color.0 = background;
+    }
+}
+
+pub(crate) fn spawn_sprite(
+    mut commands: Commands,
+    output: Res<FlowFieldOutput>,
+    field: Query<&FlowFieldSettings, With<PrimaryFlowField>>,
+) {
+    let settings = field.single();
+    commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(Vec2::new(settings.width as f32, settings.height as f32)),
+            ..default()
+        },
+        texture: output.0.clone(),
+        ..default()
+    });
+}
+
+/// Sizes the [`PrimaryFlowField`] from the primary window before it is created.
+pub(crate) fn fit_to_window(
+    sizing: Res<WindowSizing>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut field: Query<&mut FlowFieldSettings, With<PrimaryFlowField>>,
+) {
+    let (Ok(window), Ok(mut settings)) = (windows.get_single(), field.get_single_mut()) else {
+        return;
+    };
+    let size = sizing.field_size(window);
+    settings.width = size.x;
+    settings.height = size.y;
+}
+
+/// Resizes the [`PrimaryFlowField`] to the primary window once it hasn't been resized for
+/// [`RESIZE_DEBOUNCE`] seconds, so dragging the window edge doesn't reallocate every frame.
+pub(crate) fn follow_window(
+    mut last_resize: Local<Option<f32>>,
+    mut resized: EventReader<WindowResized>,
+    mut rescaled: EventReader<WindowScaleFactorChanged>,
+    time: Res<Time>,
+    sizing: Res<WindowSizing>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut field: Query<&mut FlowFieldSettings, With<PrimaryFlowField>>,
+) {
+    let now = time.raw_elapsed_seconds();
+    let resized = resized.iter().any(|event| windows.contains(event.window));
+    let rescaled = rescaled.iter().any(|event| windows.contains(event.window));
+    if resized || rescaled {
+        *last_resize = Some(now);
+    }
+    match *last_resize {
+        Some(last) if now - last >= RESIZE_DEBOUNCE => *last_resize = None,
+        _ => return,
+    }
+    let (Ok(window), Ok(mut settings)) = (windows.get_single(), field.get_single_mut()) else {
+        return;
+    };
+    let size = sizing.field_size(window);
+    if settings.width != size.x || settings.height != size.y {
+        settings.width = size.x;
+        settings.height = size.y;
+    }
+}
+
+/// Stretches the sprite showing the [`FlowFieldOutput`] over the primary window, or shows it at
+/// the size of the [`PrimaryFlowField`] without one.
+pub(crate) fn fit_sprite(
+    output: Option<Res<FlowFieldOutput>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    field: Query<&FlowFieldSettings, With<PrimaryFlowField>>,
+    mut sprites: Query<(&mut Sprite, &Handle<Image>)>,
+) {
+    let (Some(output), Ok(settings)) = (output, field.get_single()) else {
+        return;
+    };
+    let size = match windows.get_single() {
+        Ok(window) => Vec2::new(window.width(), window.height()),
+        Err(_) => Vec2::new(settings.width as f32, settings.height as f32),
+    };
+    for (mut sprite, texture) in &mut sprites {
+        if *texture == output.0 && sprite.custom_size != Some(size) {
+            sprite.custom_size = Some(size);
+        }
+    }
+}
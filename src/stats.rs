@@ -0,0 +1,123 @@
+use std::fmt::Write;
+
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+
+use crate::{
+    help, FlowFieldParams, FlowFieldSettings, InputBindings, Presets, PrimaryFlowField,
+    SeedVariation, SimulationSpeed,
+};
+
+/// Seconds between two updates of the overlay text.
+const UPDATE_INTERVAL: f32 = 0.25;
+
+/// Text in the top left corner of the window showing the frame rate, the size of the
+/// [`PrimaryFlowField`], the active preset, the [`SeedVariation`] and the main parameters. `F3` shows or hides it. Adds the
+/// [`FrameTimeDiagnosticsPlugin`] unless the app already has it.
+pub struct StatsOverlayPlugin;
+
+impl Plugin for StatsOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin);
+        }
+        app.init_resource::<InputBindings>();
+        app.add_systems(Startup, spawn_overlay);
+        app.add_systems(
+            Update,
+            (toggle_overlay.run_if(help::help_closed), update_overlay).chain(),
+        );
+    }
+}
+
+#[derive(Component)]
+struct StatsText;
+
+fn spawn_overlay(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        Visibility::Hidden,
+        StatsText,
+    ));
+}
+
+fn toggle_overlay(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut overlay: Query<&mut Visibility, With<StatsText>>,
+) {
+    if !bindings.toggle_stats.just_pressed(&keys) {
+        return;
+    }
+    for mut visibility in &mut overlay {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_overlay(
+    mut last_update: Local<Option<f32>>,
+    time: Res<Time>,
+    diagnostics: Res<DiagnosticsStore>,
+    speed: Res<SimulationSpeed>,
+    presets: Res<Presets>,
+    variation: Res<SeedVariation>,
+    field: Query<(&FlowFieldSettings, &FlowFieldParams), With<PrimaryFlowField>>,
+    mut overlay: Query<(&mut Text, &Visibility), With<StatsText>>,
+) {
+    let now = time.raw_elapsed_seconds();
+    if last_update.is_some_and(|last| now - last < UPDATE_INTERVAL) {
+        return;
+    }
+    let Ok((mut text, visibility)) = overlay.get_single_mut() else {
+        return;
+    };
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+    *last_update = Some(now);
+
+    let smoothed = |path| {
+        diagnostics
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or(0.0)
+    };
+    let value = &mut text.sections[0].value;
+    value.clear();
+    let _ = write!(
+        value,
+        "{:.0} fps, {:.2} ms",
+        smoothed(FrameTimeDiagnosticsPlugin::FPS),
+        smoothed(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+    );
+    if let Ok((settings, params)) = field.get_single() {
+        let _ = write!(value, "\n{}", settings.describe());
+        if let Some(preset) = presets.active() {
+            let _ = write!(value, "\npreset: {}", preset.name);
+        }
+        if let Some(index) = variation.index {
+            let _ = write!(value, ", variation {index}");
+        }
+        let _ = write!(value, "\nspeed {:.2}, {}", speed.0, params.describe());
+    }
+}
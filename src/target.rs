@@ -0,0 +1,64 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{TextureDimension, TextureUsages},
+};
+
+use crate::{FlowFieldSettings, OutputFormat};
+
+/// An existing image for a flow field to render into instead of creating its own, for example the
+/// texture of a material in a 3D scene. The size of the field follows the size of the image,
+/// overriding the width and height of its [`FlowFieldSettings`], and the field is created once the
+/// image is in `Assets<Image>`.
+///
+/// The image must be a 2D image with [`TextureUsages::STORAGE_BINDING`] in the
+/// [`OutputFormat::texture_format`] of the flow fields. Screenshots, GIFs and recordings also need
+/// [`TextureUsages::COPY_SRC`]. Unlike the size in the settings, the image is never shrunk to fit
+/// the limits of the GPU: a target that's too large for them panics. See
+/// [`ComputePlugin::with_target`](crate::ComputePlugin::with_target) for the primary flow field.
+#[derive(Component, Clone, Debug)]
+pub struct FlowFieldTarget(pub Handle<Image>);
+
+/// Panics unless the flow field can render into `image`.
+pub(crate) fn validate_target(entity: Entity, image: &Image, output_format: OutputFormat) {
+    let descriptor = &image.texture_descriptor;
+    assert!(
+        descriptor.dimension == TextureDimension::D2 && descriptor.size.depth_or_array_layers == 1,
+        "flow field {entity:?} target must be a single 2D image"
+    );
+    assert!(
+        descriptor.usage.contains(TextureUsages::STORAGE_BINDING),
+        "flow field {entity:?} target must have STORAGE_BINDING usage, got {:?}",
+        descriptor.usage
+    );
+    assert!(
+        descriptor.format == output_format.texture_format(),
+        "flow field {entity:?} target must be {:?} to match the output format {output_format:?}, \
+         got {:?}",
+        output_format.texture_format(),
+        descriptor.format
+    );
+    if !descriptor.usage.contains(TextureUsages::COPY_SRC) {
+        warn!("flow field {entity:?} target has no COPY_SRC usage, so it can't be exported");
+    }
+}
+
+/// Sizes a flow field from its target image.
+pub(crate) fn fit_target(settings: &mut Mut<FlowFieldSettings>, image: &Image) {
+    let size = image.size().as_uvec2();
+    if settings.width != size.x || settings.height != size.y {
+        settings.width = size.x;
+        settings.height = size.y;
+    }
+}
+
+/// Resizes the flow fields whose target image was resized.
+pub(crate) fn follow_targets(
+    mut fields: Query<(&FlowFieldTarget, &mut FlowFieldSettings)>,
+    images: Res<Assets<Image>>,
+) {
+    for (target, mut settings) in &mut fields {
+        if let Some(image) = images.get(&target.0) {
+            fit_target(&mut settings, image);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{encase, BufferDescriptor, BufferUsages, CommandEncoderDescriptor},
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+use crate::{poll, Particle, ParticleBuffer};
+
+/// Frames between two readbacks of the `debug-validate` feature, each of which stalls until the
+/// GPU has caught up.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ValidationInterval(pub u32);
+
+impl Default for ValidationInterval {
+    fn default() -> Self {
+        Self(300)
+    }
+}
+
+/// Reads the particle buffers back every [`ValidationInterval`] frames and panics if any particle
+/// has a non-finite position or velocity.
+pub(crate) fn validate_particles(
+    mut frame: Local<u32>,
+    interval: Res<ValidationInterval>,
+    fields: Query<(Entity, &ParticleBuffer)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    *frame += 1;
+    if !frame.is_multiple_of(interval.0.max(1)) {
+        return;
+    }
+
+    for (entity, buffers) in &fields {
+        let size = buffers.particles.size();
+        let staging = render_device.create_buffer(&BufferDescriptor {
+            label: Some("flow field validation readback"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(&buffers.particles, 0, &staging, 0, size);
+        render_queue.submit([encoder.finish()]);
+
+        let mapped = poll::map_read(&render_device, &staging);
+        poll::wait_for_map(&render_queue, &mapped)
+            .expect("the particle readback buffer was dropped")
+            .expect("failed to map the particle readback buffer");
+
+        let particles: Vec<Particle> =
+            encase::StorageBuffer::new(&*staging.slice(..).get_mapped_range())
+                .create()
+                .unwrap();
+        for (index, particle) in particles.iter().enumerate() {
+            assert!(
+                particle.position.is_finite() && particle.velocity.is_finite(),
+                "particle {index} of flow field {entity:?} is broken: position {}, velocity {}",
+                particle.position,
+                particle.velocity
+            );
+        }
+        staging.unmap();
+    }
+}
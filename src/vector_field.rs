@@ -0,0 +1,217 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Texture, TextureAspect,
+            TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+            TextureViewDescriptor,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        Extract,
+    },
+};
+
+/// A vector field computed on the CPU, stretched over every flow field and blended with the noise
+/// according to [`FlowFieldParams::vector_field_influence`](crate::FlowFieldParams).
+///
+/// Vectors are in pixels of the flow field with `y` pointing down, like the rows of an image, and a
+/// length of `1.0` matching the speed of the noise field. Shorter vectors only partially override
+/// the noise, so a zeroed field leaves it untouched. The data is laid out row by row.
+#[derive(Resource, Clone, Debug)]
+pub struct VectorField {
+    width: u32,
+    height: u32,
+    data: Vec<Vec2>,
+    /// Bumped by every change, so the render world only uploads new data.
+    generation: u64,
+    /// Cells changed during this frame, uploaded on their own when the texture already exists.
+    dirty: Option<(UVec2, UVec2)>,
+}
+
+impl VectorField {
+    pub fn new(width: u32, height: u32, data: Vec<Vec2>) -> Self {
+        assert!(
+            width > 0 && height > 0,
+            "a vector field needs at least one vector"
+        );
+        let mut field = Self {
+            width,
+            height,
+            data: Vec::new(),
+            generation: 0,
+            dirty: None,
+        };
+        field.set(data);
+        field
+    }
+
+    /// Replaces the vectors, which must match the grid size, and uploads them on the next frame.
+    pub fn set(&mut self, data: Vec<Vec2>) {
+        assert_eq!(
+            data.len(),
+            (self.width * self.height) as usize,
+            "vector field data doesn't match its {}x{} grid",
+            self.width,
+            self.height
+        );
+        self.data = data;
+        self.mark_dirty(UVec2::ZERO, UVec2::new(self.width, self.height));
+    }
+
+    /// Sets every vector to zero, leaving only the noise.
+    pub fn clear(&mut self) {
+        self.data.fill(Vec2::ZERO);
+        self.mark_dirty(UVec2::ZERO, UVec2::new(self.width, self.height));
+    }
+
+    /// Blends the vectors within `radius` cells of `center` towards `value`, by `strength` at the
+    /// center falling off smoothly to nothing at the edge. Positions are in cells.
+    pub fn splat(&mut self, center: Vec2, radius: f32, value: Vec2, strength: f32) {
+        let size = UVec2::new(self.width, self.height);
+        let min = (center - radius)
+            .floor()
+            .max(Vec2::ZERO)
+            .as_uvec2()
+            .min(size);
+        let max = (center + radius)
+            .ceil()
+            .max(Vec2::ZERO)
+            .as_uvec2()
+            .min(size);
+        if min.x >= max.x || min.y >= max.y {
+            return;
+        }
+
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let cell = Vec2::new(x as f32, y as f32) + 0.5;
+                let t = (cell.distance(center) / radius).min(1.0);
+                let falloff = 1.0 - t * t * (3.0 - 2.0 * t);
+                let vector = &mut self.data[(y * self.width + x) as usize];
+                *vector = vector.lerp(value, (strength * falloff).clamp(0.0, 1.0));
+            }
+        }
+        self.mark_dirty(min, max);
+    }
+
+    /// Extends the dirty region to include the cells from `min` up to `max`.
+    fn mark_dirty(&mut self, min: UVec2, max: UVec2) {
+        self.generation += 1;
+        self.dirty = Some(match self.dirty {
+            Some((dirty_min, dirty_max)) => (dirty_min.min(min), dirty_max.max(max)),
+            None => (min, max),
+        });
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[Vec2] {
+        &self.data
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct VectorFieldTexture {
+    texture: Option<(Texture, TextureView)>,
+    size: UVec2,
+    generation: u64,
+}
+
+impl VectorFieldTexture {
+    pub(crate) fn view(&self) -> Option<&TextureView> {
+        self.texture.as_ref().map(|(_, view)| view)
+    }
+}
+
+/// Starts tracking the changes of a new frame. Bypasses change detection so an untouched field isn't
+/// extracted again.
+pub(crate) fn reset_dirty_region(vector_field: Option<ResMut<VectorField>>) {
+    if let Some(mut vector_field) = vector_field {
+        vector_field.bypass_change_detection().dirty = None;
+    }
+}
+
+/// Mirrors the main world resource, cloning the data only when it changed.
+pub(crate) fn extract_vector_field(
+    mut commands: Commands,
+    vector_field: Extract<Option<Res<VectorField>>>,
+) {
+    match &*vector_field {
+        Some(vector_field) if vector_field.is_changed() => {
+            commands.insert_resource(VectorField::clone(vector_field))
+        }
+        Some(_) => {}
+        None => commands.remove_resource::<VectorField>(),
+    }
+}
+
+pub(crate) fn prepare_vector_field(
+    vector_field: Option<Res<VectorField>>,
+    mut texture: ResMut<VectorFieldTexture>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(vector_field) = vector_field else {
+        texture.texture = None;
+        return;
+    };
+    if texture.texture.is_some() && texture.generation == vector_field.generation {
+        return;
+    }
+
+    let size = UVec2::new(vector_field.width, vector_field.height);
+    let (mut min, mut max) = vector_field.dirty.unwrap_or((UVec2::ZERO, size));
+    if texture.texture.is_none() || texture.size != size {
+        (min, max) = (UVec2::ZERO, size);
+        let gpu_texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("flow field vector field"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rg32Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = gpu_texture.create_view(&TextureViewDescriptor::default());
+        texture.texture = Some((gpu_texture, view));
+        texture.size = size;
+    }
+
+    let (gpu_texture, _) = texture.texture.as_ref().unwrap();
+    let texel_size = std::mem::size_of::<Vec2>() as u32;
+    render_queue.write_texture(
+        ImageCopyTexture {
+            texture: gpu_texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: min.x,
+                y: min.y,
+                z: 0,
+            },
+            aspect: TextureAspect::All,
+        },
+        bytemuck::cast_slice(&vector_field.data),
+        ImageDataLayout {
+            offset: ((min.y * size.x + min.x) * texel_size) as u64,
+            bytes_per_row: Some(size.x * texel_size),
+            rows_per_image: None,
+        },
+        Extent3d {
+            width: max.x - min.x,
+            height: max.y - min.y,
+            depth_or_array_layers: 1,
+        },
+    );
+    texture.generation = vector_field.generation;
+}
@@ -0,0 +1,280 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::Buffer,
+        renderer::{RenderDevice, RenderQueue},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    poll,
+    screenshot::Readback,
+    shutdown::{self, Exiting, PendingExports},
+    FlowFieldOutput,
+};
+
+/// Number of staging buffers frames are read back into. When both are still waiting for ffmpeg the
+/// frame is skipped and a later one repeated, so the renderer never waits for the encoder.
+const STAGING_BUFFERS: usize = 2;
+
+/// Pipes the [`FlowFieldOutput`] into an `ffmpeg` process, which has to be on the `PATH` and
+/// encodes `path` while the app runs. Frames are repeated or skipped to keep the video at `fps`
+/// whatever the render frame rate, and the file is finished when the app exits, see
+/// [`PendingExports`].
+pub struct VideoExportPlugin {
+    pub path: PathBuf,
+    pub fps: u32,
+}
+
+impl Plugin for VideoExportPlugin {
+    fn build(&self, app: &mut App) {
+        assert!(self.fps > 0, "the video frame rate must be non-zero");
+        // After the frame has been submitted.
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            capture_video
+                .before(shutdown::wait_for_exports)
+                .in_set(RenderSet::Cleanup),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        let render_device = render_app.world.resource::<RenderDevice>().clone();
+        let render_queue = render_app.world.resource::<RenderQueue>().clone();
+        render_app.insert_resource(VideoCapture {
+            path: self.path.clone(),
+            fps: self.fps,
+            start: None,
+            emitted: 0,
+            readback: None,
+            free: Vec::new(),
+            returned: {
+                let (sender, receiver) = mpsc::channel();
+                (sender, Mutex::new(receiver))
+            },
+            frames: None,
+            writer: None,
+            failed: Arc::default(),
+            render_device,
+            render_queue,
+        });
+    }
+}
+
+struct Frame {
+    staging: Buffer,
+    mapped: Receiver<Result<(), String>>,
+    /// How many video frames this one fills.
+    repeats: u64,
+}
+
+#[derive(Resource)]
+struct VideoCapture {
+    path: PathBuf,
+    fps: u32,
+    /// When the first frame was captured, the video time is measured from there.
+    start: Option<Instant>,
+    /// Video frames handed to ffmpeg so far, repeats included.
+    emitted: u64,
+    /// Layout of the frames, known once ffmpeg has been started.
+    readback: Option<Readback>,
+    free: Vec<Buffer>,
+    /// Resources have to be `Sync`, the receiver is only ever used through `&mut self`.
+    returned: (Sender<Buffer>, Mutex<Receiver<Buffer>>),
+    /// Feeds the writer thread, `None` before the first frame and after capture stopped.
+    frames: Option<Sender<Frame>>,
+    writer: Option<JoinHandle<()>>,
+    /// Set by the writer thread when ffmpeg stops taking frames.
+    failed: Arc<AtomicBool>,
+    render_device: RenderDevice,
+    render_queue: RenderQueue,
+}
+
+impl VideoCapture {
+    fn start(&mut self, readback: Readback, pending: &PendingExports) {
+        self.readback = Some(readback);
+        let size = readback.size;
+        let ffmpeg = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{}x{}", size.x, size.y)])
+            .args(["-r", &self.fps.to_string(), "-i", "-"])
+            // yuv420p needs even sizes, odd ones get a black row or column.
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn();
+        let ffmpeg = match ffmpeg {
+            Ok(ffmpeg) => ffmpeg,
+            Err(err) => {
+                error!("could not start ffmpeg, no video is captured: {err}");
+                return;
+            }
+        };
+
+        self.free = (0..STAGING_BUFFERS)
+            .map(|_| readback.create_buffer(&self.render_device, "flow field video readback"))
+            .collect();
+        let (sender, receiver) = mpsc::channel();
+        self.frames = Some(sender);
+        let writer = Writer {
+            ffmpeg,
+            readback,
+            returned: self.returned.0.clone(),
+            failed: self.failed.clone(),
+            render_queue: self.render_queue.clone(),
+            path: self.path.clone(),
+        };
+        let pending = pending.start();
+        self.writer = Some(thread::spawn(move || {
+            let _pending = pending;
+            writer.run(receiver)
+        }));
+        info!("capturing video to {}", self.path.display());
+    }
+
+    /// Closes ffmpeg's input and waits for it to finish the file.
+    fn stop(&mut self) {
+        self.frames = None;
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                error!("the video thread panicked");
+            }
+        }
+    }
+
+    /// Closes ffmpeg's input, letting the writer finish the file on its own, counted in the
+    /// [`PendingExports`].
+    fn shutdown(&mut self) {
+        self.frames = None;
+        self.writer = None;
+    }
+}
+
+impl Drop for VideoCapture {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn capture_video(
+    mut capture: ResMut<VideoCapture>,
+    exiting: Res<Exiting>,
+    pending: Res<PendingExports>,
+    output: Option<Res<FlowFieldOutput>>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if exiting.0 {
+        capture.shutdown();
+        return;
+    }
+    if capture.failed.load(Ordering::Acquire) {
+        capture.stop();
+        return;
+    }
+    let Some(image) = output.and_then(|output| gpu_images.get(&output.0)) else {
+        return;
+    };
+    let readback = Readback::new(image);
+    match capture.readback {
+        None => capture.start(readback, &pending),
+        Some(started) if started != readback && capture.frames.is_some() => {
+            warn!("the output image changed size, the video stops here");
+            capture.stop();
+        }
+        Some(_) => {}
+    }
+    if capture.frames.is_none() {
+        return;
+    }
+
+    let now = Instant::now();
+    let elapsed = now - *capture.start.get_or_insert(now);
+    let due = (elapsed.as_secs_f64() * capture.fps as f64) as u64 + 1;
+    let repeats = due.saturating_sub(capture.emitted);
+    if repeats == 0 {
+        return;
+    }
+    let returned = capture.returned.1.get_mut().unwrap();
+    let returned: Vec<Buffer> = returned.try_iter().collect();
+    capture.free.extend(returned);
+    let Some(staging) = capture.free.pop() else {
+        return;
+    };
+
+    readback.copy(image, &staging, &render_device, &render_queue);
+    let mapped = poll::map_read(&render_device, &staging);
+    let frame = Frame {
+        staging,
+        mapped,
+        repeats,
+    };
+    // Only fails once the writer has given up, which `failed` picks up next frame.
+    let _ = capture.frames.as_ref().unwrap().send(frame);
+    capture.emitted = due;
+}
+
+/// Converts the frames and writes them to ffmpeg's input, on its own thread.
+struct Writer {
+    ffmpeg: Child,
+    readback: Readback,
+    returned: Sender<Buffer>,
+    failed: Arc<AtomicBool>,
+    render_queue: RenderQueue,
+    path: PathBuf,
+}
+
+impl Writer {
+    fn run(mut self, frames: Receiver<Frame>) {
+        let mut input = self.ffmpeg.stdin.take().expect("ffmpeg's input is piped");
+        for frame in frames {
+            // Blocks this thread rather than the renderer until the copy is done and mapped.
+            let mapped = poll::wait_for_map(&self.render_queue, &frame.mapped);
+            let pixels = mapped
+                .expect("the video buffer was dropped")
+                .and_then(|()| self.readback.srgb_pixels(&frame.staging));
+            let _ = self.returned.send(frame.staging);
+
+            // Writing fails rather than blocking once ffmpeg has exited and closed the pipe.
+            let written = pixels.and_then(|pixels| {
+                (0..frame.repeats).try_for_each(|_| {
+                    input
+                        .write_all(&pixels)
+                        .map_err(|err| format!("ffmpeg stopped taking frames: {err}"))
+                })
+            });
+            if let Err(err) = written {
+                error!("{err}, the video stops here");
+                self.failed.store(true, Ordering::Release);
+                break;
+            }
+        }
+
+        drop(input);
+        match self.ffmpeg.wait() {
+            Ok(status) if status.success() => info!("saved video to {}", self.path.display()),
+            Ok(status) => error!("ffmpeg failed to finish the video: {status}"),
+            Err(err) => error!("could not wait for ffmpeg: {err}"),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::{input::mouse::MouseWheel, prelude::*, window::PrimaryWindow};
+
+use crate::{brush, InputBindings};
+
+/// Change of the camera scale per line scrolled.
+const ZOOM_STEP: f32 = 1.1;
+/// Range of the camera scale, from zoomed in to zoomed out.
+const MIN_SCALE: f32 = 0.02;
+const MAX_SCALE: f32 = 4.0;
+
+/// The camera the window is viewed through: the active one drawn first, leaving out insets like
+/// the [`Magnifier`](crate::Magnifier).
+fn main_camera<'a>(
+    cameras: impl Iterator<
+        Item = (
+            &'a Camera,
+            Mut<'a, Transform>,
+            Mut<'a, OrthographicProjection>,
+        ),
+    >,
+) -> Option<(Mut<'a, Transform>, Mut<'a, OrthographicProjection>)> {
+    cameras
+        .filter(|(camera, _, _)| camera.is_active)
+        .min_by_key(|(camera, _, _)| camera.order)
+        .map(|(_, transform, projection)| (transform, projection))
+}
+
+/// Scrolling zooms around the cursor, so the point under it stays in place. Left to the brush
+/// while painting.
+pub(crate) fn zoom_view(
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&Camera, &mut Transform, &mut OrthographicProjection)>,
+) {
+    let steps = brush::scroll_steps(&mut wheel);
+    if steps == 0.0 || brush::painting(&keys) {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Some((mut transform, mut projection)) = main_camera(cameras.iter_mut()) else {
+        return;
+    };
+
+    let scale = (projection.scale * ZOOM_STEP.powf(-steps)).clamp(MIN_SCALE, MAX_SCALE);
+    if let Some(cursor) = window.cursor_position() {
+        let offset = Vec2::new(
+            cursor.x - window.width() / 2.0,
+            window.height() / 2.0 - cursor.y,
+        );
+        let shift = offset * (projection.scale - scale);
+        transform.translation += shift.extend(0.0);
+    }
+    projection.scale = scale;
+}
+
+/// Dragging with the middle mouse button moves the view along with the cursor.
+pub(crate) fn pan_view(
+    mut last_cursor: Local<Option<Vec2>>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&Camera, &mut Transform, &mut OrthographicProjection)>,
+) {
+    let cursor = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position());
+    let (Some(cursor), true) = (cursor, mouse.pressed(MouseButton::Middle)) else {
+        *last_cursor = None;
+        return;
+    };
+    let Some(last) = last_cursor.replace(cursor) else {
+        return;
+    };
+    let Some((mut transform, projection)) = main_camera(cameras.iter_mut()) else {
+        return;
+    };
+    let delta = cursor - last;
+    if delta != Vec2::ZERO {
+        transform.translation += Vec3::new(-delta.x, delta.y, 0.0) * projection.scale;
+    }
+}
+
+pub(crate) fn reset_view(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut cameras: Query<(&Camera, &mut Transform, &mut OrthographicProjection)>,
+) {
+    if !bindings.reset_view.just_pressed(&keys) {
+        return;
+    }
+    if let Some((mut transform, mut projection)) = main_camera(cameras.iter_mut()) {
+        transform.translation.x = 0.0;
+        transform.translation.y = 0.0;
+        projection.scale = 1.0;
+    }
+}
@@ -0,0 +1,206 @@
+//! Runs the plugins in a headless app, without a window. Skipped on machines without a GPU
+//! adapter, where the render plugin can't start.
+
+#[cfg(feature = "debug-validate")]
+use std::panic;
+use std::path::Path;
+
+#[cfg(feature = "debug-validate")]
+use bevy::render::renderer::RenderQueue;
+use bevy::{
+    prelude::*, render::settings::WgpuSettings, tasks::tick_global_task_pools_on_main_thread,
+    window::ExitCondition, winit::WinitPlugin,
+};
+use creative_coding::{
+    ComputePlugin, FlowFieldConfig, FlowFieldOutput, FlowFieldSettings, FlowFieldStatus,
+    PrimaryFlowField, WorkgroupSize,
+};
+#[cfg(feature = "debug-validate")]
+use creative_coding::{ParticleBuffer, ValidationInterval};
+
+/// Whether wgpu finds an adapter on the backends the render plugin tries.
+fn has_adapter() -> bool {
+    let backends = WgpuSettings::default()
+        .backends
+        .unwrap_or(wgpu::Backends::all());
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..default()
+    })
+    .enumerate_adapters(backends)
+    .next()
+    .is_some()
+}
+
+/// A windowless app with `plugin`, set up as far as `App::run` would before the first update, or
+/// `None` without an adapter.
+fn headless_app(plugin: ComputePlugin) -> Option<App> {
+    if !has_adapter() {
+        eprintln!("skipped: no GPU adapter");
+        return None;
+    }
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            })
+            .disable::<WinitPlugin>(),
+        plugin,
+    ));
+    while !app.ready() {
+        tick_global_task_pools_on_main_thread();
+    }
+    app.finish();
+    app.cleanup();
+    Some(app)
+}
+
+/// Updates until the pipelines have compiled and the simulation runs.
+fn run_until_ready(app: &mut App) {
+    for _ in 0..1000 {
+        app.update();
+        if app.world.resource::<FlowFieldStatus>().is_ready() {
+            return;
+        }
+    }
+    panic!(
+        "the pipelines didn't compile, {:?}",
+        app.world.resource::<FlowFieldStatus>()
+    );
+}
+
+fn small_plugin() -> ComputePlugin {
+    ComputePlugin::default()
+        .with_size(64, 48)
+        .with_particles(1024)
+        .with_seed(1)
+}
+
+/// The plugin builds into an app and sets up the primary flow field on the first update.
+#[test]
+fn builds_primary_flow_field() {
+    let Some(mut app) = headless_app(small_plugin()) else {
+        return;
+    };
+    app.update();
+    let mut primary = app
+        .world
+        .query_filtered::<&FlowFieldSettings, With<PrimaryFlowField>>();
+    let settings = primary.single(&app.world);
+    assert_eq!((settings.width, settings.height), (64, 48));
+    assert_eq!(settings.nr_particles, 1024);
+    assert!(app.world.contains_resource::<FlowFieldOutput>());
+}
+
+/// The first frames run before the output image is in the render assets and before the
+/// pipelines compile, which used to panic.
+#[test]
+fn cold_start_runs_frames() {
+    let Some(mut app) = headless_app(small_plugin()) else {
+        return;
+    };
+    for _ in 0..10 {
+        app.update();
+    }
+}
+
+/// A particle count changed on its own is fitted to the dispatch limit like a resize, rather than
+/// reaching the GPU as more workgroups than it can dispatch.
+#[test]
+fn fits_particle_count_changes() {
+    let Some(mut app) = headless_app(small_plugin().with_workgroup_size(1)) else {
+        return;
+    };
+    app.update();
+    let mut primary = app
+        .world
+        .query_filtered::<&mut FlowFieldSettings, With<PrimaryFlowField>>();
+    primary.single_mut(&mut app.world).nr_particles = 100_000;
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(primary.single(&app.world).nr_particles, 50_000);
+}
+
+/// A workgroup size too small to dispatch the particles in the workgroups a device allows keeps
+/// the pipelines it had, rather than failing dispatch validation.
+#[test]
+fn refuses_workgroup_size_too_small_for_particles() {
+    let Some(mut app) = headless_app(small_plugin().with_particles(100_000)) else {
+        return;
+    };
+    run_until_ready(&mut app);
+    *app.world.resource_mut::<WorkgroupSize>() = WorkgroupSize(1);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(app.world.resource::<FlowFieldStatus>().is_ready());
+}
+
+/// `configs/awkward_resolution.ron` is neither a multiple of the 16 by 16 pixel workgroups nor has
+/// a multiple of the workgroup size of particles, so every dispatch rounds up.
+#[test]
+fn runs_awkward_resolution() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("configs/awkward_resolution.ron");
+    let config = FlowFieldConfig::load(&path).unwrap().unwrap();
+    assert!(!config.width.is_multiple_of(16) && !config.height.is_multiple_of(16));
+    assert!(!config.particles.is_multiple_of(config.workgroup_size));
+    let Some(mut app) = headless_app(config.plugin()) else {
+        return;
+    };
+    run_until_ready(&mut app);
+    for _ in 0..10 {
+        app.update();
+    }
+    let mut primary = app
+        .world
+        .query_filtered::<&FlowFieldSettings, With<PrimaryFlowField>>();
+    let settings = primary.single(&app.world);
+    assert_eq!((settings.width, settings.height), (1366, 771));
+}
+
+/// With the `debug-validate` feature the particles are read back every [`ValidationInterval`]
+/// frames, which healthy particles pass.
+#[cfg(feature = "debug-validate")]
+#[test]
+fn validates_particles() {
+    let Some(mut app) = headless_app(small_plugin()) else {
+        return;
+    };
+    app.insert_resource(ValidationInterval(1));
+    run_until_ready(&mut app);
+    for _ in 0..10 {
+        app.update();
+    }
+}
+
+/// A particle with a non-finite position fails the next validation, before the shader gets to
+/// respawn it.
+#[cfg(feature = "debug-validate")]
+#[test]
+fn validation_catches_broken_particles() {
+    let Some(mut app) = headless_app(small_plugin()) else {
+        return;
+    };
+    app.insert_resource(ValidationInterval(1));
+    run_until_ready(&mut app);
+    let mut primary = app
+        .world
+        .query_filtered::<&ParticleBuffer, With<PrimaryFlowField>>();
+    let particles = primary.single(&app.world).particles.clone();
+    // The position of the first particle, written with the validation's copy on the next update.
+    app.world
+        .resource::<RenderQueue>()
+        .write_buffer(&particles, 0, &f32::NAN.to_ne_bytes());
+
+    let panic = panic::catch_unwind(panic::AssertUnwindSafe(|| app.update()))
+        .expect_err("the broken particle went unnoticed");
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .unwrap_or_default();
+    assert!(message.contains("particle 0 of flow field"), "{message}");
+}